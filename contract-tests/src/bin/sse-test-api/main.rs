@@ -66,6 +66,8 @@ impl From<es::SSE> for EventType {
                 },
             },
             es::SSE::Comment(comment) => Self::Comment { comment },
+            // Incremental data, raw events, resume notices and heartbeats are not enabled.
+            other => unreachable!("unexpected item {:?}", other),
         }
     }
 }
@@ -59,6 +59,7 @@ impl Inner {
         }
     }
 
+    #[allow(clippy::await_holding_lock)] // the lock serializes callbacks to the harness
     async fn send_message(&self, event_type: EventType, client: &reqwest::Client) -> bool {
         let json = match serde_json::to_string(&event_type) {
             Ok(s) => s,
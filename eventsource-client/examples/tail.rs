@@ -46,6 +46,10 @@ fn tail_events(client: impl es::Client) -> impl Stream<Item = Result<(), ()>> {
             es::SSE::Comment(comment) => {
                 println!("got a comment: \n{}", comment)
             }
+            es::SSE::Resuming { last_event_id } => {
+                println!("connecting, resuming from {:?}", last_event_id)
+            }
+            _ => {}
         })
         .map_err(|err| eprintln!("error streaming events: {:?}", err))
 }
@@ -0,0 +1,21 @@
+#![no_main]
+
+use eventsource_client::EventParser;
+use hyper::body::Bytes;
+use libfuzzer_sys::fuzz_target;
+
+// The first byte chooses where to split the rest of the input, so lines and events are also
+// exercised across chunk boundaries.
+fuzz_target!(|data: &[u8]| {
+    let (split, input) = match data.split_first() {
+        Some((split, input)) => (*split as usize, input),
+        None => return,
+    };
+    let (first, second) = input.split_at(split.min(input.len()));
+
+    let mut parser = EventParser::new().with_max_line_length(Some(1024));
+    for chunk in [first, second] {
+        let _ = parser.process_bytes(Bytes::copy_from_slice(chunk));
+        while parser.get_event().is_some() {}
+    }
+});
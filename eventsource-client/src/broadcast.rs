@@ -0,0 +1,160 @@
+use futures::StreamExt;
+use log::debug;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+use crate::client::{AbortOnDrop, BoxStream};
+use crate::error::{Error, Result};
+use crate::event_parser::{Event, SSE};
+
+/// The events of one stream, shared between any number of receivers. Returned by
+/// [`Client::stream_broadcast`](crate::Client::stream_broadcast).
+///
+/// Each receiver gets every event sent after it subscribed. Receivers that fall more than the
+/// capacity behind miss the oldest events: their next `recv` returns
+/// [`RecvError::Lagged`](broadcast::error::RecvError::Lagged) with the number missed, and they
+/// carry on from the oldest event still held. So a slow consumer can miss events, but never
+/// holds up the others or the connection.
+///
+/// Only [`SSE::Event`]s are sent. Errors are logged and the stream reconnects after them as
+/// usual. The stream is read for as long as any clone of the `EventBroadcast` is kept; once the
+/// last is dropped, or the stream closes, the connection is dropped and receivers get
+/// [`RecvError::Closed`](broadcast::error::RecvError::Closed) once they have taken the events
+/// already sent.
+///
+/// ```
+/// # use eventsource_client as es;
+/// # use es::Client;
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), es::Error> {
+/// let client = es::ClientBuilder::for_url("https://example.com/stream")?.build();
+/// let broadcast = client.stream_broadcast(64);
+/// let mut audit = broadcast.subscribe();
+/// let mut cache = broadcast.subscribe();
+/// # drop((audit, cache));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct EventBroadcast {
+    // Taken by the task once the stream closes, so that receivers see the channel close.
+    sender: Arc<SharedSender>,
+    _task: Arc<AbortOnDrop<()>>,
+}
+
+impl EventBroadcast {
+    pub(crate) fn spawn(mut stream: BoxStream<Result<SSE>>, capacity: usize) -> Self {
+        let (events, _) = broadcast::channel(capacity.max(1));
+        let sender = Arc::new(Mutex::new(Some(events.clone())));
+        let shared = sender.clone();
+        let task = tokio::spawn(async move {
+            while let Some(item) = stream.next().await {
+                match item {
+                    // Sending only fails while nobody is subscribed, which isn't a reason to stop.
+                    Ok(SSE::Event(event)) => drop(events.send(Arc::new(event))),
+                    Ok(_) => {}
+                    Err(Error::StreamClosed) => break,
+                    Err(e) => debug!("broadcast skipping stream error: {:?}", e),
+                }
+            }
+            lock(&shared).take();
+        });
+        EventBroadcast {
+            sender,
+            _task: Arc::new(AbortOnDrop(task)),
+        }
+    }
+
+    /// A receiver for the events sent from now on. Once the stream has closed, the receiver is
+    /// closed from the start.
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<Event>> {
+        match lock(&self.sender).as_ref() {
+            Some(sender) => sender.subscribe(),
+            None => broadcast::channel(1).1,
+        }
+    }
+
+    /// The number of receivers currently subscribed, or zero once the stream has closed.
+    pub fn receiver_count(&self) -> usize {
+        lock(&self.sender)
+            .as_ref()
+            .map_or(0, broadcast::Sender::receiver_count)
+    }
+}
+
+type SharedSender = Mutex<Option<broadcast::Sender<Arc<Event>>>>;
+
+fn lock(sender: &SharedSender) -> std::sync::MutexGuard<'_, Option<broadcast::Sender<Arc<Event>>>> {
+    // The sender is only ever replaced whole, so a panic while holding the lock can't leave it
+    // half-updated.
+    sender.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl std::fmt::Debug for EventBroadcast {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventBroadcast")
+            .field("receivers", &self.receiver_count())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use broadcast::error::RecvError;
+    use futures::channel::mpsc;
+
+    fn event(data: &str) -> Result<SSE> {
+        Ok(SSE::Event(Event {
+            event_type: "message".to_string(),
+            data: data.to_string(),
+            id: None,
+            retry: None,
+        }))
+    }
+
+    fn data(received: std::result::Result<Arc<Event>, RecvError>) -> String {
+        received.unwrap().data.clone()
+    }
+
+    #[tokio::test]
+    async fn test_every_receiver_gets_every_event() {
+        let (items, source) = mpsc::unbounded();
+        let broadcast = EventBroadcast::spawn(Box::pin(source), 4);
+        let mut first = broadcast.subscribe();
+        let mut second = broadcast.subscribe();
+        assert_eq!(broadcast.receiver_count(), 2);
+
+        for item in [
+            event("a"),
+            Ok(SSE::Comment("ping".to_string())),
+            Err(Error::Eof),
+            event("b"),
+            Err(Error::StreamClosed),
+        ] {
+            items.unbounded_send(item).unwrap();
+        }
+
+        for receiver in [&mut first, &mut second] {
+            assert_eq!(data(receiver.recv().await), "a");
+            assert_eq!(data(receiver.recv().await), "b");
+            assert_eq!(receiver.recv().await, Err(RecvError::Closed));
+        }
+        assert_eq!(broadcast.subscribe().recv().await, Err(RecvError::Closed));
+    }
+
+    #[tokio::test]
+    async fn test_slow_receiver_lags() {
+        let (items, source) = mpsc::unbounded();
+        let broadcast = EventBroadcast::spawn(Box::pin(source), 1);
+        let mut receiver = broadcast.subscribe();
+
+        for item in [event("a"), event("b"), event("c"), Err(Error::StreamClosed)] {
+            items.unbounded_send(item).unwrap();
+        }
+
+        assert_eq!(receiver.recv().await, Err(RecvError::Lagged(2)));
+        assert_eq!(data(receiver.recv().await), "c");
+        assert_eq!(receiver.recv().await, Err(RecvError::Closed));
+    }
+}
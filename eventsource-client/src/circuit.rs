@@ -0,0 +1,137 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use crate::config::{CircuitConfig, CircuitState};
+
+/// Counts connection failures, opening once too many happen within the configured window.
+pub(crate) struct CircuitBreaker {
+    config: CircuitConfig,
+    state: CircuitState,
+    failures: VecDeque<Instant>,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: CircuitConfig) -> Self {
+        Self {
+            config,
+            state: CircuitState::Closed,
+            failures: VecDeque::new(),
+        }
+    }
+
+    pub fn state(&self) -> CircuitState {
+        self.state
+    }
+
+    /// Record a failure at `now`. If this opens the circuit, the cooldown to wait out before
+    /// the next attempt is returned.
+    pub fn record_failure(&mut self, now: Instant) -> Option<Duration> {
+        match self.state {
+            CircuitState::Closed => {
+                while let Some(&oldest) = self.failures.front() {
+                    if now.saturating_duration_since(oldest) < self.config.window {
+                        break;
+                    }
+                    self.failures.pop_front();
+                }
+                self.failures.push_back(now);
+                if self.failures.len() < self.config.max_failures as usize {
+                    return None;
+                }
+            }
+            CircuitState::HalfOpen => {}
+            // Already waiting out the cooldown.
+            CircuitState::Open => return None,
+        }
+        self.failures.clear();
+        self.state = CircuitState::Open;
+        Some(self.config.cooldown)
+    }
+
+    /// Record a connection attempt, which is the test of whether an open circuit may close.
+    pub fn record_attempt(&mut self) {
+        if self.state == CircuitState::Open {
+            self.state = CircuitState::HalfOpen;
+        }
+    }
+
+    /// Record a successful connection, closing the circuit.
+    pub fn record_success(&mut self) {
+        if self.state != CircuitState::Closed {
+            self.state = CircuitState::Closed;
+            self.failures.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::circuit::CircuitBreaker;
+    use crate::config::{CircuitConfig, CircuitState};
+
+    fn breaker() -> CircuitBreaker {
+        CircuitBreaker::new(CircuitConfig::new(
+            3,
+            Duration::from_secs(10),
+            Duration::from_secs(60),
+        ))
+    }
+
+    #[test]
+    fn test_opens_after_failures_within_window() {
+        let mut breaker = breaker();
+        let start = Instant::now();
+
+        assert_eq!(breaker.record_failure(start), None);
+        assert_eq!(breaker.record_failure(start + Duration::from_secs(1)), None);
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert_eq!(
+            breaker.record_failure(start + Duration::from_secs(2)),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(breaker.state(), CircuitState::Open);
+    }
+
+    #[test]
+    fn test_old_failures_are_forgotten() {
+        let mut breaker = breaker();
+        let start = Instant::now();
+
+        for secs in [0, 6, 12, 18, 24] {
+            assert_eq!(
+                breaker.record_failure(start + Duration::from_secs(secs)),
+                None
+            );
+        }
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn test_half_open_attempt_closes_or_reopens() {
+        let mut breaker = breaker();
+        let start = Instant::now();
+        for _ in 0..3 {
+            breaker.record_failure(start);
+        }
+
+        breaker.record_attempt();
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        // A single failed test reopens the circuit.
+        assert_eq!(
+            breaker.record_failure(start + Duration::from_secs(60)),
+            Some(Duration::from_secs(60))
+        );
+
+        breaker.record_attempt();
+        breaker.record_success();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert_eq!(
+            breaker.record_failure(start + Duration::from_secs(120)),
+            None
+        );
+    }
+}
@@ -1,13 +1,11 @@
-use futures::{ready, Stream};
+use futures::{ready, task::AtomicWaker, FutureExt, Stream};
 use hyper::{
     body::HttpBody,
-    client::{
-        connect::{Connect, Connection},
-        ResponseFuture,
-    },
+    client::connect::{Connect, Connection},
     header::{HeaderMap, HeaderName, HeaderValue},
+    http::{uri::PathAndQuery, Extensions},
     service::Service,
-    Body, Request, StatusCode, Uri,
+    Body, Request, Response, StatusCode, Uri,
 };
 #[cfg(feature = "rustls")]
 use hyper_rustls::HttpsConnector as RustlsConnector;
@@ -15,36 +13,93 @@ use log::{debug, info, trace, warn};
 use pin_project::pin_project;
 use std::{
     boxed,
-    fmt::{self, Debug, Display, Formatter},
+    collections::VecDeque,
+    fmt::{self, Debug, Formatter},
     future::Future,
     io::ErrorKind,
+    net::SocketAddr,
     pin::Pin,
     str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
     task::{Context, Poll},
     time::{Duration, Instant},
 };
+#[cfg(feature = "rustls")]
+use tokio_rustls::rustls::{ClientConfig, ProtocolVersion};
 
-use tokio::{
-    io::{AsyncRead, AsyncWrite},
-    time::Sleep,
-};
+use tokio::io::{AsyncRead, AsyncWrite};
 
-use crate::config::ReconnectOptions;
+use crate::broadcast::EventBroadcast;
+use crate::circuit::CircuitBreaker;
+#[cfg(feature = "data-encoding")]
+use crate::config::DataEncoding;
+#[cfg(feature = "rustls")]
+use crate::config::TlsVersion;
+use crate::config::{
+    BackoffEvent, CircuitState, ContentTypePolicy, ErrorAction, EventTypePolicy,
+    LastEventIdOverflow, ReadTimeoutPolicy, ReconnectOptions, ReconnectStrategy,
+};
+use crate::connector::BoxConnector;
+#[cfg(feature = "cookies")]
+use crate::cookies::CookieJar;
 use crate::error::{Error, Result};
+use crate::observer::StreamObserver;
+use crate::timer::{SleepFuture, Timer, TimerClock, TokioTimer};
 
-pub use hyper::client::HttpConnector;
+pub use hyper::client::{connect::dns::Name, HttpConnector};
 use hyper_timeout::TimeoutConnector;
 
+use crate::event_parser::event_type_matches;
+use crate::event_parser::Event;
 use crate::event_parser::EventParser;
 use crate::event_parser::SSE;
+#[cfg(feature = "http3")]
+use crate::http3::Http3Service;
 
-use crate::retry::{BackoffRetry, RetryStrategy};
+use crate::rate::{EventRate, TokenBucket};
+use crate::retry::{AdaptiveRetry, BackoffRetry, RetryStrategy, DEFAULT_RESET_RETRY_INTERVAL};
 use std::error::Error as StdError;
 
 #[cfg(feature = "rustls")]
 pub type HttpsConnector = RustlsConnector<HttpConnector>;
 
-type BoxError = Box<dyn std::error::Error + Send + Sync>;
+/// The HTTP client that streams built from a connector `C` use, which times out reads after the
+/// configured [`read_timeout`](ClientBuilder::read_timeout).
+pub type TimeoutClient<C> = hyper::Client<TimeoutConnector<C>>;
+
+pub(crate) type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+type ResponseFuture = Pin<
+    boxed::Box<dyn Future<Output = std::result::Result<Response<Body>, BoxError>> + Send + Sync>,
+>;
+
+type ExtensionSetter = Arc<dyn Fn(&mut Extensions) + Send + Sync>;
+
+type BackoffCallback = Arc<dyn Fn(BackoffEvent) + Send + Sync>;
+
+type RequestHook = Arc<dyn Fn(&mut Request<Body>) + Send + Sync>;
+
+type RequestIdGenerator = Arc<dyn Fn() -> String + Send + Sync>;
+
+type ResponseHook = Arc<dyn Fn(&Response<()>) + Send + Sync>;
+
+type ErrorCallback = Arc<dyn Fn(&Error) -> ErrorAction + Send + Sync>;
+
+type DataTransform = Arc<dyn Fn(Vec<u8>) -> std::result::Result<Vec<u8>, BoxError> + Send + Sync>;
+
+type PreflightCallback = Arc<dyn Fn(&Response<Body>, &mut HeaderMap) + Send + Sync>;
+
+/// A request made once, before a stream first connects, configured with
+/// [`ClientBuilder::preflight`].
+#[derive(Clone)]
+struct Preflight {
+    method: hyper::Method,
+    path: String,
+    on_response: PreflightCallback,
+}
 
 /// Represents a [`Pin`]'d [`Send`] + [`Sync`] stream, returned by [`Client`]'s stream method.
 pub type BoxStream<T> = Pin<boxed::Box<dyn Stream<Item = T> + Send + Sync>>;
@@ -53,27 +108,45 @@ pub type BoxStream<T> = Pin<boxed::Box<dyn Stream<Item = T> + Send + Sync>>;
 /// This trait is sealed and cannot be implemented for types outside this crate.
 pub trait Client: Send + Sync + private::Sealed {
     fn stream(&self) -> BoxStream<Result<SSE>>;
+
+    /// Like [`Client::stream`], but starts the initial connection immediately instead of waiting
+    /// for the stream to be polled, so the response headers may already have arrived by the time
+    /// the first event is requested.
+    ///
+    /// The connection is made even if the returned stream is never polled. This must be called
+    /// from within a Tokio runtime.
+    fn stream_eager(&self) -> BoxStream<Result<SSE>>;
+
+    /// Read one [`Client::stream`] on a spawned task, sending each of its events to every
+    /// receiver subscribed to the returned [`EventBroadcast`], so that several consumers can
+    /// share a single connection. `capacity` is how many events are held for receivers that
+    /// have yet to take them; a capacity of zero is treated as one. See [`EventBroadcast`] for
+    /// how slow receivers and the end of the stream are handled.
+    ///
+    /// This must be called from within a Tokio runtime.
+    fn stream_broadcast(&self, capacity: usize) -> EventBroadcast {
+        EventBroadcast::spawn(self.stream(), capacity)
+    }
 }
 
 /*
  * TODO remove debug output
- * TODO specify list of stati to not retry (e.g. 204)
  */
 
 /// Maximum amount of redirects that the client will follow before
 /// giving up, if not overridden via [ClientBuilder::redirect_limit].
 pub const DEFAULT_REDIRECT_LIMIT: u32 = 16;
 
+/// Window over which [`ReconnectingRequest::event_rate`] is smoothed, if not overridden via
+/// [ClientBuilder::event_rate_window].
+pub const DEFAULT_EVENT_RATE_WINDOW: Duration = Duration::from_secs(10);
+
 /// ClientBuilder provides a series of builder methods to easily construct a [`Client`].
 pub struct ClientBuilder {
-    url: Uri,
-    headers: HeaderMap,
-    reconnect_opts: ReconnectOptions,
-    read_timeout: Option<Duration>,
+    props: RequestProps,
     last_event_id: Option<String>,
-    method: String,
-    body: Option<String>,
-    max_redirects: Option<u32>,
+    #[cfg(feature = "rustls")]
+    min_tls_version: TlsVersion,
 }
 
 impl ClientBuilder {
@@ -83,31 +156,96 @@ impl ClientBuilder {
             .parse()
             .map_err(|e| Error::InvalidParameter(Box::new(e)))?;
 
+        Ok(ClientBuilder::for_uri(url))
+    }
+
+    /// Create a builder for an already-parsed [`Uri`].
+    pub fn for_uri(url: Uri) -> ClientBuilder {
         let mut header_map = HeaderMap::new();
         header_map.insert("Accept", HeaderValue::from_static("text/event-stream"));
         header_map.insert("Cache-Control", HeaderValue::from_static("no-cache"));
 
-        Ok(ClientBuilder {
-            url,
-            headers: header_map,
-            reconnect_opts: ReconnectOptions::default(),
-            read_timeout: None,
+        ClientBuilder {
+            props: RequestProps {
+                url,
+                headers: header_map,
+                reconnect_opts: ReconnectOptions::default(),
+                initial_reconnect_opts: None,
+                read_timeout: None,
+                read_timeout_policy: ReadTimeoutPolicy::default(),
+                method: String::from("GET"),
+                max_redirects: DEFAULT_REDIRECT_LIMIT,
+                body: None,
+                content_type_policy: ContentTypePolicy::default(),
+                extensions: Vec::new(),
+                on_backoff_change: None,
+                reset_event_type: None,
+                error_event_type: None,
+                before_send: None,
+                request_id: None,
+                on_response: None,
+                emit_empty_events: false,
+                skip_empty_data: false,
+                dispatch_on_eof: false,
+                error_on_unexpected_eof: false,
+                #[cfg(feature = "raw-events")]
+                raw_events: false,
+                #[cfg(feature = "trace-fields")]
+                trace_fields: None,
+                max_line_length: None,
+                max_header_size: None,
+                incremental_data: false,
+                on_error: None,
+                timer: Arc::new(TokioTimer),
+                observer: None,
+                first_event_timeout: None,
+                max_last_event_id_len: None,
+                keep_alive_header: false,
+                max_bytes_per_connection: None,
+                max_events: None,
+                reconnect_attempt_header: false,
+                reset_reconnect_attempts: false,
+                event_rate_window: DEFAULT_EVENT_RATE_WINDOW,
+                rate_limit: None,
+                emit_resuming: false,
+                expected_event_types: None,
+                label: None,
+                preflight: None,
+                prepended_events: Vec::new(),
+                case_insensitive_event_types: false,
+                skip_consecutive_duplicates: false,
+                skip_seen_ids: None,
+                cache_buster: None,
+                transform_data: None,
+                #[cfg(feature = "data-encoding")]
+                data_encoding: DataEncoding::default(),
+                #[cfg(feature = "cookies")]
+                cookie_jar: None,
+                heartbeat_interval: None,
+                sticky_node_header: None,
+            },
             last_event_id: None,
-            method: String::from("GET"),
-            max_redirects: None,
-            body: None,
-        })
+            #[cfg(feature = "rustls")]
+            min_tls_version: TlsVersion::default(),
+        }
+    }
+
+    /// Replace the path, and query if any, of the URL the client connects to, keeping its scheme
+    /// and authority. `path` must start with `/`, for example `/topics/prices?since=now`.
+    pub fn path(mut self, path: &str) -> Result<ClientBuilder> {
+        self.props.url = with_path(&self.props.url, path)?;
+        Ok(self)
     }
 
     /// Set the request method used for the initial connection to the SSE endpoint.
     pub fn method(mut self, method: String) -> ClientBuilder {
-        self.method = method;
+        self.props.method = method;
         self
     }
 
     /// Set the request body used for the initial connection to the SSE endpoint.
     pub fn body(mut self, body: String) -> ClientBuilder {
-        self.body = Some(body);
+        self.props.body = Some(body);
         self
     }
 
@@ -118,475 +256,6204 @@ impl ClientBuilder {
         self
     }
 
-    /// Set a HTTP header on the SSE request.
-    pub fn header(mut self, name: &str, value: &str) -> Result<ClientBuilder> {
-        let name = HeaderName::from_str(name).map_err(|e| Error::InvalidParameter(Box::new(e)))?;
+    /// Deliver `events` at the start of every stream, before it first connects, for example to
+    /// replay events cached before a restart through the same consumer as live ones. Each event
+    /// is handled as if it had come from the server, so it is transformed, rate limited and
+    /// checked against [`error_event_type`](ClientBuilder::error_event_type) like the others, and
+    /// its ID is stored as it is delivered, so the first request resumes from the last of them.
+    /// A [`Client::stream_eager`] stream waits until they have been delivered before connecting.
+    pub fn prepend_events(mut self, events: Vec<Event>) -> ClientBuilder {
+        self.props.prepended_events = events;
+        self
+    }
 
-        let value =
-            HeaderValue::from_str(value).map_err(|e| Error::InvalidParameter(Box::new(e)))?;
+    /// Label the client's streams in its log messages, so that the messages of several streams
+    /// can be told apart. Messages about connecting, reconnecting and errors are prefixed with
+    /// `[label]`. Streams are unlabelled by default.
+    pub fn label(mut self, label: &str) -> ClientBuilder {
+        self.props.label = Some(label.to_string());
+        self
+    }
 
-        self.headers.insert(name, value);
-        Ok(self)
+    /// Set an event type that instructs the client to forget its last event id. When an event of
+    /// this type is received, the stored id is cleared, so later reconnects are made without a
+    /// `Last-Event-ID` header. The event itself is still delivered to the stream.
+    pub fn reset_event_type(mut self, event_type: &str) -> ClientBuilder {
+        self.props.reset_event_type = Some(event_type.to_string());
+        self
     }
 
-    /// Set a read timeout for the underlying connection. There is no read timeout by default.
-    pub fn read_timeout(mut self, read_timeout: Duration) -> ClientBuilder {
-        self.read_timeout = Some(read_timeout);
+    /// Compare event types ignoring ASCII case, so that an `Error` event matches an
+    /// [`error_event_type`](ClientBuilder::error_event_type) of `error`. This applies to the
+    /// reset, error and expected event types. Off by default, as the SSE specification compares
+    /// event types exactly.
+    pub fn case_insensitive_event_types(mut self, enabled: bool) -> ClientBuilder {
+        self.props.case_insensitive_event_types = enabled;
         self
     }
 
-    /// Configure the client's reconnect behaviour according to the supplied
-    /// [`ReconnectOptions`].
-    ///
-    /// [`ReconnectOptions`]: struct.ReconnectOptions.html
-    pub fn reconnect(mut self, opts: ReconnectOptions) -> ClientBuilder {
-        self.reconnect_opts = opts;
+    /// Drop an event that has the same type, ID and data as the event received just before it,
+    /// for servers that sometimes send an event twice in a row. Only the previous event is
+    /// remembered, including across a reconnect, so this catches an event that is repeated
+    /// straight away but not one repeated later. Events are compared as received, before any
+    /// [`transform_data`](ClientBuilder::transform_data). Off by default.
+    pub fn skip_consecutive_duplicates(mut self, enabled: bool) -> ClientBuilder {
+        self.props.skip_consecutive_duplicates = enabled;
         self
     }
 
-    /// Customize the client's following behavior when served a redirect.
-    /// To disable following redirects, pass `0`.
-    /// By default, the limit is [`DEFAULT_REDIRECT_LIMIT`].
-    pub fn redirect_limit(mut self, limit: u32) -> ClientBuilder {
-        self.max_redirects = Some(limit);
+    /// Drop an event whose `id:` field repeats the ID of an earlier event, for servers that
+    /// replay events the client has already received after a reconnect. The last `capacity`
+    /// distinct IDs are remembered across reconnects; once that many have been seen, each new ID
+    /// evicts the oldest. This bounds the memory used, at the cost of letting through a duplicate
+    /// that arrives after more than `capacity` other IDs. Events without an `id:` field of their
+    /// own are never dropped. See [`ReconnectingRequest::seen_ids`] for how full the set is. Off
+    /// by default.
+    pub fn skip_seen_ids(mut self, capacity: usize) -> ClientBuilder {
+        self.props.skip_seen_ids = Some(capacity);
         self
     }
 
-    /// Build with a specific client connector.
-    pub fn build_with_conn<C>(self, conn: C) -> impl Client
-    where
-        C: Service<Uri> + Clone + Send + Sync + 'static,
-        C::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin,
-        C::Future: Send + 'static,
-        C::Error: Into<BoxError>,
-    {
-        let mut connector = TimeoutConnector::new(conn);
-        connector.set_read_timeout(self.read_timeout);
+    /// Add a query parameter called `name` with a fresh, unique value to the URL of every
+    /// connection attempt, for caching layers that replay a stored response despite the
+    /// `Cache-Control: no-cache` header. The value combines the time of the attempt with a
+    /// random number, and is generated again for each reconnect. Any query already in the URL
+    /// is kept. The name must be a non-empty query parameter name that needs no escaping. Off by
+    /// default.
+    pub fn cache_buster(mut self, name: &str) -> Result<ClientBuilder> {
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_' | '~'));
+        if !valid {
+            return Err(Error::InvalidParameter(Box::new(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("invalid cache buster parameter name {:?}", name),
+            ))));
+        }
+        self.props.cache_buster = Some(name.to_string());
+        Ok(self)
+    }
 
-        let client = hyper::Client::builder().build::<_, hyper::Body>(connector);
+    /// Set an event type that the server uses to report application errors. Events of this type
+    /// are yielded as [`Error::ServerEvent`] holding the event's data, instead of as
+    /// [`SSE::Event`]. The stream stays connected afterwards. By default no event type is treated
+    /// as an error.
+    pub fn error_event_type(mut self, event_type: &str) -> ClientBuilder {
+        self.props.error_event_type = Some(event_type.to_string());
+        self
+    }
 
-        ClientImpl {
-            http: client,
-            request_props: RequestProps {
-                url: self.url,
-                headers: self.headers,
-                method: self.method,
-                body: self.body,
-                reconnect_opts: self.reconnect_opts,
-                max_redirects: self.max_redirects.unwrap_or(DEFAULT_REDIRECT_LIMIT),
-            },
-            last_event_id: self.last_event_id,
-        }
+    /// Declare the event types the application handles, so that the client can notice when the
+    /// server sends any other type. Each such event is logged as a warning and counted in
+    /// [`ReconnectingRequest::unexpected_event_types`], then delivered or reported as an error
+    /// according to `policy`. Events without an `event:` field have the type `"message"`, which
+    /// must be listed if they are expected. The [`reset_event_type`] and [`error_event_type`]
+    /// are always expected. By default all event types are expected.
+    ///
+    /// [`reset_event_type`]: ClientBuilder::reset_event_type
+    /// [`error_event_type`]: ClientBuilder::error_event_type
+    pub fn expected_event_types(
+        mut self,
+        types: &[&str],
+        policy: EventTypePolicy,
+    ) -> ClientBuilder {
+        let types = types.iter().map(|t| t.to_string()).collect();
+        self.props.expected_event_types = Some((types, policy));
+        self
     }
 
-    /// Build with an HTTP client connector.
-    pub fn build_http(self) -> impl Client {
-        self.build_with_conn(HttpConnector::new())
+    /// Configure whether to deliver events that have no `data:` field.
+    ///
+    /// Per the SSE specification, an event without data is not dispatched, so by default such
+    /// events are dropped. If `emit` is `true`, an event consisting only of other fields (for
+    /// example just `event: ping`) is delivered with empty data, which is useful for servers that
+    /// use such events as signals. Blank lines that don't end any fields, such as those at the
+    /// start of a stream, never produce events.
+    ///
+    /// An event with a `data:` field whose value is empty is a different case: the
+    /// specification dispatches it, and it is delivered whatever this is set to. See
+    /// [`skip_empty_data`](ClientBuilder::skip_empty_data) to drop those.
+    pub fn emit_empty_events(mut self, emit: bool) -> ClientBuilder {
+        self.props.emit_empty_events = emit;
+        self
     }
 
-    #[cfg(feature = "rustls")]
-    /// Build with an HTTPS client connector, using the OS root certificate store.
-    pub fn build(self) -> impl Client {
-        let conn = HttpsConnector::with_native_roots();
-        self.build_with_conn(conn)
+    /// Drop events that have a `data:` field with an empty value, for example a block made up of
+    /// `id: 42` and a bare `data:` line. The specification dispatches these with empty data,
+    /// which is what happens by default; the ID of a dropped event is still used for resuming.
+    /// Events with no `data:` field at all are controlled by
+    /// [`emit_empty_events`](ClientBuilder::emit_empty_events).
+    pub fn skip_empty_data(mut self, skip: bool) -> ClientBuilder {
+        self.props.skip_empty_data = skip;
+        self
     }
 
-    /// Build with the given [`hyper::client::Client`].
-    pub fn build_with_http_client<C>(self, http: hyper::Client<C>) -> impl Client
-    where
-        C: Connect + Clone + Send + Sync + 'static,
-    {
-        ClientImpl {
-            http,
-            request_props: RequestProps {
-                url: self.url,
-                headers: self.headers,
-                method: self.method,
-                body: self.body,
-                reconnect_opts: self.reconnect_opts,
-                max_redirects: self.max_redirects.unwrap_or(DEFAULT_REDIRECT_LIMIT),
-            },
-            last_event_id: self.last_event_id,
-        }
+    /// Also yield each piece of an event's data as an [`SSE::Partial`] as soon as it is
+    /// received, rather than only once the whole event has arrived. This suits servers that
+    /// stream a long value, such as generated text, within a single event. The complete event
+    /// is still delivered at its end. Off by default.
+    pub fn incremental_data(mut self, enabled: bool) -> ClientBuilder {
+        self.props.incremental_data = enabled;
+        self
     }
-}
 
-#[derive(Clone)]
-struct RequestProps {
-    url: Uri,
-    headers: HeaderMap,
-    method: String,
-    body: Option<String>,
-    reconnect_opts: ReconnectOptions,
-    max_redirects: u32,
-}
+    /// Also yield an [`SSE::Raw`] for each event block, listing its fields exactly as the server
+    /// sent them. Off by default.
+    #[cfg(feature = "raw-events")]
+    pub fn raw_events(mut self, enabled: bool) -> ClientBuilder {
+        self.props.raw_events = enabled;
+        self
+    }
 
-/// A client implementation that connects to a server using the Server-Sent Events protocol
-/// and consumes the event stream indefinitely.
-/// Can be parameterized with different hyper Connectors, such as HTTP or HTTPS.
-struct ClientImpl<C> {
-    http: hyper::Client<C>,
-    request_props: RequestProps,
-    last_event_id: Option<String>,
-}
+    /// Log each field of the event stream at trace level as it is parsed, noting any field the
+    /// parser doesn't recognize, to diagnose events that aren't dispatched as expected. The
+    /// values of the fields named in `redacted`, such as `["data"]` for a stream of secrets,
+    /// are logged as `<redacted>`; comments are logged as the field `comment`. Off by default.
+    #[cfg(feature = "trace-fields")]
+    pub fn trace_fields(mut self, redacted: &[&str]) -> ClientBuilder {
+        self.props.trace_fields = Some(redacted.iter().map(|name| name.to_string()).collect());
+        self
+    }
 
-impl<C> Client for ClientImpl<C>
-where
-    C: Connect + Clone + Send + Sync + 'static,
-{
-    /// Connect to the server and begin consuming the stream. Produces a
-    /// [`Stream`] of [`Event`](crate::Event)s wrapped in [`Result`].
-    ///
-    /// Do not use the stream after it returned an error!
-    ///
-    /// After the first successful connection, the stream will
-    /// reconnect for retryable errors.
-    fn stream(&self) -> BoxStream<Result<SSE>> {
-        Box::pin(ReconnectingRequest::new(
-            self.http.clone(),
-            self.request_props.clone(),
-            self.last_event_id.clone(),
-        ))
+    /// Dispatch the final event of a response whose body ends without the blank line that should
+    /// terminate it. The spec says such an event is discarded, but some servers close the stream
+    /// straight after the last `data:` line. The event is delivered before the end of the
+    /// stream is reported as [`Error::Eof`]. Off by default.
+    pub fn dispatch_on_eof(mut self, dispatch: bool) -> ClientBuilder {
+        self.props.dispatch_on_eof = dispatch;
+        self
     }
-}
 
-#[allow(clippy::large_enum_variant)] // false positive
-#[pin_project(project = StateProj)]
-enum State {
-    New,
-    Connecting {
-        retry: bool,
-        #[pin]
-        resp: ResponseFuture,
-    },
-    Connected(#[pin] hyper::Body),
-    WaitingToReconnect(#[pin] Sleep),
-    FollowingRedirect(Option<HeaderValue>),
-    StreamClosed,
-}
+    /// Treat the server ending the response as the end of the stream when reconnection is
+    /// disabled, so that a supervisor can tell the server hanging up from the stream being
+    /// stopped: the end is reported as [`Error::Eof`] (or [`Error::UnexpectedEof`] in the
+    /// middle of an event), and the stream then yields only [`Error::StreamClosed`]. Without
+    /// this the end is reported the same way, but the stream reconnects even when reconnection
+    /// is disabled. Off by default.
+    pub fn error_on_unexpected_eof(mut self, enabled: bool) -> ClientBuilder {
+        self.props.error_on_unexpected_eof = enabled;
+        self
+    }
 
-impl State {
-    fn name(&self) -> &'static str {
-        match self {
-            State::New => "new",
-            State::Connecting { retry: false, .. } => "connecting(no-retry)",
-            State::Connecting { retry: true, .. } => "connecting(retry)",
-            State::Connected(_) => "connected",
-            State::WaitingToReconnect(_) => "waiting-to-reconnect",
-            State::FollowingRedirect(_) => "following-redirect",
-            State::StreamClosed => "closed",
-        }
+    /// Limit how long, in bytes, a single line of the event stream may be. A connection that
+    /// sends a longer line yields [`Error::InvalidLine`] and is dropped: the stream reconnects
+    /// after the next backoff delay, or closes if reconnection is disabled, unless an
+    /// [`on_error`](ClientBuilder::on_error) handler decides otherwise. Nothing more of the
+    /// connection is parsed, so the rest of the oversized line can't be mistaken for fields. By
+    /// default lines may be any length.
+    pub fn max_line_length(mut self, max: usize) -> ClientBuilder {
+        self.props.max_line_length = Some(max);
+        self
     }
-}
 
-impl Debug for State {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.name())
+    /// Limit the size, in bytes, of each request's headers, counting every header as its name
+    /// and value plus the four bytes that separate them on the wire. A request whose headers
+    /// come to more, once the `Last-Event-ID`, cookies and any other headers are added, isn't
+    /// sent: the stream yields [`Error::HeadersTooLarge`] and closes, rather than the server or
+    /// a proxy rejecting the request with a less helpful error. By default there is no limit.
+    pub fn max_header_size(mut self, max: usize) -> ClientBuilder {
+        self.props.max_header_size = Some(max);
+        self
     }
-}
 
-#[must_use = "streams do nothing unless polled"]
-#[pin_project]
-pub struct ReconnectingRequest<C> {
-    http: hyper::Client<C>,
-    props: RequestProps,
-    #[pin]
-    state: State,
-    retry_strategy: Box<dyn RetryStrategy + Send + Sync>,
-    current_url: Uri,
-    redirect_count: u32,
-    event_parser: EventParser,
-    last_event_id: Option<String>,
-}
+    /// Don't send the `Accept: text/event-stream` and `Cache-Control: no-cache` headers that are
+    /// set by default, for servers that reject them. This removes those headers whatever their
+    /// value, so call it before setting your own.
+    pub fn no_default_headers(mut self) -> ClientBuilder {
+        self.props.headers.remove(hyper::header::ACCEPT);
+        self.props.headers.remove(hyper::header::CACHE_CONTROL);
+        self
+    }
 
-impl<C> ReconnectingRequest<C> {
-    fn new(
-        http: hyper::Client<C>,
-        props: RequestProps,
-        last_event_id: Option<String>,
-    ) -> ReconnectingRequest<C> {
-        let reconnect_delay = props.reconnect_opts.delay;
-        let delay_max = props.reconnect_opts.delay_max;
-        let backoff_factor = props.reconnect_opts.backoff_factor;
+    /// Set a HTTP header on the SSE request, replacing any values previously set for the same
+    /// header name. Use [`ClientBuilder::append_header`] to send the header more than once.
+    pub fn header(mut self, name: &str, value: &str) -> Result<ClientBuilder> {
+        let (name, value) = parse_header(name, value)?;
+        self.props.headers.insert(name, value);
+        Ok(self)
+    }
 
-        let url = props.url.clone();
-        ReconnectingRequest {
-            props,
-            http,
-            state: State::New,
-            retry_strategy: Box::new(BackoffRetry::new(
-                reconnect_delay,
-                delay_max,
-                backoff_factor,
-                true,
-            )),
-            redirect_count: 0,
-            current_url: url,
-            event_parser: EventParser::new(),
-            last_event_id,
+    /// Set the `Origin` header sent with every request, for servers that check it even for
+    /// non-browser clients. `origin` must be a serialized origin such as `https://example.com`
+    /// (a scheme and host, with an optional port), or `null`.
+    pub fn origin(mut self, origin: &str) -> Result<ClientBuilder> {
+        if origin != "null" {
+            let valid = origin.parse::<Uri>().is_ok_and(|uri| {
+                uri.scheme().is_some()
+                    && uri.authority().is_some()
+                    && matches!(uri.path(), "" | "/")
+                    && uri.query().is_none()
+            });
+            if !valid {
+                return Err(Error::InvalidParameter(Box::new(std::io::Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("invalid origin {:?}: expected scheme://host[:port]", origin),
+                ))));
+            }
         }
+
+        let (name, value) = parse_header("Origin", origin)?;
+        self.props.headers.insert(name, value);
+        Ok(self)
     }
 
-    fn send_request(&self) -> Result<ResponseFuture>
+    /// Add a HTTP header to the SSE request, keeping any values already set for the same header
+    /// name. Each value is sent as a separate header line.
+    pub fn append_header(mut self, name: &str, value: &str) -> Result<ClientBuilder> {
+        let (name, value) = parse_header(name, value)?;
+        self.props.headers.append(name, value);
+        Ok(self)
+    }
+
+    /// Attach a value to the [`Extensions`] of every request made by the client, including
+    /// reconnects and redirects. This is useful for custom connectors or middleware that route
+    /// requests based on their extensions. Setting a second value of the same type replaces the
+    /// first.
+    pub fn extension<T>(mut self, value: T) -> ClientBuilder
     where
-        C: Connect + Clone + Send + Sync + 'static,
+        T: Clone + Send + Sync + 'static,
     {
-        let mut request_builder = Request::builder()
-            .method(self.props.method.as_str())
-            .uri(&self.current_url);
-
-        for (name, value) in &self.props.headers {
-            request_builder = request_builder.header(name, value);
-        }
+        self.props
+            .extensions
+            .push(Arc::new(move |extensions: &mut Extensions| {
+                extensions.insert(value.clone());
+            }));
+        self
+    }
 
-        if let Some(id) = self.last_event_id.as_ref() {
-            if !id.is_empty() {
-                let id_as_header =
-                    HeaderValue::from_str(id).map_err(|e| Error::InvalidParameter(Box::new(e)))?;
+    /// Send a header called `header`, such as `X-Request-Id` or `traceparent`, whose value is
+    /// produced by calling `generate` for each connection attempt, so that the server's logs of
+    /// every reconnect can be told apart. It replaces any static header of the same name. If the
+    /// generated value isn't a valid header value, the attempt fails with
+    /// [`Error::InvalidParameter`].
+    ///
+    /// ```
+    /// # use eventsource_client as es;
+    /// # use std::sync::atomic::{AtomicU64, Ordering};
+    /// # fn main() -> Result<(), es::Error> {
+    /// let next_id = AtomicU64::new(1);
+    /// let client = es::ClientBuilder::for_url("https://example.com/stream")?
+    ///     .request_id("x-request-id", move || {
+    ///         format!("stream-{}", next_id.fetch_add(1, Ordering::Relaxed))
+    ///     })?
+    ///     .build_http();
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn request_id<F>(mut self, header: &str, generate: F) -> Result<ClientBuilder>
+    where
+        F: Fn() -> String + Send + Sync + 'static,
+    {
+        let header =
+            HeaderName::from_str(header).map_err(|e| Error::InvalidParameter(Box::new(e)))?;
+        self.props.request_id = Some((header, Arc::new(generate)));
+        Ok(self)
+    }
 
-                request_builder = request_builder.header("last-event-id", id_as_header);
-            }
-        }
+    /// Register a hook that can inspect or modify each request just before it is sent. The hook
+    /// runs for every connection attempt, including reconnects and redirects, after the
+    /// configured headers, body and extensions have been applied.
+    pub fn before_send<F>(mut self, hook: F) -> ClientBuilder
+    where
+        F: Fn(&mut Request<Body>) + Send + Sync + 'static,
+    {
+        self.props.before_send = Some(Arc::new(hook));
+        self
+    }
 
-        let body = match &self.props.body {
-            Some(body) => Body::from(body.to_string()),
-            None => Body::empty(),
-        };
+    /// Register a hook that is called with the status, headers and HTTP version of each response
+    /// accepted as a successful connection, just before the stream starts reading its body. It
+    /// runs on every connect, including reconnects, but not for redirects or responses that are
+    /// rejected.
+    pub fn on_response<F>(mut self, hook: F) -> ClientBuilder
+    where
+        F: Fn(&Response<()>) + Send + Sync + 'static,
+    {
+        self.props.on_response = Some(Arc::new(hook));
+        self
+    }
 
-        let request = request_builder
-            .body(body)
+    /// Make a one-off request before the stream first connects, for example to check
+    /// credentials or to obtain a session token. The request is sent with `method` to `path` on
+    /// the stream's endpoint, with the configured headers and extensions but no body. `path`
+    /// must start with `/`.
+    ///
+    /// If the preflight gets a 2xx response, `on_response` is called with it and with an empty
+    /// header map; headers it adds are sent with every request the stream makes from then on,
+    /// replacing any configured values for the same names. Otherwise the stream yields
+    /// [`Error::PreflightFailed`] or [`Error::PreflightRejected`] and closes, without
+    /// retrying. The preflight isn't repeated when the stream reconnects, and makes a
+    /// [`Client::stream_eager`] stream connect only once it is polled.
+    pub fn preflight<F>(mut self, method: &str, path: &str, on_response: F) -> Result<ClientBuilder>
+    where
+        F: Fn(&Response<Body>, &mut HeaderMap) + Send + Sync + 'static,
+    {
+        let method = hyper::Method::from_bytes(method.as_bytes())
             .map_err(|e| Error::InvalidParameter(Box::new(e)))?;
+        with_path(&self.props.url, path)?;
+        self.props.preflight = Some(Preflight {
+            method,
+            path: path.to_string(),
+            on_response: Arc::new(on_response),
+        });
+        Ok(self)
+    }
 
-        Ok(self.http.request(request))
+    /// Register a callback that decides what to do when the connection attempt fails or reading
+    /// the response body fails. The callback receives the error that will be reported to the
+    /// stream, and returns an [`ErrorAction`] that overrides the [`ReconnectOptions`] for that
+    /// error.
+    ///
+    /// Without a callback, connection failures are retried only if
+    /// [`retry_initial`](crate::ReconnectOptionsBuilder::retry_initial) is set, and body errors
+    /// are followed by a reconnect if [`reconnect`](ReconnectOptions::reconnect) is enabled.
+    /// Other failures, such as unexpected statuses, are not passed to the callback.
+    pub fn on_error<F>(mut self, callback: F) -> ClientBuilder
+    where
+        F: Fn(&Error) -> ErrorAction + Send + Sync + 'static,
+    {
+        self.props.on_error = Some(Arc::new(callback));
+        self
     }
 
-    fn reset_redirects(self: Pin<&mut Self>) {
-        let url = self.props.url.clone();
-        let this = self.project();
-        *this.current_url = url;
-        *this.redirect_count = 0;
+    /// Send an explicit `Connection: keep-alive` header, which some legacy servers and proxies
+    /// need. Off by default.
+    ///
+    /// The header is only added to HTTP/1.x requests, and not if a `Connection` header has
+    /// already been set. If a connection negotiates HTTP/2, hyper drops the header, as
+    /// connection-specific headers are not allowed there.
+    pub fn keep_alive_header(mut self, enabled: bool) -> ClientBuilder {
+        self.props.keep_alive_header = enabled;
+        self
     }
 
-    fn increment_redirect_counter(self: Pin<&mut Self>) -> bool {
-        if self.redirect_count == self.props.max_redirects {
+    /// Limit how many bytes of response body a single connection may deliver. Once a chunk
+    /// would take a connection over `max` bytes, that chunk is discarded, the stream yields
+    /// [`Error::ConnectionByteLimit`], and `action` decides what happens next: with
+    /// [`ErrorAction::Reconnect`] a new connection is made after the next backoff delay, even if
+    /// reconnection is otherwise disabled. Events from earlier chunks are all delivered first.
+    /// The count starts again for each connection. There is no limit by default.
+    pub fn max_bytes_per_connection(mut self, max: u64, action: ErrorAction) -> ClientBuilder {
+        self.props.max_bytes_per_connection = Some((max, action));
+        self
+    }
+
+    /// Transform each event's data before it is delivered, for example to decrypt or decompress
+    /// it. The transform is given the data as received and returns the data to deliver, which
+    /// must be valid UTF-8. It runs before the data is looked at by anything else, such as the
+    /// [`error_event_type`](ClientBuilder::error_event_type) check, but isn't applied to
+    /// partial data reported by [`incremental_data`](ClientBuilder::incremental_data).
+    ///
+    /// If the transform fails, or returns invalid UTF-8, the event is dropped, the stream yields
+    /// [`Error::TransformFailed`], and `action` decides what happens next. The event's ID is
+    /// still stored, so a reconnect doesn't receive the same event again.
+    pub fn transform_data<F>(mut self, transform: F, action: ErrorAction) -> ClientBuilder
+    where
+        F: Fn(Vec<u8>) -> std::result::Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.props.transform_data = Some((Arc::new(transform), action));
+        self
+    }
+
+    /// Decode each event's data from `encoding` before it is delivered, and before any
+    /// [`transform_data`](ClientBuilder::transform_data) transform sees it. Partial data reported
+    /// by [`incremental_data`](ClientBuilder::incremental_data) isn't decoded.
+    ///
+    /// If the data can't be decoded, the event is dropped and the stream yields
+    /// [`Error::InvalidDataEncoding`], staying connected. The event's ID is still stored, so a
+    /// reconnect doesn't receive the same event again. By default data is delivered as received.
+    #[cfg(feature = "data-encoding")]
+    pub fn data_encoding(mut self, encoding: DataEncoding) -> ClientBuilder {
+        self.props.data_encoding = encoding;
+        self
+    }
+
+    /// Store the cookies the server sets in `jar`, and send the cookies in `jar` that apply to
+    /// each request's host and path with that request, including reconnects and the
+    /// [`preflight`](ClientBuilder::preflight) request. They are added after any `Cookie`
+    /// header set with [`header`](ClientBuilder::header). Keep a clone of `jar` to inspect or
+    /// seed the cookies.
+    #[cfg(feature = "cookies")]
+    pub fn cookie_jar(mut self, jar: CookieJar) -> ClientBuilder {
+        self.props.cookie_jar = Some(jar);
+        self
+    }
+
+    /// Send an `X-Reconnect-Attempt` header with every request, counting how many times the
+    /// stream has reconnected: `0` for the first connection, `1` for the first reconnect, and so
+    /// on. Following a redirect doesn't count as a reconnect. Off by default.
+    pub fn reconnect_attempt_header(mut self, enabled: bool) -> ClientBuilder {
+        self.props.reconnect_attempt_header = enabled;
+        self
+    }
+
+    /// Restart the count sent in the `X-Reconnect-Attempt` header from zero whenever a
+    /// connection succeeds, so it counts consecutive attempts rather than all reconnects over
+    /// the stream's lifetime. Off by default.
+    pub fn reset_reconnect_attempts(mut self, reset: bool) -> ClientBuilder {
+        self.props.reset_reconnect_attempts = reset;
+        self
+    }
+
+    /// Set how quickly [`ReconnectingRequest::event_rate`] follows changes in the rate of events:
+    /// the influence of an event fades by a factor of `e` over each `window`. Shorter windows
+    /// react faster but are noisier. The default is [`DEFAULT_EVENT_RATE_WINDOW`]; windows
+    /// shorter than a millisecond are treated as a millisecond.
+    pub fn event_rate_window(mut self, window: Duration) -> ClientBuilder {
+        self.props.event_rate_window = window;
+        self
+    }
+
+    /// Deliver at most `events_per_second` events a second, after an initial burst of up to that
+    /// many. Once the limit is reached the stream waits, using the configured [`timer`], before
+    /// yielding the next event, and stops reading from the connection in the meantime so that
+    /// the server is slowed down rather than events piling up in memory. Comments and errors
+    /// are not limited, and events that are dropped, such as duplicates, don't count towards
+    /// the limit. A limit of zero is treated as one. There is no limit by default.
+    ///
+    /// [`timer`]: ClientBuilder::timer
+    pub fn rate_limit(mut self, events_per_second: u32) -> ClientBuilder {
+        self.props.rate_limit = Some(events_per_second);
+        self
+    }
+
+    /// End the stream once it has delivered `max` events, counted across reconnects. The
+    /// connection is closed as soon as the last event is yielded, and the stream then ends.
+    /// Only [`SSE::Event`]s count, including any set with [`prepend_events`]; unlike
+    /// [`take_events`](crate::take_events) the count lives in the stream, so it also holds for
+    /// [`ReconnectingRequest::next_event_timeout`] and the other ways of reading the stream. A
+    /// limit of zero ends the stream without connecting. There is no limit by default.
+    ///
+    /// [`prepend_events`]: ClientBuilder::prepend_events
+    pub fn max_events(mut self, max: u64) -> ClientBuilder {
+        self.props.max_events = Some(max);
+        self
+    }
+
+    /// Yield an [`SSE::Resuming`] before the stream's first connection and before each
+    /// reconnect, carrying the last event ID that the request will send, so that it can be
+    /// logged whether the stream is resuming or starting afresh. Redirects aren't announced, and
+    /// neither is the first connection of a [`Client::stream_eager`] stream, which is made
+    /// before the stream is polled. Off by default.
+    pub fn emit_resuming(mut self, enabled: bool) -> ClientBuilder {
+        self.props.emit_resuming = enabled;
+        self
+    }
+
+    /// Yield an [`SSE::Heartbeat`] every `interval` while the stream is connected, interleaved
+    /// with the events received, to drive housekeeping without a separate timer task. The first
+    /// heartbeat comes `interval` after each connection is made, and none are yielded while the
+    /// stream is connecting or waiting to reconnect. The delays are measured by the
+    /// [`timer`](ClientBuilder::timer). Off by default.
+    pub fn heartbeat_interval(mut self, interval: Duration) -> ClientBuilder {
+        self.props.heartbeat_interval = Some(interval);
+        self
+    }
+
+    /// Set a read timeout for the underlying connection. There is no read timeout by default.
+    pub fn read_timeout(mut self, read_timeout: Duration) -> ClientBuilder {
+        self.props.read_timeout = Some(read_timeout);
+        self
+    }
+
+    /// Choose what resets the read timeout. By default any bytes received do, so a server
+    /// that sends keep-alive comments never times out; with [`ReadTimeoutPolicy::EventsOnly`]
+    /// the connection times out if no event arrives within the read timeout, and the stream
+    /// yields [`Error::TimedOut`] and reconnects as if the connection itself had timed out.
+    /// Has no effect unless a [`read_timeout`](ClientBuilder::read_timeout) is set.
+    pub fn read_timeout_policy(mut self, policy: ReadTimeoutPolicy) -> ClientBuilder {
+        self.props.read_timeout_policy = policy;
+        self
+    }
+
+    /// Fail a connection that receives no event within `timeout` of connecting.
+    ///
+    /// Unlike the read timeout, this is not reset by comments or other data, so it catches
+    /// servers that accept the request but never send any events. Once the first event of a
+    /// connection arrives the timeout no longer applies. On expiry the stream yields
+    /// [`Error::FirstEventTimeout`] and reconnects if reconnection is enabled, or closes
+    /// otherwise; an [`on_error`](Self::on_error) callback overrides this choice.
+    pub fn first_event_timeout(mut self, timeout: Duration) -> ClientBuilder {
+        self.props.first_event_timeout = Some(timeout);
+        self
+    }
+
+    /// Limit the length, in bytes, of the event ID the client remembers and sends back as
+    /// `Last-Event-ID` when reconnecting. An ID over the limit is truncated or not stored at all,
+    /// according to `overflow`, and a warning is logged. By default IDs of any length are
+    /// stored.
+    pub fn max_last_event_id_len(
+        mut self,
+        max: usize,
+        overflow: LastEventIdOverflow,
+    ) -> ClientBuilder {
+        self.props.max_last_event_id_len = Some((max, overflow));
+        self
+    }
+
+    /// Configure the client's reconnect behaviour according to the supplied
+    /// [`ReconnectOptions`].
+    ///
+    /// [`ReconnectOptions`]: struct.ReconnectOptions.html
+    pub fn reconnect(mut self, opts: ReconnectOptions) -> ClientBuilder {
+        self.props.reconnect_opts = opts;
+        self
+    }
+
+    /// Use `opts` for reconnecting until the stream has had a stable connection, then switch to
+    /// the options set with [`reconnect`](ClientBuilder::reconnect). A connection is stable once
+    /// it has stayed up for a minute, as long as it takes for the backoff to be reset. This lets
+    /// the first connection be retried quickly, while later outages back off more gently. The
+    /// switch is made when the stable connection ends, and the backoff then starts from the new
+    /// options' base delay. By default the same options are used throughout.
+    pub fn initial_reconnect(mut self, opts: ReconnectOptions) -> ClientBuilder {
+        self.props.initial_reconnect_opts = Some(opts);
+        self
+    }
+
+    /// Customize the client's following behavior when served a redirect.
+    /// To disable following redirects, pass `0`.
+    /// By default, the limit is [`DEFAULT_REDIRECT_LIMIT`].
+    pub fn redirect_limit(mut self, limit: u32) -> ClientBuilder {
+        self.props.max_redirects = limit;
+        self
+    }
+
+    /// Pin the stream to the node named by the response header `name`, for load balancers that
+    /// tell clients which node is serving them. When a connection is made with the header set,
+    /// its value becomes the URL of every later reconnect, until a connection made with it
+    /// names another node. The value may be an absolute URL, or a path and query on the current
+    /// host.
+    ///
+    /// Unlike a redirect, which is only followed by the request that received it, the node is
+    /// kept across reconnects. It is forgotten, and the stream goes back to its original URL,
+    /// if a connection to it fails or is turned away. It isn't carried over by
+    /// [`ReconnectingRequest::to_client_builder`].
+    pub fn sticky_node_header(mut self, name: &str) -> Result<ClientBuilder> {
+        let name = HeaderName::from_str(name).map_err(|e| Error::InvalidParameter(Box::new(e)))?;
+        self.props.sticky_node_header = Some(name);
+        Ok(self)
+    }
+
+    /// Configure how a successful response without a `text/event-stream` content type is
+    /// handled. By default the content type is not checked; see [`ContentTypePolicy`].
+    ///
+    /// With [`ContentTypePolicy::Reconnect`], a mismatch is treated like a dropped connection:
+    /// the reconnect attempt waits for the next backoff delay from the configured
+    /// [`ReconnectOptions`], and that backoff is only reset by a connection that passes the
+    /// check. Non-2xx statuses are reported as [`Error::UnexpectedResponse`] before the content
+    /// type is considered.
+    pub fn content_type_policy(mut self, policy: ContentTypePolicy) -> ClientBuilder {
+        self.props.content_type_policy = policy;
+        self
+    }
+
+    /// Register a callback that is notified when the reconnect delay reaches the configured
+    /// maximum, and again when a connection subsequently succeeds. See [`BackoffEvent`].
+    ///
+    /// The callback is invoked from within the stream's `poll_next`, so it should not block.
+    pub fn on_backoff_change<F>(mut self, callback: F) -> ClientBuilder
+    where
+        F: Fn(BackoffEvent) + Send + Sync + 'static,
+    {
+        self.props.on_backoff_change = Some(Arc::new(callback));
+        self
+    }
+
+    /// Capture the builder's effective configuration, for logging or bug reports. The values of
+    /// credential-bearing headers (`Authorization`, `Proxy-Authorization` and `Cookie`) are
+    /// redacted.
+    pub fn describe(&self) -> ClientConfigSnapshot {
+        let headers = self
+            .props
+            .headers
+            .iter()
+            .map(|(name, value)| {
+                let value = if REDACTED_HEADERS.contains(name) {
+                    REDACTED.to_string()
+                } else {
+                    String::from_utf8_lossy(value.as_bytes()).into_owned()
+                };
+                (name.to_string(), value)
+            })
+            .collect();
+
+        ClientConfigSnapshot {
+            url: self.props.url.to_string(),
+            method: self.props.method.clone(),
+            headers,
+            has_body: self.props.body.is_some(),
+            last_event_id: self.last_event_id.clone(),
+            read_timeout: self.props.read_timeout,
+            read_timeout_policy: self.props.read_timeout_policy,
+            reconnect: self.props.reconnect_opts.clone(),
+            redirect_limit: self.props.max_redirects,
+            content_type_policy: self.props.content_type_policy,
+        }
+    }
+
+    /// Use `timer` for the delays between reconnection attempts, instead of [`TokioTimer`].
+    ///
+    /// This lets reconnection be timed by another runtime's timer, or by a
+    /// [`WallClockTimer`](crate::WallClockTimer) that isn't delayed by system suspend. It doesn't
+    /// affect the read timeout, which is enforced by the connector.
+    pub fn timer<T>(mut self, timer: T) -> ClientBuilder
+    where
+        T: Timer + 'static,
+    {
+        self.props.timer = Arc::new(timer);
+        self
+    }
+
+    /// Notify `observer` of each step in the stream's life, from connection attempts to events
+    /// and errors; see [`StreamObserver`]. This replaces any observer set before.
+    pub fn observer<O>(mut self, observer: O) -> ClientBuilder
+    where
+        O: StreamObserver + 'static,
+    {
+        self.props.observer = Some(Arc::new(observer));
+        self
+    }
+
+    /// Build with a specific client connector.
+    pub fn build_with_conn<C>(self, conn: C) -> impl Client
+    where
+        C: Service<Uri> + Clone + Send + Sync + 'static,
+        C::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin,
+        C::Future: Send + 'static,
+        C::Error: Into<BoxError>,
+    {
+        let client = self.timeout_client(conn);
+        self.build_with_http_client(client)
+    }
+
+    /// Build a single stream with a specific client connector, set up as by
+    /// [`build_with_conn`](ClientBuilder::build_with_conn). Unlike [`Client::stream`], this
+    /// returns the concrete [`ReconnectingRequest`], so its inspection and control methods (such
+    /// as [`ReconnectingRequest::time_to_first_event`]) can be used.
+    pub fn build_stream_with_conn<C>(self, conn: C) -> ReconnectingRequest<TimeoutClient<C>>
+    where
+        C: Service<Uri> + Clone + Send + Sync + 'static,
+        C::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin,
+        C::Future: Send + 'static,
+        C::Error: Into<BoxError>,
+    {
+        let client = self.timeout_client(conn);
+        self.build_stream_with_service(client)
+    }
+
+    /// The HTTP client for `conn`, which times out reads after the configured read timeout.
+    fn timeout_client<C>(&self, conn: C) -> TimeoutClient<C>
+    where
+        C: Service<Uri> + Clone + Send + Sync + 'static,
+        C::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin,
+        C::Future: Send + 'static,
+        C::Error: Into<BoxError>,
+    {
+        let mut connector = TimeoutConnector::new(conn);
+        connector.set_read_timeout(self.props.read_timeout);
+
+        hyper::Client::builder().build::<_, hyper::Body>(connector)
+    }
+
+    /// Build with a [`BoxConnector`], returning a boxed client. Every connector is boxed the
+    /// same way, so the connector can be chosen at runtime, and the client stored as a single
+    /// type whichever connector it uses.
+    pub fn build_with_boxed_conn(self, conn: BoxConnector) -> Box<dyn Client> {
+        Box::new(self.build_with_conn(conn))
+    }
+
+    /// Build with an HTTP client connector.
+    pub fn build_http(self) -> impl Client {
+        self.build_with_conn(HttpConnector::new())
+    }
+
+    /// Build a single stream with an HTTP client connector, returning the concrete
+    /// [`ReconnectingRequest`]; see [`build_stream_with_conn`](ClientBuilder::build_stream_with_conn).
+    pub fn build_stream_http(self) -> ReconnectingRequest<TimeoutClient<HttpConnector>> {
+        self.build_stream_with_conn(HttpConnector::new())
+    }
+
+    /// Build with an HTTP client connector that resolves host names using `resolver` instead of
+    /// the system resolver. This can be used, for example, to point a production host name at a
+    /// local server in tests.
+    pub fn build_http_with_resolver<R>(self, resolver: R) -> impl Client
+    where
+        R: Service<Name> + Clone + Send + Sync + 'static,
+        R::Response: Iterator<Item = SocketAddr>,
+        R::Error: Into<BoxError>,
+        R::Future: Send,
+    {
+        self.build_with_conn(HttpConnector::new_with_resolver(resolver))
+    }
+
+    /// Set the oldest TLS version that [`ClientBuilder::build`] will negotiate. Connections to
+    /// servers that only offer older versions fail. The default is [`TlsVersion::Tls12`].
+    #[cfg(feature = "rustls")]
+    pub fn min_tls_version(mut self, version: TlsVersion) -> ClientBuilder {
+        self.min_tls_version = version;
+        self
+    }
+
+    #[cfg(feature = "rustls")]
+    /// Build with an HTTPS client connector, using the OS root certificate store.
+    pub fn build(self) -> impl Client {
+        let conn = https_connector(self.min_tls_version);
+        self.build_with_conn(conn)
+    }
+
+    /// Build a single stream with an HTTPS client connector, set up as by
+    /// [`build`](ClientBuilder::build), returning the concrete [`ReconnectingRequest`]; see
+    /// [`build_stream_with_conn`](ClientBuilder::build_stream_with_conn).
+    #[cfg(feature = "rustls")]
+    pub fn build_stream(self) -> ReconnectingRequest<TimeoutClient<HttpsConnector>> {
+        let conn = https_connector(self.min_tls_version);
+        self.build_stream_with_conn(conn)
+    }
+
+    /// Build with an experimental [`Http3Service`], which streams over HTTP/3 (QUIC), trusting
+    /// the Mozilla root certificates. The URL must be `https`, and the server must offer HTTP/3
+    /// on its port. Each connection attempt makes a new QUIC connection. A
+    /// [`read_timeout`](ClientBuilder::read_timeout) only applies with
+    /// [`ReadTimeoutPolicy::EventsOnly`], as there is no connector to time out reads.
+    /// To use other TLS settings, pass [`Http3Service::with_tls_config`] to
+    /// [`build_with_service`](ClientBuilder::build_with_service).
+    #[cfg(feature = "http3")]
+    pub fn build_http3(self) -> impl Client {
+        self.build_with_service(Http3Service::new())
+    }
+
+    /// Build with the given [`hyper::client::Client`].
+    pub fn build_with_http_client<C>(self, http: hyper::Client<C>) -> impl Client
+    where
+        C: Connect + Clone + Send + Sync + 'static,
+    {
+        self.build_impl(http)
+    }
+
+    /// Build with an arbitrary HTTP [`Service`], such as a [`hyper::client::Client`] wrapped in
+    /// tower middleware (timeouts, concurrency limits and so on). The service is cloned for each
+    /// connection attempt, and is polled for readiness before each request is sent.
+    ///
+    /// Reconnect and redirect handling is still done by the client, around the service.
+    pub fn build_with_service<S>(self, service: S) -> impl Client
+    where
+        S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + Sync + 'static,
+        S::Error: Into<BoxError>,
+        S::Future: Send + Sync + 'static,
+    {
+        self.build_impl(service)
+    }
+
+    /// Build a client that replays a recorded event stream from the file at `path` instead of
+    /// connecting to the network, for reproducing problems offline or testing against captured
+    /// streams.
+    ///
+    /// Each connection attempt reads the whole file and serves it as a successful
+    /// `text/event-stream` response, so the events go through the same parsing and reconnect
+    /// handling as a live stream. The end of the file is the end of the response: the stream
+    /// then reconnects, replaying the file again, or not, according to the reconnect options. A
+    /// file that can't be read fails the connection attempt. Nothing is sent to the builder's
+    /// URL, so any URL will do.
+    #[cfg(feature = "test-util")]
+    pub fn build_from_file<P: AsRef<std::path::Path>>(self, path: P) -> impl Client {
+        let path = Arc::new(path.as_ref().to_path_buf());
+        self.build_impl(hyper::service::service_fn(move |_request| {
+            let response = std::fs::read(&*path).map(|contents| {
+                let mut response = Response::new(Body::from(contents));
+                response.headers_mut().insert(
+                    hyper::header::CONTENT_TYPE,
+                    HeaderValue::from_static("text/event-stream"),
+                );
+                response
+            });
+            futures::future::ready(response)
+        }))
+    }
+
+    /// Build a single stream using the given [`hyper::client::Client`], returning the concrete
+    /// [`ReconnectingRequest`]; see [`build_stream_with_conn`](ClientBuilder::build_stream_with_conn).
+    /// The client is used as it is, so settings applied to the connector, such as the
+    /// [`read_timeout`](ClientBuilder::read_timeout), are not.
+    pub fn build_stream_with_http_client<C>(
+        self,
+        http: hyper::Client<C>,
+    ) -> ReconnectingRequest<hyper::Client<C>>
+    where
+        C: Connect + Clone + Send + Sync + 'static,
+    {
+        self.build_stream_with_service(http)
+    }
+
+    /// Build a single stream with an arbitrary HTTP [`Service`], as
+    /// [`build_with_service`](ClientBuilder::build_with_service) does, returning the concrete
+    /// [`ReconnectingRequest`]; see [`build_stream_with_conn`](ClientBuilder::build_stream_with_conn).
+    pub fn build_stream_with_service<S>(self, service: S) -> ReconnectingRequest<S>
+    where
+        S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + Sync + 'static,
+        S::Error: Into<BoxError>,
+        S::Future: Send + Sync + 'static,
+    {
+        let client = self.build_impl(service);
+        ReconnectingRequest::new(client.http, client.request_props, client.last_event_id)
+    }
+
+    fn build_impl<S>(self, http: S) -> ClientImpl<S> {
+        ClientImpl {
+            http,
+            request_props: self.props,
+            last_event_id: self.last_event_id,
+        }
+    }
+}
+
+const RECONNECT_ATTEMPT_HEADER: &str = "x-reconnect-attempt";
+
+const REDACTED: &str = "<redacted>";
+
+const REDACTED_HEADERS: [HeaderName; 3] = [
+    hyper::header::AUTHORIZATION,
+    hyper::header::PROXY_AUTHORIZATION,
+    hyper::header::COOKIE,
+];
+
+/// A snapshot of a [`ClientBuilder`]'s configuration, returned by [`ClientBuilder::describe`].
+///
+/// Its [`Display`](fmt::Display) implementation prints one setting per line.
+#[derive(Clone, Debug)]
+pub struct ClientConfigSnapshot {
+    pub url: String,
+    pub method: String,
+    /// Header names and values in the order they will be sent, with credentials redacted.
+    pub headers: Vec<(String, String)>,
+    pub has_body: bool,
+    pub last_event_id: Option<String>,
+    pub read_timeout: Option<Duration>,
+    pub read_timeout_policy: ReadTimeoutPolicy,
+    pub reconnect: ReconnectOptions,
+    pub redirect_limit: u32,
+    pub content_type_policy: ContentTypePolicy,
+}
+
+impl fmt::Display for ClientConfigSnapshot {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "url: {}", self.url)?;
+        writeln!(f, "method: {}", self.method)?;
+        writeln!(f, "headers:")?;
+        for (name, value) in &self.headers {
+            writeln!(f, "  {}: {}", name, value)?;
+        }
+        writeln!(f, "body: {}", if self.has_body { "set" } else { "none" })?;
+        writeln!(f, "last event id: {:?}", self.last_event_id)?;
+        writeln!(f, "read timeout: {:?}", self.read_timeout)?;
+        writeln!(f, "read timeout policy: {:?}", self.read_timeout_policy)?;
+        writeln!(f, "reconnect: {:?}", self.reconnect)?;
+        writeln!(f, "redirect limit: {}", self.redirect_limit)?;
+        write!(f, "content type policy: {:?}", self.content_type_policy)
+    }
+}
+
+/// The settings of a [`ClientBuilder`], which each of its streams is configured with, and
+/// which [`ReconnectingRequest::to_client_builder`] copies back into a builder.
+#[derive(Clone)]
+struct RequestProps {
+    url: Uri,
+    headers: HeaderMap,
+    method: String,
+    body: Option<String>,
+    reconnect_opts: ReconnectOptions,
+    initial_reconnect_opts: Option<ReconnectOptions>,
+    read_timeout: Option<Duration>,
+    read_timeout_policy: ReadTimeoutPolicy,
+    max_redirects: u32,
+    content_type_policy: ContentTypePolicy,
+    extensions: Vec<ExtensionSetter>,
+    on_backoff_change: Option<BackoffCallback>,
+    reset_event_type: Option<String>,
+    error_event_type: Option<String>,
+    before_send: Option<RequestHook>,
+    request_id: Option<(HeaderName, RequestIdGenerator)>,
+    on_response: Option<ResponseHook>,
+    emit_empty_events: bool,
+    skip_empty_data: bool,
+    dispatch_on_eof: bool,
+    error_on_unexpected_eof: bool,
+    #[cfg(feature = "raw-events")]
+    raw_events: bool,
+    #[cfg(feature = "trace-fields")]
+    trace_fields: Option<Vec<String>>,
+    max_line_length: Option<usize>,
+    max_header_size: Option<usize>,
+    incremental_data: bool,
+    on_error: Option<ErrorCallback>,
+    timer: Arc<dyn Timer>,
+    observer: Option<Arc<dyn StreamObserver>>,
+    first_event_timeout: Option<Duration>,
+    max_last_event_id_len: Option<(usize, LastEventIdOverflow)>,
+    keep_alive_header: bool,
+    max_bytes_per_connection: Option<(u64, ErrorAction)>,
+    max_events: Option<u64>,
+    reconnect_attempt_header: bool,
+    reset_reconnect_attempts: bool,
+    event_rate_window: Duration,
+    rate_limit: Option<u32>,
+    emit_resuming: bool,
+    expected_event_types: Option<(Vec<String>, EventTypePolicy)>,
+    label: Option<String>,
+    preflight: Option<Preflight>,
+    prepended_events: Vec<Event>,
+    case_insensitive_event_types: bool,
+    skip_consecutive_duplicates: bool,
+    skip_seen_ids: Option<usize>,
+    cache_buster: Option<String>,
+    transform_data: Option<(DataTransform, ErrorAction)>,
+    #[cfg(feature = "data-encoding")]
+    data_encoding: DataEncoding,
+    #[cfg(feature = "cookies")]
+    cookie_jar: Option<CookieJar>,
+    heartbeat_interval: Option<Duration>,
+    sticky_node_header: Option<HeaderName>,
+}
+
+impl RequestProps {
+    /// Whether `event` is of the `configured` type, if one is set.
+    fn is_event_type(&self, configured: &Option<String>, event: &Event) -> bool {
+        configured.as_deref().is_some_and(|configured| {
+            event_type_matches(
+                configured,
+                &event.event_type,
+                self.case_insensitive_event_types,
+            )
+        })
+    }
+
+    fn log_prefix(&self) -> LogPrefix<'_> {
+        LogPrefix(self.label.as_deref())
+    }
+}
+
+/// Formats as `[label] ` for a labelled stream, and as nothing otherwise, to start its log
+/// messages with.
+struct LogPrefix<'a>(Option<&'a str>);
+
+impl fmt::Display for LogPrefix<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(label) => write!(f, "[{}] ", label),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A client implementation that connects to a server using the Server-Sent Events protocol
+/// and consumes the event stream indefinitely.
+/// Can be parameterized with different HTTP services, such as a hyper client using an HTTP or
+/// HTTPS connector.
+struct ClientImpl<S> {
+    http: S,
+    request_props: RequestProps,
+    last_event_id: Option<String>,
+}
+
+impl<S> Client for ClientImpl<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + Sync + 'static,
+    S::Error: Into<BoxError>,
+    S::Future: Send + Sync + 'static,
+{
+    /// Connect to the server and begin consuming the stream. Produces a
+    /// [`Stream`] of [`Event`](crate::Event)s wrapped in [`Result`].
+    ///
+    /// Do not use the stream after it returned an error!
+    ///
+    /// After the first successful connection, the stream will
+    /// reconnect for retryable errors.
+    fn stream(&self) -> BoxStream<Result<SSE>> {
+        Box::pin(ReconnectingRequest::new(
+            self.http.clone(),
+            self.request_props.clone(),
+            self.last_event_id.clone(),
+        ))
+    }
+
+    fn stream_eager(&self) -> BoxStream<Result<SSE>> {
+        let mut request = Box::pin(ReconnectingRequest::new(
+            self.http.clone(),
+            self.request_props.clone(),
+            self.last_event_id.clone(),
+        ));
+        request.as_mut().connect_eagerly();
+        request
+    }
+}
+
+#[allow(clippy::large_enum_variant)] // false positive
+#[pin_project(project = StateProj)]
+enum State {
+    New,
+    Preflighting(#[pin] ResponseFuture),
+    Connecting {
+        retry: bool,
+        #[pin]
+        resp: ResponseFuture,
+    },
+    Connected(#[pin] hyper::Body),
+    WaitingToReconnect(#[pin] SleepFuture),
+    FollowingRedirect(Option<HeaderValue>),
+    StreamClosed,
+}
+
+impl State {
+    fn name(&self) -> &'static str {
+        match self {
+            State::New => "new",
+            State::Preflighting(_) => "preflighting",
+            State::Connecting { retry: false, .. } => "connecting(no-retry)",
+            State::Connecting { retry: true, .. } => "connecting(retry)",
+            State::Connected(_) => "connected",
+            State::WaitingToReconnect(_) => "waiting-to-reconnect",
+            State::FollowingRedirect(_) => "following-redirect",
+            State::StreamClosed => "closed",
+        }
+    }
+}
+
+impl Debug for State {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Default)]
+struct ReconnectSignal {
+    requested: AtomicBool,
+    waker: AtomicWaker,
+}
+
+/// Why a [`ReconnectingRequest`] last reconnected, as returned by
+/// [`ReconnectingRequest::last_reconnect_reason`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReconnectReason {
+    /// The server ended the response body.
+    ServerClosed,
+    /// A connection attempt failed, or the connection was lost with an error.
+    TransportError,
+    /// The connection timed out, either reading the body or waiting for the first event.
+    Timeout,
+    /// The server turned the request away, with an error status or the wrong content type, or
+    /// drained the connection with an HTTP/2 GOAWAY.
+    ServerRetry,
+    /// The client dropped the connection: [`ReconnectingRequest::reconnect_now`] was called, the
+    /// connection reached [`ClientBuilder::max_bytes_per_connection`], or an event's data
+    /// couldn't be transformed by [`ClientBuilder::transform_data`].
+    ClientRequested,
+}
+
+impl ReconnectReason {
+    fn for_error(error: &Error) -> Self {
+        match error {
+            Error::TimedOut | Error::FirstEventTimeout => ReconnectReason::Timeout,
+            Error::Eof | Error::UnexpectedEof => ReconnectReason::ServerClosed,
+            Error::UnexpectedResponse(_) | Error::InvalidContentType(_) => {
+                ReconnectReason::ServerRetry
+            }
+            Error::ConnectionByteLimit(_) | Error::TransformFailed(_) => {
+                ReconnectReason::ClientRequested
+            }
+            _ => ReconnectReason::TransportError,
+        }
+    }
+}
+
+/// Whether a reconnect recorded in a [`ReconnectRecord`] retried a failed attempt.
+#[cfg(feature = "test-util")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReconnectKind {
+    /// A connection attempt failed and is being retried.
+    Retry,
+    /// A connection was lost or rejected, and a new one will be made.
+    Reconnect,
+}
+
+/// A reconnect scheduled by a [`ReconnectingRequest`], as returned by
+/// [`ReconnectingRequest::reconnect_log`].
+#[cfg(feature = "test-util")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReconnectRecord {
+    /// How long the stream waits before the attempt.
+    pub delay: Duration,
+    pub kind: ReconnectKind,
+    /// The number of the reconnect attempt that follows the wait, counted as for
+    /// [`ClientBuilder::reconnect_attempt_header`].
+    pub attempt: u32,
+}
+
+/// A handle for asking a [`ReconnectingRequest`] to reconnect immediately, which can be used
+/// from any task or thread. Obtained from [`ReconnectingRequest::reconnect_handle`].
+#[derive(Clone)]
+pub struct ReconnectHandle {
+    signal: Arc<ReconnectSignal>,
+}
+
+impl ReconnectHandle {
+    /// Drop the stream's current connection and reconnect, skipping any remaining backoff. See
+    /// [`ReconnectingRequest::reconnect_now`].
+    pub fn reconnect_now(&self) {
+        self.signal.requested.store(true, Ordering::SeqCst);
+        self.signal.waker.wake();
+    }
+}
+
+/// A stream of events from one URL, which reconnects as configured. [`Client::stream`] returns
+/// one boxed; to use its inspection and control methods, build one with
+/// [`ClientBuilder::build_stream_http`] or another of the `build_stream_*` methods.
+#[must_use = "streams do nothing unless polled"]
+#[pin_project]
+pub struct ReconnectingRequest<S> {
+    http: S,
+    props: RequestProps,
+    #[pin]
+    state: State,
+    retry_strategy: Box<dyn RetryStrategy + Send + Sync>,
+    dns_retry_strategy: Option<Box<dyn RetryStrategy + Send + Sync>>,
+    current_url: Uri,
+    sticky_url: Option<Uri>,
+    redirect_count: u32,
+    event_parser: EventParser,
+    last_event_id: Option<String>,
+    at_max_backoff: bool,
+    last_connect_attempt: Option<Instant>,
+    first_connect_attempt: Option<Instant>,
+    time_to_first_event: Option<Duration>,
+    first_event_deadline: Option<SleepFuture>,
+    read_deadline: Option<SleepFuture>,
+    heartbeat: Option<SleepFuture>,
+    shutting_down: bool,
+    reconnect_attempts: u32,
+    last_reconnect_reason: Option<ReconnectReason>,
+    reconnect_signal: Arc<ReconnectSignal>,
+    connection_bytes: u64,
+    eof_dispatched: bool,
+    event_rate: EventRate,
+    rate_limiter: Option<TokenBucket>,
+    rate_limit_wait: Option<(SleepFuture, SSE)>,
+    resuming_announced: bool,
+    unexpected_event_types: u64,
+    preflight_headers: Option<HeaderMap>,
+    last_status: Option<StatusCode>,
+    circuit_breaker: Option<CircuitBreaker>,
+    steady_reconnect_opts: Option<ReconnectOptions>,
+    connected_since: Option<Instant>,
+    events_remaining: Option<u64>,
+    budgeted_reconnects: VecDeque<Instant>,
+    last_received: Option<Event>,
+    held_items: VecDeque<SSE>,
+    holding_items: bool,
+    #[cfg(feature = "test-util")]
+    reconnect_log: Vec<ReconnectRecord>,
+}
+
+impl<S> ReconnectingRequest<S> {
+    fn new(
+        http: S,
+        mut props: RequestProps,
+        last_event_id: Option<String>,
+    ) -> ReconnectingRequest<S> {
+        // The initial options are used until a stable connection, then swapped for these.
+        let steady_reconnect_opts = props
+            .initial_reconnect_opts
+            .take()
+            .map(|initial| std::mem::replace(&mut props.reconnect_opts, initial));
+        let url = props.url.clone();
+        let mut event_parser = new_event_parser(&props);
+        // Prepended events are yielded like parsed ones, so they go through the same handling.
+        for event in &props.prepended_events {
+            event_parser.queue_event(event.clone());
+        }
+        let event_rate = EventRate::new(props.event_rate_window);
+        let dns_retry_strategy = dns_retry_strategy(&props.reconnect_opts);
+        let rate_limiter = props.rate_limit.map(TokenBucket::new);
+        let circuit_breaker = props
+            .reconnect_opts
+            .circuit_breaker
+            .map(CircuitBreaker::new);
+        let retry_strategy = retry_strategy(&props.reconnect_opts);
+        let events_remaining = props.max_events;
+        ReconnectingRequest {
+            props,
+            http,
+            state: State::New,
+            retry_strategy,
+            dns_retry_strategy,
+            redirect_count: 0,
+            current_url: url,
+            sticky_url: None,
+            event_parser,
+            last_event_id,
+            at_max_backoff: false,
+            last_connect_attempt: None,
+            first_connect_attempt: None,
+            time_to_first_event: None,
+            first_event_deadline: None,
+            read_deadline: None,
+            heartbeat: None,
+            shutting_down: false,
+            reconnect_attempts: 0,
+            last_reconnect_reason: None,
+            reconnect_signal: Arc::default(),
+            connection_bytes: 0,
+            eof_dispatched: false,
+            event_rate,
+            rate_limiter,
+            rate_limit_wait: None,
+            resuming_announced: false,
+            unexpected_event_types: 0,
+            preflight_headers: None,
+            last_status: None,
+            circuit_breaker,
+            steady_reconnect_opts,
+            connected_since: None,
+            events_remaining,
+            budgeted_reconnects: VecDeque::new(),
+            last_received: None,
+            held_items: VecDeque::new(),
+            holding_items: false,
+            #[cfg(feature = "test-util")]
+            reconnect_log: Vec::new(),
+        }
+    }
+
+    /// The time between the stream's first connection attempt and the delivery of its first
+    /// event, or `None` if no event has been delivered yet. The first attempt is made when the
+    /// stream is first polled (or immediately, for [`Client::stream_eager`]), so this covers
+    /// connecting, any redirects, and the server's time to send the first event.
+    pub fn time_to_first_event(&self) -> Option<Duration> {
+        self.time_to_first_event
+    }
+
+    /// A smoothed count of the events this stream has delivered per second, averaged over the
+    /// window set with [`ClientBuilder::event_rate_window`]. It falls back towards zero while no
+    /// events arrive, including while the stream is reconnecting.
+    pub fn event_rate(&self) -> f64 {
+        self.event_rate.rate_at(Instant::now())
+    }
+
+    /// The clock that this stream's reconnect delays are measured against, which decides how
+    /// they behave across a system suspend. See [`TimerClock`].
+    pub fn timer_clock(&self) -> TimerClock {
+        self.props.timer.clock()
+    }
+
+    /// The status of the most recent response accepted as a successful connection, or `None` if
+    /// the stream hasn't connected yet. Servers may use different success statuses, such as
+    /// `200` and `206`, to tell a fresh stream from a resumed one.
+    pub fn last_status(&self) -> Option<StatusCode> {
+        self.last_status
+    }
+
+    /// The state of the circuit breaker set with [`ReconnectOptionsBuilder::circuit_breaker`],
+    /// which is always [`CircuitState::Closed`] if there isn't one.
+    ///
+    /// [`ReconnectOptionsBuilder::circuit_breaker`]: crate::ReconnectOptionsBuilder::circuit_breaker
+    pub fn circuit_state(&self) -> CircuitState {
+        self.circuit_breaker
+            .as_ref()
+            .map_or(CircuitState::Closed, CircuitBreaker::state)
+    }
+
+    /// The smoothed time that this stream's connections have stayed up, with failed attempts
+    /// counting as zero, as learned by [`ReconnectStrategy::Adaptive`]. `None` with any other
+    /// strategy, or until a connection or attempt has ended.
+    pub fn average_uptime(&self) -> Option<Duration> {
+        self.retry_strategy.average_uptime()
+    }
+
+    /// How many events this stream has received of a type not declared with
+    /// [`ClientBuilder::expected_event_types`].
+    pub fn unexpected_event_types(&self) -> u64 {
+        self.unexpected_event_types
+    }
+
+    /// How many event IDs are remembered by [`ClientBuilder::skip_seen_ids`], and how many have
+    /// been evicted to make room for newer ones. Both are zero if it isn't enabled.
+    pub fn seen_ids(&self) -> (usize, u64) {
+        self.event_parser
+            .seen_ids()
+            .map_or((0, 0), |seen_ids| (seen_ids.len(), seen_ids.evictions()))
+    }
+
+    /// Every reconnect this stream has scheduled, oldest first. The log is never trimmed, so this
+    /// is meant for tests rather than long-running streams.
+    #[cfg(feature = "test-util")]
+    pub fn reconnect_log(&self) -> &[ReconnectRecord] {
+        &self.reconnect_log
+    }
+
+    /// Drop the current connection and reconnect now, for example because the credentials it
+    /// was made with are stale.
+    ///
+    /// This applies when the stream is connected or waiting to reconnect: any remaining backoff
+    /// is skipped (though a configured minimum reconnect interval and reconnect grace are still
+    /// honoured), and the new request is built afresh, running the [`ClientBuilder::before_send`]
+    /// hook again. If a connection attempt is in progress, the reconnect happens once it has
+    /// connected. Events already received are delivered before the connection is dropped.
+    pub fn reconnect_now(&self) {
+        self.reconnect_handle().reconnect_now();
+    }
+
+    /// Why the stream last reconnected, or `None` if it hasn't. This is recorded as soon as a
+    /// reconnect is scheduled, so it also describes a reconnect that is still waiting out its
+    /// delay.
+    pub fn last_reconnect_reason(&self) -> Option<ReconnectReason> {
+        self.last_reconnect_reason
+    }
+
+    /// A handle for calling [`reconnect_now`](Self::reconnect_now) from another task.
+    pub fn reconnect_handle(&self) -> ReconnectHandle {
+        ReconnectHandle {
+            signal: self.reconnect_signal.clone(),
+        }
+    }
+
+    /// Stop the stream without losing events that have already been received.
+    ///
+    /// The connection is closed and no further connection attempts are made, but events that
+    /// were already parsed are still yielded. Once they have been consumed the stream ends, so
+    /// the stream yielding `None` is the sign that every one of them has been delivered.
+    /// Nothing more is read from the connection, and data for an event that hadn't been
+    /// completely received is discarded.
+    ///
+    /// This only marks the stream as shutting down, rather than being an `async` method that
+    /// completes once the events are delivered: they are delivered by polling the stream, which
+    /// needs the same mutable access, so such a future couldn't finish while the caller is
+    /// consuming them. Keep polling the stream, or use a method such as
+    /// [`pipe_to`](Self::pipe_to) that runs until it ends.
+    pub fn shutdown(self: Pin<&mut Self>) {
+        let mut this = self.project();
+        *this.shutting_down = true;
+        this.state.set(State::StreamClosed);
+    }
+
+    /// Write each event of the stream to `writer` in the event stream format, as given by
+    /// [`Event::to_sse`](crate::Event::to_sse), until the stream ends. The output can be replayed
+    /// with `ClientBuilder::build_from_file`, which the `test-util` feature provides.
+    ///
+    /// Each event is written and flushed before the next is read, so a slow writer slows down
+    /// reading from the connection rather than events building up in memory. Comments and other
+    /// items aren't written. Errors from the stream are logged and skipped, as the stream
+    /// reconnects after them, and piping stops without an error once the stream is closed. An
+    /// error writing to `writer` stops piping and is returned.
+    pub async fn pipe_to<W>(mut self: Pin<&mut Self>, mut writer: W) -> Result<()>
+    where
+        W: AsyncWrite + Unpin,
+        S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + Sync + 'static,
+        S::Error: Into<BoxError>,
+        S::Future: Send + Sync + 'static,
+    {
+        while let Some(event) = self.as_mut().next_event().await {
+            write_all(&mut writer, event.to_sse().as_bytes()).await?;
+        }
+        Ok(())
+    }
+
+    /// The stream's next event, skipping other items and logging errors, or `None` once the
+    /// stream has ended or closed.
+    pub(crate) async fn next_event(mut self: Pin<&mut Self>) -> Option<Event>
+    where
+        S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + Sync + 'static,
+        S::Error: Into<BoxError>,
+        S::Future: Send + Sync + 'static,
+    {
+        while let Some(item) = futures::future::poll_fn(|cx| self.as_mut().poll_next(cx)).await {
+            match item {
+                Ok(SSE::Event(event)) => return Some(event),
+                Ok(_) => {}
+                Err(Error::StreamClosed) => break,
+                Err(e) => debug!("{}skipping stream error: {:?}", self.props.log_prefix(), e),
+            }
+        }
+        None
+    }
+
+    /// Wait up to `timeout` for the stream's next event, skipping comments and other items.
+    ///
+    /// Returns `Ok(None)` if no event arrives in time. The connection is left alone, so the
+    /// stream can be polled again afterwards and an event that was partly received is still
+    /// delivered. Errors from the stream are returned as they are yielded, and the stream
+    /// reconnects after them as usual; once the stream has ended or closed,
+    /// [`Error::StreamClosed`] is returned. The timeout is measured with the configured
+    /// [`timer`](ClientBuilder::timer).
+    pub async fn next_event_timeout(
+        mut self: Pin<&mut Self>,
+        timeout: Duration,
+    ) -> Result<Option<Event>>
+    where
+        S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + Sync + 'static,
+        S::Error: Into<BoxError>,
+        S::Future: Send + Sync + 'static,
+    {
+        let mut deadline = self.props.timer.sleep(timeout);
+        futures::future::poll_fn(|cx| loop {
+            match self.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(SSE::Event(event)))) => return Poll::Ready(Ok(Some(event))),
+                Poll::Ready(Some(Ok(_))) => continue,
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(e)),
+                Poll::Ready(None) => return Poll::Ready(Err(Error::StreamClosed)),
+                Poll::Pending => return deadline.as_mut().poll(cx).map(|()| Ok(None)),
+            }
+        })
+        .await
+    }
+
+    /// Drive the stream until it is connected, so that a bad URL or rejected credentials can be
+    /// caught before consuming it.
+    ///
+    /// Returns `Ok(())` once a response has been accepted, or straight away if the stream is
+    /// already connected. The first error the stream yields is returned instead, leaving the
+    /// stream to reconnect as usual if it is polled again; [`Error::StreamClosed`] is returned if
+    /// the stream has ended or closed. Items the stream yields while connecting, such as resume
+    /// notices or events that arrive with the response, are kept and yielded first when the
+    /// stream is next polled, so nothing is lost.
+    pub async fn wait_for_connection(mut self: Pin<&mut Self>) -> Result<()>
+    where
+        S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + Sync + 'static,
+        S::Error: Into<BoxError>,
+        S::Future: Send + Sync + 'static,
+    {
+        futures::future::poll_fn(|cx| {
+            // Items already held are left for the caller, and new ones are held after them.
+            *self.as_mut().project().holding_items = true;
+            let polled = self.as_mut().poll_connected(cx);
+            *self.as_mut().project().holding_items = false;
+            polled
+        })
+        .await
+    }
+
+    fn poll_connected(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>>
+    where
+        S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + Sync + 'static,
+        S::Error: Into<BoxError>,
+        S::Future: Send + Sync + 'static,
+    {
+        loop {
+            if matches!(self.state, State::Connected(_)) {
+                return Poll::Ready(Ok(()));
+            }
+            match self.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(item))) => self.as_mut().project().held_items.push_back(item),
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(e)),
+                Poll::Ready(None) => return Poll::Ready(Err(Error::StreamClosed)),
+                Poll::Pending if matches!(self.state, State::Connected(_)) => {
+                    return Poll::Ready(Ok(()))
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+
+    /// Replace the reconnect options used by this stream from now on.
+    ///
+    /// A reconnect delay that is already being waited out is left alone; the new options apply
+    /// from the next delay computed. The backoff carries on from where it was, capped at the new
+    /// maximum, unless the base delay or the [`ReconnectStrategy`] has changed, in which case it
+    /// starts again from the new base delay. A changed circuit breaker starts again closed, with
+    /// no failures counted. While the options set with [`ClientBuilder::initial_reconnect`] are
+    /// in use, these replace them, and the stream still switches to the steady options once it
+    /// has had a stable connection.
+    pub fn update_reconnect_opts(self: Pin<&mut Self>, opts: ReconnectOptions) {
+        let this = self.project();
+        if opts.circuit_breaker != this.props.reconnect_opts.circuit_breaker {
+            *this.circuit_breaker = opts.circuit_breaker.map(CircuitBreaker::new);
+        }
+        if opts.strategy != this.props.reconnect_opts.strategy {
+            *this.retry_strategy = retry_strategy(&opts);
+        } else {
+            this.retry_strategy
+                .change_limits(opts.delay, opts.delay_max, opts.backoff_factor);
+        }
+        match (this.dns_retry_strategy.as_mut(), opts.dns_delays()) {
+            (Some(strategy), Some((delay, delay_max))) => {
+                strategy.change_limits(delay, delay_max, opts.backoff_factor)
+            }
+            _ => *this.dns_retry_strategy = dns_retry_strategy(&opts),
+        }
+        this.props.reconnect_opts = opts;
+    }
+
+    /// Point the stream at a different path, and query if any, on the same endpoint, keeping the
+    /// connector, headers and other configuration. `path` must start with `/`.
+    ///
+    /// The current connection, if any, is left open; the new path is used from the next time
+    /// the stream connects, and replaces the original URL in [`to_client_builder`]. Any redirect
+    /// followed from the old path is forgotten, as is any node set by
+    /// [`ClientBuilder::sticky_node_header`].
+    ///
+    /// [`to_client_builder`]: ReconnectingRequest::to_client_builder
+    pub fn set_path(mut self: Pin<&mut Self>, path: &str) -> Result<()> {
+        let url = with_path(&self.props.url, path)?;
+        info!("{}switching to path {}", self.props.log_prefix(), path);
+        let this = self.as_mut().project();
+        this.props.url = url;
+        *this.sticky_url = None;
+        self.reset_redirects();
+        Ok(())
+    }
+
+    /// Create a [`ClientBuilder`] configured like the one this stream was built from, resuming
+    /// from the stream's current last event ID.
+    ///
+    /// Everything set on the original builder is carried over, including headers, reconnect
+    /// options and callbacks, but not the HTTP client or connector: choose one again when
+    /// building, and set its minimum TLS version again if needed. The builder targets the
+    /// original URL, not any redirect that was followed.
+    /// Events set with [`ClientBuilder::prepend_events`] aren't carried over, since the stream
+    /// has already delivered them.
+    pub fn to_client_builder(&self) -> ClientBuilder {
+        let mut props = self.props.clone();
+        // While the initial options are in use, the steady ones are kept aside.
+        if let Some(steady) = &self.steady_reconnect_opts {
+            let initial = std::mem::replace(&mut props.reconnect_opts, steady.clone());
+            props.initial_reconnect_opts = Some(initial);
+        }
+        props.prepended_events = Vec::new();
+        ClientBuilder {
+            props,
+            last_event_id: self.last_event_id.clone(),
+            #[cfg(feature = "rustls")]
+            min_tls_version: TlsVersion::default(),
+        }
+    }
+
+    fn record_connect_attempt(self: Pin<&mut Self>, now: Instant) {
+        let this = self.project();
+        if this.last_connect_attempt.is_some() && *this.redirect_count == 0 {
+            *this.reconnect_attempts += 1;
+        }
+        *this.last_connect_attempt = Some(now);
+        this.first_connect_attempt.get_or_insert(now);
+        if let Some(breaker) = this.circuit_breaker {
+            breaker.record_attempt();
+        }
+        if let (Some(observer), 0) = (&this.props.observer, *this.redirect_count) {
+            observer.on_connecting();
+        }
+    }
+
+    /// Count a reconnect about to be made at `now` against the retry budget, returning whether
+    /// the budget allows it. First connections, redirects and reconnects the client asked for
+    /// aren't counted.
+    fn spend_retry_budget(self: Pin<&mut Self>, now: Instant) -> bool {
+        let this = self.project();
+        let (max, window) = match this.props.reconnect_opts.retry_budget {
+            Some(budget) => budget,
+            None => return true,
+        };
+        if this.last_connect_attempt.is_none()
+            || *this.redirect_count != 0
+            || *this.last_reconnect_reason == Some(ReconnectReason::ClientRequested)
+        {
+            return true;
+        }
+
+        let reconnects = this.budgeted_reconnects;
+        while let Some(&oldest) = reconnects.front() {
+            if now.saturating_duration_since(oldest) < window {
+                break;
+            }
+            reconnects.pop_front();
+        }
+        if reconnects.len() >= max as usize {
+            warn!(
+                "{}retry budget of {} reconnects per {:?} exhausted; closing the stream",
+                this.props.log_prefix(),
+                max,
+                window
+            );
+            return false;
+        }
+        reconnects.push_back(now);
+        true
+    }
+
+    fn send_request(&self) -> Result<ResponseFuture>
+    where
+        S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + Sync + 'static,
+        S::Error: Into<BoxError>,
+        S::Future: Send + Sync + 'static,
+    {
+        let request = self.build_request()?;
+        self.check_header_size(&request)?;
+        Ok(Box::pin(call_service(self.http.clone(), request)))
+    }
+
+    fn check_header_size<B>(&self, request: &Request<B>) -> Result<()> {
+        let max = match self.props.max_header_size {
+            Some(max) => max,
+            None => return Ok(()),
+        };
+        let size = header_size(request.headers());
+        if size > max {
+            warn!(
+                "{}not sending request: its headers are {} bytes, over the limit of {}",
+                self.props.log_prefix(),
+                size,
+                max
+            );
+            return Err(Error::HeadersTooLarge(size));
+        }
+        Ok(())
+    }
+
+    fn needs_preflight(&self) -> bool {
+        self.props.preflight.is_some() && self.preflight_headers.is_none()
+    }
+
+    fn build_preflight_request(&self, preflight: &Preflight) -> Result<Request<Body>> {
+        let mut request_builder = Request::builder()
+            .method(preflight.method.clone())
+            .uri(with_path(&self.props.url, &preflight.path)?);
+
+        for (name, value) in &self.props.headers {
+            request_builder = request_builder.header(name, value);
+        }
+
+        let mut request = request_builder
+            .body(Body::empty())
+            .map_err(|e| Error::InvalidParameter(Box::new(e)))?;
+
+        for set_extension in &self.props.extensions {
+            set_extension(request.extensions_mut());
+        }
+
+        #[cfg(feature = "cookies")]
+        if let Some(jar) = &self.props.cookie_jar {
+            let url = request.uri().clone();
+            jar.add_to_request(request.headers_mut(), &url, &self.props.url);
+        }
+
+        Ok(request)
+    }
+
+    /// Reset the event parser and send the stream's request, moving to `Connecting`. An event ID
+    /// that the old parser saw without an event is stored first, so that the request resumes
+    /// from it.
+    fn connect(mut self: Pin<&mut Self>) -> Result<()>
+    where
+        S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + Sync + 'static,
+        S::Error: Into<BoxError>,
+        S::Future: Send + Sync + 'static,
+    {
+        let parser = new_event_parser(&self.props);
+        let this = self.as_mut().project();
+        let mut old_parser = std::mem::replace(this.event_parser, parser);
+        this.event_parser.keep_seen_ids(&mut old_parser);
+        // `poll_next` yields every event the old parser has queued before it gets here, so that
+        // events from the old connection all come before those from the new one.
+        debug_assert!(old_parser.peek_event().is_none());
+        if let Some(id) = old_parser.take_undispatched_id() {
+            debug!(
+                "{}resuming from event id {:?}, set without an event",
+                this.props.log_prefix(),
+                id
+            );
+            store_last_event_id(this.props, this.last_event_id, id);
+        }
+        let resp = self.send_request()?;
+        let retry = self.props.reconnect_opts.retry_initial;
+        self.project().state.set(State::Connecting { resp, retry });
+        Ok(())
+    }
+
+    /// Start the initial connection on a spawned task, moving straight to `Connecting`. If the
+    /// request can't be built, the stream stays in `New` and reports the error when polled.
+    ///
+    /// The task is aborted if the stream is dropped before it completes, so that the attempt,
+    /// and the connection it holds, are released along with the stream.
+    fn connect_eagerly(mut self: Pin<&mut Self>)
+    where
+        S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + Sync + 'static,
+        S::Error: Into<BoxError>,
+        S::Future: Send + Sync + 'static,
+    {
+        if self.needs_preflight() || self.event_parser.peek_event().is_some() {
+            return;
+        }
+        let request = match self.build_request() {
+            Ok(request) if self.check_header_size(&request).is_ok() => request,
+            _ => return,
+        };
+
+        let handle = AbortOnDrop(tokio::spawn(call_service(self.http.clone(), request)));
+        let resp = handle.map(|joined| joined.unwrap_or_else(|e| Err(BoxError::from(e))));
+
+        self.as_mut().record_connect_attempt(Instant::now());
+        let retry = self.props.reconnect_opts.retry_initial;
+        self.project().state.set(State::Connecting {
+            resp: Box::pin(resp),
+            retry,
+        });
+    }
+
+    fn build_request(&self) -> Result<Request<Body>> {
+        let uri = match &self.props.cache_buster {
+            Some(name) => with_cache_buster(&self.current_url, name)?,
+            None => self.current_url.clone(),
+        };
+        let mut request_builder = Request::builder()
+            .method(self.props.method.as_str())
+            .uri(uri);
+
+        for (name, value) in &self.props.headers {
+            request_builder = request_builder.header(name, value);
+        }
+
+        if let Some(id) = self.last_event_id.as_ref() {
+            if !id.is_empty() {
+                let id_as_header =
+                    HeaderValue::from_str(id).map_err(|e| Error::InvalidParameter(Box::new(e)))?;
+
+                request_builder = request_builder.header("last-event-id", id_as_header);
+            }
+        }
+
+        if let Some((header, generate)) = &self.props.request_id {
+            let value = HeaderValue::from_str(&generate())
+                .map_err(|e| Error::InvalidParameter(Box::new(e)))?;
+            if let Some(headers) = request_builder.headers_mut() {
+                headers.insert(header, value);
+            }
+        }
+
+        if self.props.reconnect_attempt_header {
+            request_builder = request_builder.header(
+                RECONNECT_ATTEMPT_HEADER,
+                HeaderValue::from(self.reconnect_attempts),
+            );
+        }
+
+        let body = match &self.props.body {
+            Some(body) => Body::from(body.to_string()),
+            None => Body::empty(),
+        };
+
+        let mut request = request_builder
+            .body(body)
+            .map_err(|e| Error::InvalidParameter(Box::new(e)))?;
+
+        for set_extension in &self.props.extensions {
+            set_extension(request.extensions_mut());
+        }
+
+        if let Some(headers) = &self.preflight_headers {
+            request.headers_mut().extend(headers.clone());
+        }
+
+        #[cfg(feature = "cookies")]
+        if let Some(jar) = &self.props.cookie_jar {
+            jar.add_to_request(request.headers_mut(), &self.current_url, &self.props.url);
+        }
+
+        if let Some(before_send) = &self.props.before_send {
+            before_send(&mut request);
+        }
+
+        if self.props.keep_alive_header
+            && request.version() < hyper::Version::HTTP_2
+            && !request.headers().contains_key(hyper::header::CONNECTION)
+        {
+            request.headers_mut().insert(
+                hyper::header::CONNECTION,
+                HeaderValue::from_static("keep-alive"),
+            );
+        }
+
+        Ok(request)
+    }
+
+    fn next_reconnect_delay(mut self: Pin<&mut Self>) -> Duration {
+        self.as_mut().end_initial_reconnect_if_stable();
+        let this = self.project();
+        let duration = this.retry_strategy.next_delay(Instant::now());
+
+        if this.retry_strategy.at_max_delay() && !*this.at_max_backoff {
+            warn!(
+                "{}reconnect delay has reached its maximum of {:?}",
+                this.props.log_prefix(),
+                duration
+            );
+            *this.at_max_backoff = true;
+            if let Some(callback) = &this.props.on_backoff_change {
+                callback(BackoffEvent::ReachedMax);
+            }
+        }
+
+        duration
+    }
+
+    /// The delay before retrying a failed connection attempt, using the separate backoff for
+    /// resolution failures if one is configured and `dns_failure` is set.
+    fn next_retry_delay(mut self: Pin<&mut Self>, dns_failure: bool) -> Duration {
+        if dns_failure {
+            if let Some(strategy) = self.as_mut().project().dns_retry_strategy {
+                let duration = strategy.next_delay(Instant::now());
+                debug!(
+                    "{}host name couldn't be resolved, retrying in {:?}",
+                    self.props.log_prefix(),
+                    duration
+                );
+                return duration;
+            }
+        }
+        self.next_reconnect_delay()
+    }
+
+    /// Switch from the initial reconnect options to the steady ones, if the connection that just
+    /// ended was stable.
+    fn end_initial_reconnect_if_stable(mut self: Pin<&mut Self>) {
+        let this = self.as_mut().project();
+        let stable = this
+            .connected_since
+            .take()
+            .is_some_and(|since| since.elapsed() >= DEFAULT_RESET_RETRY_INTERVAL);
+        if !stable {
+            return;
+        }
+        if let Some(opts) = this.steady_reconnect_opts.take() {
+            info!(
+                "{}connection was stable; switching to the steady reconnect options",
+                this.props.log_prefix()
+            );
+            self.update_reconnect_opts(opts);
+        }
+    }
+
+    fn reset_backoff(self: Pin<&mut Self>) {
+        let this = self.project();
+        let now = Instant::now();
+        *this.connected_since = Some(now);
+        this.retry_strategy.reset(now);
+        if let Some(strategy) = this.dns_retry_strategy {
+            strategy.reset(now);
+        }
+        if this.props.reset_reconnect_attempts {
+            *this.reconnect_attempts = 0;
+        }
+        if let Some(breaker) = this.circuit_breaker {
+            if breaker.state() != CircuitState::Closed {
+                info!("{}circuit breaker closed", this.props.log_prefix());
+            }
+            breaker.record_success();
+        }
+
+        if *this.at_max_backoff {
+            info!(
+                "{}connected after reaching the maximum reconnect delay",
+                this.props.log_prefix()
+            );
+            *this.at_max_backoff = false;
+            if let Some(callback) = &this.props.on_backoff_change {
+                callback(BackoffEvent::Recovered);
+            }
+        }
+    }
+
+    fn schedule_reconnect(
+        mut self: Pin<&mut Self>,
+        reason: ReconnectReason,
+        duration: Duration,
+        description: &str,
+    ) {
+        let duration = match self.as_mut().record_reconnect(reason) {
+            Some(wait) => duration.max(wait),
+            None => duration,
+        };
+        self.wait_to_reconnect(duration, description);
+    }
+
+    /// Reconnect for `reason` when next polled, without a delay unless the circuit breaker
+    /// opens or a connection was lost and there is a reconnect grace.
+    fn reconnect_immediately(mut self: Pin<&mut Self>, reason: ReconnectReason) {
+        match self.as_mut().record_reconnect(reason) {
+            Some(wait) => self.wait_to_reconnect(wait, "reconnecting"),
+            None => self.project().state.set(State::New),
+        }
+    }
+
+    /// Record that the stream is reconnecting for `reason`, counting it as a failure for the
+    /// circuit breaker unless the client asked for it. Returns the minimum wait before the
+    /// next attempt, if there is one: the cooldown if the breaker opened, or the reconnect
+    /// grace if a connection was lost, whichever is longer.
+    fn record_reconnect(mut self: Pin<&mut Self>, reason: ReconnectReason) -> Option<Duration> {
+        self.as_mut().end_initial_reconnect_if_stable();
+        let this = self.project();
+        let connected = matches!(*this.state, State::Connected(_));
+        if let (Some(observer), true) = (&this.props.observer, connected) {
+            observer.on_disconnected(reason);
+        }
+        *this.last_reconnect_reason = Some(reason);
+        let grace = Some(this.props.reconnect_opts.reconnect_grace)
+            .filter(|grace| connected && !grace.is_zero());
+        if reason == ReconnectReason::ClientRequested {
+            return grace;
+        }
+        let cooldown = this
+            .circuit_breaker
+            .as_mut()
+            .and_then(|breaker| breaker.record_failure(Instant::now()));
+        if let Some(cooldown) = cooldown {
+            warn!(
+                "{}circuit breaker opened; pausing reconnects for {:?}",
+                this.props.log_prefix(),
+                cooldown
+            );
+        }
+        cooldown.max(grace)
+    }
+
+    fn wait_to_reconnect(self: Pin<&mut Self>, duration: Duration, description: &str) {
+        let sleep = delay(&self.props, duration, description);
+        if let Some(observer) = &self.props.observer {
+            observer.on_reconnect_scheduled(duration);
+        }
+        let mut this = self.project();
+        #[cfg(feature = "test-util")]
+        this.reconnect_log.push(ReconnectRecord {
+            delay: duration,
+            kind: if description == "retrying" {
+                ReconnectKind::Retry
+            } else {
+                ReconnectKind::Reconnect
+            },
+            attempt: *this.reconnect_attempts + 1,
+        });
+        this.state.set(State::WaitingToReconnect(sleep));
+    }
+
+    /// How much longer to wait before a connection attempt may be made at `now`, if any, to
+    /// honour the configured minimum reconnect interval. Redirects are followed without waiting.
+    fn time_until_next_attempt(&self, now: Instant) -> Option<Duration> {
+        if self.redirect_count != 0 {
+            return None;
+        }
+        let last_attempt = self.last_connect_attempt?;
+        let interval = self.props.reconnect_opts.min_reconnect_interval;
+        match last_attempt.checked_add(interval) {
+            Some(earliest) => earliest
+                .checked_duration_since(now)
+                .filter(|d| !d.is_zero()),
+            None => Some(interval),
+        }
+    }
+
+    fn apply_error_action(
+        mut self: Pin<&mut Self>,
+        action: ErrorAction,
+        error: Error,
+    ) -> Poll<Option<Result<SSE>>> {
+        debug!(
+            "{}handling error {:?} with {:?}",
+            self.props.log_prefix(),
+            error,
+            action
+        );
+        match action {
+            ErrorAction::Reconnect => {
+                let dns_failure = matches!(&error, Error::HttpStream(e) if is_dns_error(&**e));
+                let duration = self.as_mut().next_retry_delay(dns_failure);
+                let reason = ReconnectReason::for_error(&error);
+                self.schedule_reconnect(reason, duration, "reconnecting");
+                Poll::Ready(Some(Err(error)))
+            }
+            ErrorAction::Fail => {
+                self.project().state.set(State::StreamClosed);
+                Poll::Ready(Some(Err(error)))
+            }
+            ErrorAction::End => {
+                self.project().state.set(State::StreamClosed);
+                Poll::Ready(None)
+            }
+        }
+    }
+
+    fn reset_redirects(self: Pin<&mut Self>) {
+        let url = self.sticky_url.as_ref().unwrap_or(&self.props.url).clone();
+        let this = self.project();
+        *this.current_url = url;
+        *this.redirect_count = 0;
+    }
+
+    /// Stick to the node named in `headers` by the configured sticky node header, if any.
+    fn update_sticky_node(self: Pin<&mut Self>, headers: &HeaderMap) {
+        let value = match &self.props.sticky_node_header {
+            Some(name) => match headers.get(name) {
+                Some(value) => value,
+                None => return,
+            },
+            None => return,
+        };
+        match sticky_node_url(&self.current_url, value) {
+            Ok(url) => {
+                if self.sticky_url.as_ref() != Some(&url) {
+                    info!("{}sticking to node {}", self.props.log_prefix(), url);
+                }
+                *self.project().sticky_url = Some(url);
+            }
+            Err(e) => warn!(
+                "{}ignoring invalid sticky node {:?}: {:?}",
+                self.props.log_prefix(),
+                value,
+                e
+            ),
+        }
+    }
+
+    /// Go back to the original URL after a connection to the sticky node failed.
+    fn forget_sticky_node(mut self: Pin<&mut Self>) {
+        if self.as_mut().project().sticky_url.take().is_some() {
+            info!(
+                "{}forgetting sticky node after a failed connection",
+                self.props.log_prefix()
+            );
+            self.reset_redirects();
+        }
+    }
+
+    fn increment_redirect_counter(self: Pin<&mut Self>) -> bool {
+        if self.redirect_count == self.props.max_redirects {
             return false;
         }
-        *self.project().redirect_count += 1;
-        true
+        *self.project().redirect_count += 1;
+        true
+    }
+}
+
+impl<S> Stream for ReconnectingRequest<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + Sync + 'static,
+    S::Error: Into<BoxError>,
+    S::Future: Send + Sync + 'static,
+{
+    type Item = Result<SSE>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        // Items received by `wait_for_connection` are yielded first; they were already counted
+        // and observed.
+        if !self.holding_items {
+            if let Some(item) = self.as_mut().project().held_items.pop_front() {
+                return Poll::Ready(Some(Ok(item)));
+            }
+        }
+        let item = ready!(self.as_mut().poll_stream(cx));
+        if let Some(observer) = &self.props.observer {
+            match &item {
+                Some(Ok(SSE::Event(event))) => observer.on_event(event),
+                Some(Err(error)) => observer.on_error(error),
+                _ => {}
+            }
+        }
+        Poll::Ready(item)
+    }
+}
+
+impl<S> ReconnectingRequest<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + Sync + 'static,
+    S::Error: Into<BoxError>,
+    S::Future: Send + Sync + 'static,
+{
+    fn poll_stream(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<SSE>>> {
+        trace!(
+            "{}ReconnectingRequest::poll({:?})",
+            self.props.log_prefix(),
+            &self.state
+        );
+        // An event held back by the rate limiter has already been handled, and may be the last
+        // one allowed; deliver it once its wait is over.
+        let this = self.as_mut().project();
+        if let Some((wait, _)) = this.rate_limit_wait.as_mut() {
+            ready!(wait.as_mut().poll(cx));
+            if let Some((_, event)) = this.rate_limit_wait.take() {
+                return Poll::Ready(Some(Ok(event)));
+            }
+        }
+        if self.events_remaining == Some(0) {
+            return Poll::Ready(None);
+        }
+        self.reconnect_signal.waker.register(cx.waker());
+
+        loop {
+            let mut this = self.as_mut().project();
+            if let Some(mut event) = this.event_parser.get_event() {
+                if this.read_deadline.is_some()
+                    && resets_read_timeout(this.props.read_timeout_policy, &event)
+                {
+                    *this.read_deadline =
+                        this.props.read_timeout.map(|t| this.props.timer.sleep(t));
+                }
+
+                return match event {
+                    SSE::Event(ref mut evt) => {
+                        if this.props.skip_consecutive_duplicates {
+                            let is_duplicate = this.last_received.as_ref().is_some_and(|last| {
+                                last.event_type == evt.event_type
+                                    && last.id == evt.id
+                                    && last.data == evt.data
+                            });
+                            if is_duplicate {
+                                debug!(
+                                    "{}skipping repeated {:?} event",
+                                    this.props.log_prefix(),
+                                    evt.event_type
+                                );
+                                continue;
+                            }
+                            *this.last_received = Some(evt.clone());
+                        }
+
+                        *this.first_event_deadline = None;
+                        this.event_rate.record(Instant::now());
+                        if this.time_to_first_event.is_none() {
+                            *this.time_to_first_event =
+                                this.first_connect_attempt.map(|start| start.elapsed());
+                        }
+
+                        let is_reset = this.props.is_event_type(&this.props.reset_event_type, evt);
+                        if is_reset {
+                            debug!(
+                                "{}received {:?} event, clearing last event id",
+                                this.props.log_prefix(),
+                                evt.event_type
+                            );
+                            *this.last_event_id = None;
+                        } else {
+                            store_last_event_id(this.props, this.last_event_id, evt.id.clone());
+                        }
+
+                        if let Some(retry) = evt.retry {
+                            if let Some(delay) = this.props.reconnect_opts.server_retry_delay(retry)
+                            {
+                                this.retry_strategy.change_base_delay(delay);
+                            }
+                        }
+
+                        #[cfg(feature = "data-encoding")]
+                        if this.props.data_encoding != DataEncoding::Raw {
+                            match this.props.data_encoding.decode(&evt.data) {
+                                Ok(data) => evt.data = data,
+                                Err(e) => {
+                                    warn!(
+                                        "{}failed to decode event data: {}",
+                                        this.props.log_prefix(),
+                                        e
+                                    );
+                                    return Poll::Ready(Some(Err(Error::InvalidDataEncoding(e))));
+                                }
+                            }
+                        }
+
+                        if let Some((transform, action)) = &this.props.transform_data {
+                            let data = std::mem::take(&mut evt.data).into_bytes();
+                            match transform(data).and_then(|data| Ok(String::from_utf8(data)?)) {
+                                Ok(data) => evt.data = data,
+                                Err(e) => {
+                                    warn!(
+                                        "{}failed to transform event data: {}",
+                                        this.props.log_prefix(),
+                                        e
+                                    );
+                                    let action = *action;
+                                    return self
+                                        .as_mut()
+                                        .apply_error_action(action, Error::TransformFailed(e));
+                                }
+                            }
+                        }
+
+                        if this.props.is_event_type(&this.props.error_event_type, evt) {
+                            return Poll::Ready(Some(Err(Error::ServerEvent(evt.data.clone()))));
+                        }
+
+                        if let Some((expected, policy)) = &this.props.expected_event_types {
+                            let is_expected = expected.iter().any(|t| {
+                                event_type_matches(
+                                    t,
+                                    &evt.event_type,
+                                    this.props.case_insensitive_event_types,
+                                )
+                            });
+                            if !is_reset && !is_expected {
+                                warn!(
+                                    "{}received event of unexpected type {:?}",
+                                    this.props.log_prefix(),
+                                    evt.event_type
+                                );
+                                *this.unexpected_event_types += 1;
+                                if *policy == EventTypePolicy::Error {
+                                    return Poll::Ready(Some(Err(Error::UnexpectedEventType(
+                                        evt.event_type.clone(),
+                                    ))));
+                                }
+                            }
+                        }
+                        if reached_max_events(this.props, this.events_remaining) {
+                            this.state.set(State::StreamClosed);
+                        }
+                        // Only events that are delivered take a token.
+                        if let Some(limiter) = this.rate_limiter.as_mut() {
+                            if let Some(wait) = limiter.acquire(Instant::now()) {
+                                trace!(
+                                    "{}rate limit reached, delaying event by {:?}",
+                                    this.props.log_prefix(),
+                                    wait
+                                );
+                                let sleep = this.props.timer.sleep(wait);
+                                *this.rate_limit_wait = Some((sleep, event));
+                                return self.poll_stream(cx);
+                            }
+                        }
+                        Poll::Ready(Some(Ok(event)))
+                    }
+                    _ => Poll::Ready(Some(Ok(event))),
+                };
+            }
+
+            if *this.shutting_down {
+                return Poll::Ready(None);
+            }
+
+            if matches!(
+                *this.state,
+                State::Connected(_) | State::WaitingToReconnect(_)
+            ) && this
+                .reconnect_signal
+                .requested
+                .swap(false, Ordering::SeqCst)
+            {
+                info!("{}reconnecting on request", self.props.log_prefix());
+                self.as_mut()
+                    .reconnect_immediately(ReconnectReason::ClientRequested);
+                continue;
+            }
+
+            trace!(
+                "{}ReconnectingRequest::poll loop({:?})",
+                this.props.log_prefix(),
+                &this.state
+            );
+
+            let state = this.state.project();
+            match state {
+                StateProj::StreamClosed => return Poll::Ready(Some(Err(Error::StreamClosed))),
+                // New immediately transitions to Connecting, and exists only
+                // to ensure that we only connect when polled.
+                StateProj::New => {
+                    let now = Instant::now();
+                    if let Some(wait) = self.time_until_next_attempt(now) {
+                        // Not a reconnect of its own, so it isn't reported as one.
+                        debug!(
+                            "{}waiting {:?} for the minimum reconnect interval",
+                            self.props.log_prefix(),
+                            wait
+                        );
+                        let sleep = self.props.timer.sleep(wait);
+                        self.as_mut()
+                            .project()
+                            .state
+                            .set(State::WaitingToReconnect(sleep));
+                        continue;
+                    }
+
+                    if self.props.emit_resuming && self.redirect_count == 0 {
+                        // Announce the attempt, then make it when next polled.
+                        let this = self.as_mut().project();
+                        if !*this.resuming_announced {
+                            *this.resuming_announced = true;
+                            let last_event_id = this.last_event_id.clone();
+                            return Poll::Ready(Some(Ok(SSE::Resuming { last_event_id })));
+                        }
+                        *this.resuming_announced = false;
+                    }
+
+                    if !self.as_mut().spend_retry_budget(now) {
+                        self.as_mut().project().state.set(State::StreamClosed);
+                        return Poll::Ready(Some(Err(Error::RetryBudgetExhausted)));
+                    }
+                    self.as_mut().record_connect_attempt(now);
+
+                    if let Some(preflight) = self.props.preflight.clone() {
+                        if self.needs_preflight() {
+                            info!(
+                                "{}sending preflight {} {}",
+                                self.props.log_prefix(),
+                                preflight.method,
+                                preflight.path
+                            );
+                            match self.build_preflight_request(&preflight) {
+                                Ok(request) => {
+                                    let resp = Box::pin(call_service(self.http.clone(), request));
+                                    self.as_mut().project().state.set(State::Preflighting(resp));
+                                    continue;
+                                }
+                                Err(e) => {
+                                    self.as_mut().project().state.set(State::StreamClosed);
+                                    return Poll::Ready(Some(Err(e)));
+                                }
+                            }
+                        }
+                    }
+
+                    if let Err(e) = self.as_mut().connect() {
+                        // This error seems to be unrecoverable. So we should just shut down the
+                        // stream.
+                        self.as_mut().project().state.set(State::StreamClosed);
+                        return Poll::Ready(Some(Err(e)));
+                    }
+                }
+                StateProj::Preflighting(resp) => {
+                    let resp = ready!(resp.poll(cx));
+                    #[cfg(feature = "cookies")]
+                    if let (Ok(resp), Some(jar), Some(preflight)) =
+                        (&resp, &self.props.cookie_jar, &self.props.preflight)
+                    {
+                        if let Ok(url) = with_path(&self.props.url, &preflight.path) {
+                            jar.store_response(resp.headers(), &url);
+                        }
+                    }
+                    let error = match resp {
+                        Ok(resp) if resp.status().is_success() => {
+                            debug!("{}preflight response: {:#?}", self.props.log_prefix(), resp);
+                            let mut headers = HeaderMap::new();
+                            if let Some(preflight) = &self.props.preflight {
+                                (preflight.on_response)(&resp, &mut headers);
+                            }
+                            *self.as_mut().project().preflight_headers = Some(headers);
+                            match self.as_mut().connect() {
+                                Ok(()) => continue,
+                                Err(e) => e,
+                            }
+                        }
+                        Ok(resp) => Error::PreflightRejected(resp.status()),
+                        Err(e) => Error::PreflightFailed(e),
+                    };
+                    warn!("{}preflight failed: {:?}", self.props.log_prefix(), error);
+                    self.as_mut().project().state.set(State::StreamClosed);
+                    return Poll::Ready(Some(Err(error)));
+                }
+                StateProj::Connecting { retry, resp } => match ready!(resp.poll(cx)) {
+                    Ok(resp) => {
+                        debug!("{}HTTP response: {:#?}", self.props.log_prefix(), resp);
+                        #[cfg(feature = "cookies")]
+                        if let Some(jar) = &self.props.cookie_jar {
+                            jar.store_response(resp.headers(), &self.current_url);
+                        }
+
+                        // The SSE specification has servers send 204 No Content to tell the
+                        // client to stop reconnecting.
+                        if resp.status() == StatusCode::NO_CONTENT {
+                            info!(
+                                "{}server responded with 204 No Content, closing",
+                                self.props.log_prefix()
+                            );
+                            *self.as_mut().project().last_status = Some(resp.status());
+                            self.as_mut().reset_redirects();
+                            self.as_mut().project().state.set(State::StreamClosed);
+                            return Poll::Ready(None);
+                        }
+
+                        if self.props.reconnect_opts.accepts_status(resp.status()) {
+                            *self.as_mut().project().last_status = Some(resp.status());
+                            let policy = self.props.content_type_policy;
+                            if policy != ContentTypePolicy::Ignore && !is_event_stream(&resp) {
+                                let content_type =
+                                    resp.headers().get(hyper::header::CONTENT_TYPE).cloned();
+                                warn!(
+                                    "{}unexpected content type: {:?}",
+                                    self.props.log_prefix(),
+                                    content_type
+                                );
+
+                                self.as_mut().reset_redirects();
+                                if policy == ContentTypePolicy::Reconnect
+                                    && self.props.reconnect_opts.reconnect
+                                {
+                                    let duration = self.as_mut().next_reconnect_delay();
+                                    self.as_mut().schedule_reconnect(
+                                        ReconnectReason::ServerRetry,
+                                        duration,
+                                        "reconnecting",
+                                    );
+                                } else {
+                                    self.as_mut().project().state.set(State::StreamClosed);
+                                }
+                                return Poll::Ready(Some(Err(Error::InvalidContentType(
+                                    content_type,
+                                ))));
+                            }
+
+                            self.as_mut().update_sticky_node(resp.headers());
+                            self.as_mut().reset_backoff();
+                            self.as_mut().reset_redirects();
+                            let deadline = self
+                                .props
+                                .first_event_timeout
+                                .map(|timeout| self.props.timer.sleep(timeout));
+                            *self.as_mut().project().first_event_deadline = deadline;
+                            let deadline = match self.props.read_timeout_policy {
+                                ReadTimeoutPolicy::AnyBytes => None,
+                                _ => self
+                                    .props
+                                    .read_timeout
+                                    .map(|timeout| self.props.timer.sleep(timeout)),
+                            };
+                            *self.as_mut().project().read_deadline = deadline;
+                            let heartbeat = self
+                                .props
+                                .heartbeat_interval
+                                .map(|interval| self.props.timer.sleep(interval));
+                            *self.as_mut().project().heartbeat = heartbeat;
+                            *self.as_mut().project().connection_bytes = 0;
+                            *self.as_mut().project().eof_dispatched = false;
+                            let (parts, body) = resp.into_parts();
+                            if let Some(observer) = &self.props.observer {
+                                observer.on_connected(parts.status, &parts.headers);
+                            }
+                            if let Some(on_response) = &self.props.on_response {
+                                on_response(&Response::from_parts(parts, ()));
+                            }
+                            self.as_mut().project().state.set(State::Connected(body));
+                            continue;
+                        }
+
+                        if resp.status().is_redirection()
+                            && !resp.headers().contains_key(hyper::header::LOCATION)
+                        {
+                            // Retrying won't produce a Location to follow, so give up.
+                            warn!(
+                                "{}redirect ({}) without a Location header",
+                                self.props.log_prefix(),
+                                resp.status()
+                            );
+                            self.as_mut().project().state.set(State::StreamClosed);
+                            return Poll::Ready(Some(Err(Error::RedirectWithoutLocation(
+                                resp.status(),
+                            ))));
+                        }
+
+                        if resp.status() == 301 || resp.status() == 307 {
+                            debug!(
+                                "{}got redirected ({})",
+                                self.props.log_prefix(),
+                                resp.status()
+                            );
+
+                            if self.as_mut().increment_redirect_counter() {
+                                debug!(
+                                    "{}following redirect {}",
+                                    self.props.log_prefix(),
+                                    self.redirect_count
+                                );
+
+                                self.as_mut().project().state.set(State::FollowingRedirect(
+                                    resp.headers().get(hyper::header::LOCATION).cloned(),
+                                ));
+                                continue;
+                            } else {
+                                debug!(
+                                    "{}redirect limit reached ({})",
+                                    self.props.log_prefix(),
+                                    self.props.max_redirects
+                                );
+
+                                self.as_mut().project().state.set(State::StreamClosed);
+                                return Poll::Ready(Some(Err(Error::MaxRedirectLimitReached(
+                                    self.props.max_redirects,
+                                ))));
+                            }
+                        }
+
+                        self.as_mut().forget_sticky_node();
+                        self.as_mut().reset_redirects();
+                        self.as_mut()
+                            .reconnect_immediately(ReconnectReason::ServerRetry);
+                        return Poll::Ready(Some(Err(Error::UnexpectedResponse(resp.status()))));
+                    }
+                    Err(e) => {
+                        // This seems basically impossible. AFAIK we can only get this way if we
+                        // poll after it was already ready
+                        warn!(
+                            "{}request returned an error: {}",
+                            this.props.log_prefix(),
+                            e
+                        );
+                        let retry = *retry;
+                        self.as_mut().forget_sticky_node();
+                        if let Some(on_error) = self.props.on_error.clone() {
+                            let error = Error::HttpStream(e);
+                            let action = on_error(&error);
+                            return self.as_mut().apply_error_action(action, error);
+                        }
+                        if !retry {
+                            self.as_mut()
+                                .reconnect_immediately(ReconnectReason::TransportError);
+                            return Poll::Ready(Some(Err(Error::HttpStream(e))));
+                        }
+                        let duration = self.as_mut().next_retry_delay(is_dns_error(&*e));
+                        self.as_mut().schedule_reconnect(
+                            ReconnectReason::TransportError,
+                            duration,
+                            "retrying",
+                        )
+                    }
+                },
+                StateProj::FollowingRedirect(maybe_header) => match uri_from_header(maybe_header) {
+                    Ok(uri) => {
+                        *self.as_mut().project().current_url = uri;
+                        self.as_mut().project().state.set(State::New);
+                    }
+                    Err(e) => {
+                        self.as_mut().project().state.set(State::StreamClosed);
+                        return Poll::Ready(Some(Err(e)));
+                    }
+                },
+                StateProj::Connected(body) => {
+                    let timed_out = match this.first_event_deadline.as_mut() {
+                        Some(deadline) => deadline.as_mut().poll(cx).is_ready(),
+                        None => false,
+                    };
+                    if timed_out {
+                        warn!(
+                            "{}no event received within the first event timeout",
+                            self.props.log_prefix()
+                        );
+                        *self.as_mut().project().first_event_deadline = None;
+                        if let Some(on_error) = self.props.on_error.clone() {
+                            let error = Error::FirstEventTimeout;
+                            let action = on_error(&error);
+                            return self.as_mut().apply_error_action(action, error);
+                        }
+
+                        if self.props.reconnect_opts.reconnect {
+                            let duration = self.as_mut().next_reconnect_delay();
+                            self.as_mut().schedule_reconnect(
+                                ReconnectReason::Timeout,
+                                duration,
+                                "reconnecting",
+                            );
+                        } else {
+                            self.as_mut().project().state.set(State::StreamClosed);
+                        }
+                        return Poll::Ready(Some(Err(Error::FirstEventTimeout)));
+                    }
+
+                    let timed_out = match this.read_deadline.as_mut() {
+                        Some(deadline) => deadline.as_mut().poll(cx).is_ready(),
+                        None => false,
+                    };
+                    if timed_out {
+                        warn!(
+                            "{}no {} received within the read timeout",
+                            self.props.log_prefix(),
+                            match self.props.read_timeout_policy {
+                                ReadTimeoutPolicy::EventsAndComments => "event or comment",
+                                _ => "event",
+                            }
+                        );
+                        *self.as_mut().project().read_deadline = None;
+                        if let Some(on_error) = self.props.on_error.clone() {
+                            let error = Error::TimedOut;
+                            let action = on_error(&error);
+                            return self.as_mut().apply_error_action(action, error);
+                        }
+
+                        if self.props.reconnect_opts.reconnect {
+                            let duration = self.as_mut().next_reconnect_delay();
+                            self.as_mut().schedule_reconnect(
+                                ReconnectReason::Timeout,
+                                duration,
+                                "reconnecting",
+                            );
+                        } else {
+                            self.as_mut().project().state.set(State::StreamClosed);
+                        }
+                        return Poll::Ready(Some(Err(Error::TimedOut)));
+                    }
+
+                    let beat = match this.heartbeat.as_mut() {
+                        Some(heartbeat) => heartbeat.as_mut().poll(cx).is_ready(),
+                        None => false,
+                    };
+                    if beat {
+                        let next = self
+                            .props
+                            .heartbeat_interval
+                            .map(|interval| self.props.timer.sleep(interval));
+                        *self.as_mut().project().heartbeat = next;
+                        return Poll::Ready(Some(Ok(SSE::Heartbeat)));
+                    }
+
+                    match ready!(body.poll_data(cx)) {
+                        Some(Ok(result)) if result.is_empty() => {
+                            // HTTP/2 servers may send empty DATA frames, for example as
+                            // keep-alives. There is nothing to parse, and the frame arriving on
+                            // the connection has already reset the read timeout.
+                            trace!("{}ignoring empty data frame", self.props.log_prefix());
+                            continue;
+                        }
+                        Some(Ok(result)) => {
+                            *this.connection_bytes += result.len() as u64;
+                            if let Some((max, action)) = this.props.max_bytes_per_connection {
+                                if *this.connection_bytes > max {
+                                    warn!(
+                                        "{}connection exceeded {} bytes",
+                                        self.props.log_prefix(),
+                                        max
+                                    );
+                                    return self.as_mut().apply_error_action(
+                                        action,
+                                        Error::ConnectionByteLimit(max),
+                                    );
+                                }
+                            }
+                            match this.event_parser.process_bytes(result) {
+                                Ok(()) => continue,
+                                Err(error @ Error::InvalidLine(_)) => {
+                                    // The rest of the connection can't be trusted to line up
+                                    // with the stream's lines, so it is dropped.
+                                    let action = match &self.props.on_error {
+                                        Some(on_error) => on_error(&error),
+                                        None if self.props.reconnect_opts.reconnect => {
+                                            ErrorAction::Reconnect
+                                        }
+                                        None => ErrorAction::Fail,
+                                    };
+                                    return self.as_mut().apply_error_action(action, error);
+                                }
+                                Err(error) => return Poll::Ready(Some(Err(error))),
+                            }
+                        }
+                        Some(Err(e)) if is_go_away(&e) => {
+                            // A GOAWAY is the server draining the connection, not a failure, so we
+                            // always reconnect and don't escalate the backoff.
+                            info!(
+                                "{}server sent GOAWAY, reconnecting",
+                                self.props.log_prefix()
+                            );
+                            let duration = self.props.reconnect_opts.delay;
+                            self.as_mut().schedule_reconnect(
+                                ReconnectReason::ServerRetry,
+                                duration,
+                                "reconnecting",
+                            );
+                        }
+                        Some(Err(e)) => {
+                            if let Some(on_error) = self.props.on_error.clone() {
+                                let error = body_error(e);
+                                let action = on_error(&error);
+                                return self.as_mut().apply_error_action(action, error);
+                            }
+
+                            if self.props.reconnect_opts.reconnect {
+                                let duration = self.as_mut().next_reconnect_delay();
+                                let reason = if is_timeout(&e) {
+                                    ReconnectReason::Timeout
+                                } else {
+                                    ReconnectReason::TransportError
+                                };
+                                self.as_mut()
+                                    .schedule_reconnect(reason, duration, "reconnecting");
+                            }
+
+                            if let Some(cause) = e.source() {
+                                if let Some(downcast) = cause.downcast_ref::<std::io::Error>() {
+                                    if let std::io::ErrorKind::TimedOut = downcast.kind() {
+                                        return Poll::Ready(Some(Err(Error::TimedOut)));
+                                    }
+                                }
+                            } else {
+                                return Poll::Ready(Some(Err(Error::HttpStream(Box::new(e)))));
+                            }
+                        }
+                        None => {
+                            if self.props.dispatch_on_eof && !self.eof_dispatched {
+                                // Deliver the event first; the body is polled again, and
+                                // reports its end again, once the event has been consumed.
+                                let this = self.as_mut().project();
+                                *this.eof_dispatched = true;
+                                this.event_parser.flush()?;
+                                continue;
+                            }
+
+                            if self.props.error_on_unexpected_eof
+                                && !self.props.reconnect_opts.reconnect
+                            {
+                                info!(
+                                    "{}server ended the stream, closing",
+                                    self.props.log_prefix()
+                                );
+                                self.as_mut().project().state.set(State::StreamClosed);
+                            } else {
+                                let duration = self.as_mut().next_reconnect_delay();
+                                self.as_mut().schedule_reconnect(
+                                    ReconnectReason::ServerClosed,
+                                    duration,
+                                    "retrying",
+                                );
+                            }
+
+                            if self.event_parser.was_processing() {
+                                return Poll::Ready(Some(Err(Error::UnexpectedEof)));
+                            }
+                            return Poll::Ready(Some(Err(Error::Eof)));
+                        }
+                    }
+                }
+                StateProj::WaitingToReconnect(delay) => {
+                    ready!(delay.poll(cx));
+                    info!("{}Reconnecting", self.props.log_prefix());
+                    self.as_mut().project().state.set(State::New);
+                }
+            };
+        }
+    }
+}
+
+/// An HTTPS connector using the OS root certificate store, negotiating at least `min_version`.
+#[cfg(feature = "rustls")]
+fn https_connector(min_version: TlsVersion) -> HttpsConnector {
+    if min_version == TlsVersion::default() {
+        return HttpsConnector::with_native_roots();
+    }
+
+    // Set up like `HttpsConnector::with_native_roots`, which doesn't expose its config.
+    let mut config = ClientConfig::new();
+    config.root_store = match rustls_native_certs::load_native_certs() {
+        Ok(store) => store,
+        Err((Some(store), err)) => {
+            warn!("Could not load all certificates: {:?}", err);
+            store
+        }
+        Err((None, err)) => panic!("cannot access native cert store: {:?}", err),
+    };
+    if config.root_store.is_empty() {
+        panic!("no CA certificates found");
+    }
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    config.ct_logs = Some(&ct_logs::LOGS);
+    config.versions = tls_versions(min_version);
+
+    let mut http = HttpConnector::new();
+    http.enforce_http(false);
+    (http, config).into()
+}
+
+/// The protocol versions to offer for a minimum of `min_version`, newest first.
+#[cfg(feature = "rustls")]
+fn tls_versions(min_version: TlsVersion) -> Vec<ProtocolVersion> {
+    [
+        (TlsVersion::Tls13, ProtocolVersion::TLSv1_3),
+        (TlsVersion::Tls12, ProtocolVersion::TLSv1_2),
+    ]
+    .into_iter()
+    .filter(|(version, _)| *version >= min_version)
+    .map(|(_, protocol)| protocol)
+    .collect()
+}
+
+/// A spawned task that is aborted when its handle is dropped, rather than detached.
+pub(crate) struct AbortOnDrop<T>(pub(crate) tokio::task::JoinHandle<T>);
+
+impl<T> Future for AbortOnDrop<T> {
+    type Output = std::result::Result<T, tokio::task::JoinError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.0).poll(cx)
+    }
+}
+
+impl<T> Drop for AbortOnDrop<T> {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Send `request` on `service` once it is ready.
+async fn call_service<S>(
+    mut service: S,
+    request: Request<Body>,
+) -> std::result::Result<Response<Body>, BoxError>
+where
+    S: Service<Request<Body>, Response = Response<Body>>,
+    S::Error: Into<BoxError>,
+{
+    futures::future::poll_fn(|cx| service.poll_ready(cx))
+        .await
+        .map_err(Into::into)?;
+    service.call(request).await.map_err(Into::into)
+}
+
+/// Whether `err` was caused by the connection's read timing out.
+fn is_timeout(err: &hyper::Error) -> bool {
+    err.source()
+        .and_then(|cause| cause.downcast_ref::<std::io::Error>())
+        .map(|io_err| io_err.kind() == ErrorKind::TimedOut)
+        .unwrap_or(false)
+}
+
+/// Convert an error reading the response body into the error reported to the stream.
+fn body_error(err: hyper::Error) -> Error {
+    if is_timeout(&err) {
+        Error::TimedOut
+    } else {
+        Error::HttpStream(Box::new(err))
+    }
+}
+
+/// Whether `event` resets a read timeout that follows `policy`.
+fn resets_read_timeout(policy: ReadTimeoutPolicy, event: &SSE) -> bool {
+    match event {
+        SSE::Event(_) => true,
+        SSE::Comment(_) => policy != ReadTimeoutPolicy::EventsOnly,
+        _ => policy == ReadTimeoutPolicy::AnyBytes,
+    }
+}
+
+fn retry_strategy(opts: &ReconnectOptions) -> Box<dyn RetryStrategy + Send + Sync> {
+    match opts.strategy {
+        ReconnectStrategy::Exponential => Box::new(BackoffRetry::new(
+            opts.delay,
+            opts.delay_max,
+            opts.backoff_factor,
+            true,
+        )),
+        ReconnectStrategy::Adaptive => Box::new(AdaptiveRetry::new(
+            opts.delay,
+            opts.delay_max,
+            opts.backoff_factor,
+            true,
+        )),
+    }
+}
+
+fn dns_retry_strategy(opts: &ReconnectOptions) -> Option<Box<dyn RetryStrategy + Send + Sync>> {
+    let (delay, delay_max) = opts.dns_delays()?;
+    Some(Box::new(BackoffRetry::new(
+        delay,
+        delay_max,
+        opts.backoff_factor,
+        true,
+    )))
+}
+
+/// Whether `err` was caused by a failure to resolve the server's host name.
+///
+/// hyper doesn't expose a dedicated error type for this, so it is recognized by the shape of a
+/// connect error: one caused by an I/O error without an OS error code, which is how resolvers,
+/// including hyper's default one, report a failed lookup, whereas a failed TCP connection
+/// reports the OS's error code. Only if the connect error has no I/O error among its causes,
+/// as can happen with a custom connector, is its message checked for the `dns error` that
+/// hyper's connector reports instead.
+fn is_dns_error(err: &(dyn StdError + 'static)) -> bool {
+    let connect_error = causes(err).find(|cause| {
+        cause
+            .downcast_ref::<hyper::Error>()
+            .is_some_and(hyper::Error::is_connect)
+    });
+    let connect_error = match connect_error {
+        Some(connect_error) => connect_error,
+        None => return false,
+    };
+
+    match causes(connect_error).find_map(|cause| cause.downcast_ref::<std::io::Error>()) {
+        Some(io_err) => {
+            io_err.raw_os_error().is_none()
+                && !matches!(io_err.kind(), ErrorKind::TimedOut | ErrorKind::Interrupted)
+        }
+        None => causes(connect_error).any(|cause| cause.to_string().starts_with("dns error")),
+    }
+}
+
+/// `err` followed by the chain of errors that caused it.
+fn causes<'a>(
+    err: &'a (dyn StdError + 'static),
+) -> impl Iterator<Item = &'a (dyn StdError + 'static)> {
+    std::iter::successors(Some(err), |&cause| cause.source())
+}
+
+#[cfg(feature = "http2")]
+fn is_go_away(err: &hyper::Error) -> bool {
+    let mut source = err.source();
+    while let Some(cause) = source {
+        if let Some(h2_err) = cause.downcast_ref::<h2::Error>() {
+            return h2_err.is_go_away() && h2_err.is_remote();
+        }
+        source = cause.source();
+    }
+    false
+}
+
+#[cfg(not(feature = "http2"))]
+fn is_go_away(_err: &hyper::Error) -> bool {
+    false
+}
+
+fn parse_header(name: &str, value: &str) -> Result<(HeaderName, HeaderValue)> {
+    let name = HeaderName::from_str(name).map_err(|e| Error::InvalidParameter(Box::new(e)))?;
+
+    let value = HeaderValue::from_str(value).map_err(|e| Error::InvalidParameter(Box::new(e)))?;
+
+    Ok((name, value))
+}
+
+/// The size of `headers` as sent over HTTP/1.1: each header's name and value, plus 4 bytes for
+/// the `: ` between them and the line ending.
+fn header_size(headers: &HeaderMap) -> usize {
+    headers
+        .iter()
+        .map(|(name, value)| name.as_str().len() + value.len() + 4)
+        .sum()
+}
+
+/// The URL of the sticky node named by `value`: an absolute URL, or a path and query on the host
+/// of `current`.
+fn sticky_node_url(current: &Uri, value: &HeaderValue) -> Result<Uri> {
+    let value = value
+        .to_str()
+        .map_err(|e| Error::InvalidParameter(Box::new(e)))?;
+    if value.starts_with('/') {
+        return with_path(current, value);
+    }
+    let url = value
+        .parse::<Uri>()
+        .map_err(|e| Error::InvalidParameter(Box::new(e)))?;
+    if url.scheme().is_none() || url.authority().is_none() {
+        return Err(Error::InvalidParameter(Box::new(std::io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("not an absolute URL or path: {:?}", value),
+        ))));
+    }
+    Ok(url)
+}
+
+/// Return `url` with its path and query replaced by `path`.
+fn with_path(url: &Uri, path: &str) -> Result<Uri> {
+    if !path.starts_with('/') {
+        return Err(Error::InvalidParameter(Box::new(std::io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("invalid path {:?}: expected it to start with '/'", path),
+        ))));
+    }
+
+    let mut parts = url.clone().into_parts();
+    parts.path_and_query =
+        Some(PathAndQuery::from_str(path).map_err(|e| Error::InvalidParameter(Box::new(e)))?);
+    Uri::from_parts(parts).map_err(|e| Error::InvalidParameter(Box::new(e)))
+}
+
+/// `url` with a `name` query parameter holding a value unique to this call appended to its
+/// query.
+fn with_cache_buster(url: &Uri, name: &str) -> Result<Uri> {
+    let millis = std::time::SystemTime::now()
+        .duration_since(std::time::SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let param = format!("{}={}-{:08x}", name, millis, rand::random::<u32>());
+    let path_and_query = match url.query() {
+        Some(query) if !query.is_empty() => format!("{}?{}&{}", url.path(), query, param),
+        _ => format!("{}?{}", url.path(), param),
+    };
+
+    let mut parts = url.clone().into_parts();
+    parts.path_and_query = Some(
+        PathAndQuery::from_str(&path_and_query)
+            .map_err(|e| Error::InvalidParameter(Box::new(e)))?,
+    );
+    Uri::from_parts(parts).map_err(|e| Error::InvalidParameter(Box::new(e)))
+}
+
+fn uri_from_header(maybe_header: &Option<HeaderValue>) -> Result<Uri> {
+    let header = maybe_header.as_ref().ok_or_else(|| {
+        Error::MalformedLocationHeader(Box::new(std::io::Error::new(
+            ErrorKind::NotFound,
+            "missing Location header",
+        )))
+    })?;
+
+    let header_string = header
+        .to_str()
+        .map_err(|e| Error::MalformedLocationHeader(Box::new(e)))?;
+
+    header_string
+        .parse::<Uri>()
+        .map_err(|e| Error::MalformedLocationHeader(Box::new(e)))
+}
+
+/// Remember `id` as the last event ID, subject to the configured length limit.
+fn store_last_event_id(props: &RequestProps, stored: &mut Option<String>, id: Option<String>) {
+    let id = match (id, props.max_last_event_id_len) {
+        (Some(mut id), Some((max, overflow))) if id.len() > max => match overflow {
+            LastEventIdOverflow::Truncate => {
+                warn!(
+                    "{}truncating event id of {} bytes to {}",
+                    props.log_prefix(),
+                    id.len(),
+                    max
+                );
+                let mut end = max;
+                while !id.is_char_boundary(end) {
+                    end -= 1;
+                }
+                id.truncate(end);
+                Some(id)
+            }
+            LastEventIdOverflow::Ignore => {
+                warn!(
+                    "{}not storing event id of {} bytes",
+                    props.log_prefix(),
+                    id.len()
+                );
+                return;
+            }
+        },
+        (id, _) => id,
+    };
+    *stored = id;
+}
+
+/// Count an event delivered by the stream, returning whether that was the last one allowed by
+/// [`ClientBuilder::max_events`].
+fn reached_max_events(props: &RequestProps, remaining: &mut Option<u64>) -> bool {
+    match remaining {
+        Some(remaining) => {
+            *remaining -= 1;
+            if *remaining == 0 {
+                info!(
+                    "{}delivered the maximum number of events",
+                    props.log_prefix()
+                );
+            }
+            *remaining == 0
+        }
+        None => false,
+    }
+}
+
+fn new_event_parser(props: &RequestProps) -> EventParser {
+    let parser = EventParser::new()
+        .with_reset_event_type(props.reset_event_type.clone())
+        .with_case_insensitive_event_types(props.case_insensitive_event_types)
+        .with_emit_empty_events(props.emit_empty_events)
+        .with_skip_empty_data(props.skip_empty_data)
+        .with_skip_seen_ids(props.skip_seen_ids)
+        .with_max_line_length(props.max_line_length)
+        .with_incremental_data(props.incremental_data);
+    #[cfg(feature = "raw-events")]
+    let parser = parser.with_raw_events(props.raw_events);
+    #[cfg(feature = "trace-fields")]
+    let parser = parser.with_field_trace(props.trace_fields.clone());
+    parser
+}
+
+// `Content-Type` describes the decoded body, so a compressed event stream is still accepted here;
+// `Content-Encoding` is deliberately not consulted.
+fn is_event_stream<B>(resp: &hyper::Response<B>) -> bool {
+    resp.headers()
+        .get(hyper::header::CONTENT_TYPE)
+        .map(|value| is_media_type(value.as_bytes(), "text", "event-stream"))
+        .unwrap_or(false)
+}
+
+/// Whether a `Content-Type` value names the media type `type_/subtype`. The type and subtype are
+/// compared case-insensitively and with surrounding whitespace trimmed, and parameters are
+/// ignored whatever they contain, since servers format the header in many slightly different
+/// ways.
+fn is_media_type(value: &[u8], type_: &str, subtype: &str) -> bool {
+    let essence = value.split(|b| *b == b';').next().unwrap_or_default();
+    let essence = match std::str::from_utf8(essence) {
+        Ok(essence) => essence,
+        Err(_) => return false,
+    };
+
+    match essence.split_once('/') {
+        Some((t, s)) => {
+            t.trim().eq_ignore_ascii_case(type_) && s.trim().eq_ignore_ascii_case(subtype)
+        }
+        None => false,
+    }
+}
+
+/// Write all of `buf` to `writer`, then flush it.
+async fn write_all<W: AsyncWrite + Unpin>(writer: &mut W, mut buf: &[u8]) -> std::io::Result<()> {
+    while !buf.is_empty() {
+        let written =
+            futures::future::poll_fn(|cx| Pin::new(&mut *writer).poll_write(cx, buf)).await?;
+        if written == 0 {
+            return Err(ErrorKind::WriteZero.into());
+        }
+        buf = &buf[written..];
+    }
+    futures::future::poll_fn(|cx| Pin::new(&mut *writer).poll_flush(cx)).await
+}
+
+fn delay(props: &RequestProps, dur: Duration, description: &str) -> SleepFuture {
+    info!(
+        "{}Waiting {:?} before {}",
+        props.log_prefix(),
+        dur,
+        description
+    );
+    props.timer.sleep(dur)
+}
+
+#[cfg(test)]
+impl<S> ReconnectingRequest<S> {
+    /// Parse `bytes` as if they had been received from the server.
+    pub(crate) fn receive_for_test(self: Pin<&mut Self>, bytes: &'static str) {
+        let this = self.project();
+        this.event_parser
+            .process_bytes(hyper::body::Bytes::from(bytes))
+            .unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CircuitConfig;
+    use futures::{executor::block_on, StreamExt};
+    use hyper::body::Bytes;
+    use test_case::test_case;
+
+    fn response_with_content_type(content_type: Option<&'static str>) -> hyper::Response<()> {
+        let mut builder = hyper::Response::builder();
+        if let Some(content_type) = content_type {
+            builder = builder.header(hyper::header::CONTENT_TYPE, content_type);
+        }
+        builder.body(()).unwrap()
+    }
+
+    fn reconnecting_request(
+        builder: ClientBuilder,
+    ) -> ReconnectingRequest<hyper::Client<HttpConnector>> {
+        builder.build_stream_with_http_client(hyper::Client::new())
+    }
+
+    #[test]
+    fn test_for_uri_matches_for_url() {
+        let uri: Uri = "http://example.com/stream?a=b".parse().unwrap();
+        let from_uri = reconnecting_request(ClientBuilder::for_uri(uri.clone()))
+            .build_request()
+            .unwrap();
+        let from_url = reconnecting_request(ClientBuilder::for_url(&uri.to_string()).unwrap())
+            .build_request()
+            .unwrap();
+
+        assert_eq!(from_uri.uri(), &uri);
+        assert_eq!(from_uri.uri(), from_url.uri());
+        assert_eq!(from_uri.headers(), from_url.headers());
+    }
+
+    #[test]
+    fn test_path_keeps_authority() {
+        let builder = ClientBuilder::for_url("https://user@example.com:8443/old?x=1")
+            .unwrap()
+            .path("/topics/prices?since=now")
+            .unwrap();
+        let mut request = Box::pin(reconnecting_request(builder));
+        assert_eq!(
+            request.build_request().unwrap().uri(),
+            "https://user@example.com:8443/topics/prices?since=now"
+        );
+
+        request.as_mut().set_path("/topics/rates").unwrap();
+        assert_eq!(
+            request.build_request().unwrap().uri(),
+            "https://user@example.com:8443/topics/rates"
+        );
+        assert_eq!(
+            request.to_client_builder().props.url,
+            "https://user@example.com:8443/topics/rates"
+        );
+
+        assert!(request.as_mut().set_path("topics/rates").is_err());
+        assert!(ClientBuilder::for_url("http://example.com")
+            .unwrap()
+            .path("/with space")
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sticky_node_is_kept_until_it_fails() {
+        let uris = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = uris.clone();
+        let service = hyper::service::service_fn(move |request: Request<Body>| {
+            let uri = request.uri().to_string();
+            let mut seen = seen.lock().unwrap();
+            seen.push(uri.clone());
+            // The node serves one connection, then turns the stream away.
+            let node_requests = seen.iter().filter(|uri| uri.contains("node-2")).count();
+            let response = if !uri.contains("node-2") {
+                Response::builder()
+                    .header("x-node", "http://node-2.example.com/stream")
+                    .body(Body::from("data: origin\n\n"))
+            } else if node_requests == 1 {
+                Response::builder().body(Body::from("data: node\n\n"))
+            } else {
+                Response::builder().status(503).body(Body::empty())
+            };
+            async move { response }
+        });
+        let client = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .reconnect(
+                ReconnectOptions::reconnect(true)
+                    .delay(Duration::from_millis(1))
+                    .build(),
+            )
+            .sticky_node_header("x-node")
+            .unwrap()
+            .build_with_service(service);
+        let mut stream = client.stream();
+
+        let mut data = Vec::new();
+        while data.len() < 3 {
+            match stream.next().await {
+                Some(Ok(SSE::Event(event))) => data.push(event.data),
+                Some(Err(Error::Eof)) | Some(Err(Error::UnexpectedResponse(_))) => (),
+                other => panic!("unexpected item {:?}", other),
+            }
+        }
+        assert_eq!(data, vec!["origin", "node", "origin"]);
+        assert_eq!(
+            *uris.lock().unwrap(),
+            vec![
+                "http://example.com/stream",
+                "http://node-2.example.com/stream",
+                "http://node-2.example.com/stream",
+                "http://example.com/stream",
+            ]
+        );
+    }
+
+    #[test_case("http://node-2.example.com/stream", Some("http://node-2.example.com/stream"); "absolute")]
+    #[test_case("/stream?node=2", Some("https://example.com:8443/stream?node=2"); "path")]
+    #[test_case("node-2", None; "relative")]
+    fn test_sticky_node_url(value: &str, expected: Option<&str>) {
+        let current = Uri::from_static("https://example.com:8443/stream");
+        let url = sticky_node_url(&current, &HeaderValue::from_str(value).unwrap());
+        assert_eq!(
+            url.ok().map(|url| url.to_string()),
+            expected.map(String::from)
+        );
+    }
+
+    #[test]
+    fn test_header_replaces_and_append_header_adds() {
+        let builder = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .header("X-Tag", "replaced")
+            .unwrap()
+            .header("X-Tag", "one")
+            .unwrap()
+            .append_header("X-Tag", "two")
+            .unwrap();
+        let request = reconnecting_request(builder).build_request().unwrap();
+
+        let values: Vec<_> = request.headers().get_all("x-tag").iter().collect();
+        assert_eq!(values, vec!["one", "two"]);
+    }
+
+    #[test]
+    fn test_reset_event_clears_last_event_id() {
+        let builder = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .last_event_id("initial".to_string())
+            .reset_event_type("reset");
+        let mut request = Box::pin(reconnecting_request(builder));
+
+        let header = |request: &ReconnectingRequest<hyper::Client<HttpConnector>>| {
+            request
+                .build_request()
+                .unwrap()
+                .headers()
+                .get("last-event-id")
+                .cloned()
+        };
+        assert_eq!(header(&request), Some(HeaderValue::from_static("initial")));
+
+        request
+            .as_mut()
+            .project()
+            .event_parser
+            .process_bytes(Bytes::from(
+                "id: 1\ndata: first\n\nevent: reset\ndata:\n\ndata: after\n\n",
+            ))
+            .unwrap();
+
+        let first = block_on(request.next());
+        assert!(matches!(first, Some(Ok(SSE::Event(ref e))) if e.id.as_deref() == Some("1")));
+        assert_eq!(header(&request), Some(HeaderValue::from_static("1")));
+
+        let reset = block_on(request.next());
+        assert!(matches!(reset, Some(Ok(SSE::Event(ref e))) if e.event_type == "reset"));
+        assert_eq!(header(&request), None);
+
+        let after = block_on(request.next());
+        assert!(matches!(after, Some(Ok(SSE::Event(ref e))) if e.id.is_none()));
+        assert_eq!(header(&request), None);
+    }
+
+    #[test_case("id: 1\ndata: a\n\nid\ndata: b\n\n", 2; "empty id event")]
+    #[test_case("id: 1\ndata: a\n\nid:\n\n", 1; "lone empty id")]
+    #[tokio::test]
+    async fn test_cleared_id_is_not_sent_on_reconnect(body: &'static str, events: usize) {
+        let (mut sender, first_body) = Body::channel();
+        sender.try_send_data(Bytes::from(body)).unwrap();
+        let first_body = Arc::new(std::sync::Mutex::new(Some(first_body)));
+        let ids = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = ids.clone();
+        let service = hyper::service::service_fn(move |request: Request<Body>| {
+            seen.lock().unwrap().push(
+                request
+                    .headers()
+                    .get("last-event-id")
+                    .map(|id| id.to_str().unwrap().to_string()),
+            );
+            let body = first_body
+                .lock()
+                .unwrap()
+                .take()
+                .unwrap_or_else(|| Body::from("data: c\n\n"));
+            async move { Ok::<_, std::convert::Infallible>(Response::new(body)) }
+        });
+        let client = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .build_impl(service);
+        let mut request = Box::pin(ReconnectingRequest::new(
+            client.http,
+            client.request_props,
+            Some("0".into()),
+        ));
+
+        for _ in 0..events {
+            assert!(matches!(request.next().await, Some(Ok(SSE::Event(_)))));
+        }
+        request.reconnect_now();
+        match request.next().await {
+            Some(Ok(SSE::Event(event))) => assert_eq!(event.data, "c"),
+            other => panic!("expected an event, got {:?}", other),
+        }
+        assert_eq!(*ids.lock().unwrap(), vec![Some("0".to_string()), None]);
+        drop(sender);
+    }
+
+    #[test]
+    fn test_min_reconnect_interval_between_attempts() {
+        let builder = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .reconnect(
+                ReconnectOptions::reconnect(true)
+                    .min_reconnect_interval(Duration::from_secs(5))
+                    .build(),
+            );
+        let mut request = reconnecting_request(builder);
+        let start = Instant::now();
+        assert_eq!(request.time_until_next_attempt(start), None);
+
+        request.last_connect_attempt = Some(start);
+        assert_eq!(
+            request.time_until_next_attempt(start + Duration::from_secs(2)),
+            Some(Duration::from_secs(3))
+        );
+        assert_eq!(
+            request.time_until_next_attempt(start + Duration::from_secs(5)),
+            None
+        );
+
+        request.redirect_count = 1;
+        assert_eq!(
+            request.time_until_next_attempt(start + Duration::from_secs(2)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_huge_min_reconnect_interval() {
+        let builder = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .reconnect(
+                ReconnectOptions::reconnect(true)
+                    .min_reconnect_interval(Duration::MAX)
+                    .build(),
+            );
+        let mut request = reconnecting_request(builder);
+        let start = Instant::now();
+        request.last_connect_attempt = Some(start);
+        assert_eq!(request.time_until_next_attempt(start), Some(Duration::MAX));
+    }
+
+    #[tokio::test]
+    async fn test_redirects_are_followed_without_the_min_reconnect_interval() {
+        let service = hyper::service::service_fn(|request: Request<Body>| async move {
+            if request.uri().path() == "/stream" {
+                Response::builder()
+                    .status(307)
+                    .header(hyper::header::LOCATION, "http://example.com/moved")
+                    .body(Body::empty())
+            } else {
+                Response::builder()
+                    .header(hyper::header::CONTENT_TYPE, "text/event-stream")
+                    .body(Body::from("data: hello\n\n"))
+            }
+        });
+        let client = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .reconnect(
+                ReconnectOptions::reconnect(true)
+                    .min_reconnect_interval(Duration::from_secs(60))
+                    .build(),
+            )
+            .build_with_service(service);
+        let mut stream = client.stream();
+
+        let item = tokio::time::timeout(Duration::from_secs(5), stream.next())
+            .await
+            .expect("the redirect should be followed without waiting");
+        assert!(matches!(item, Some(Ok(SSE::Event(_)))));
+    }
+
+    #[test]
+    fn test_error_event_type_is_yielded_as_error() {
+        let builder = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .error_event_type("error");
+        let mut request = Box::pin(reconnecting_request(builder));
+
+        request
+            .as_mut()
+            .project()
+            .event_parser
+            .process_bytes(Bytes::from(
+                "id: 1\nevent: error\ndata: {\"code\":42}\n\ndata: ok\n\n",
+            ))
+            .unwrap();
+
+        match block_on(request.next()) {
+            Some(Err(Error::ServerEvent(data))) => assert_eq!(data, r#"{"code":42}"#),
+            other => panic!("expected a server error event, got {:?}", other),
+        }
+        assert_eq!(request.last_event_id.as_deref(), Some("1"));
+        assert!(matches!(block_on(request.next()), Some(Ok(SSE::Event(_)))));
+    }
+
+    #[test_case(false; "lazy")]
+    #[test_case(true; "eager")]
+    #[tokio::test]
+    async fn test_dropping_while_connecting_closes_the_connection(eager: bool) {
+        // The server accepts the connection but never responds.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/stream", listener.local_addr().unwrap());
+        let client = ClientBuilder::for_url(&url).unwrap().build_http();
+
+        let mut stream = if eager {
+            client.stream_eager()
+        } else {
+            client.stream()
+        };
+        if !eager {
+            assert!(futures::poll!(stream.next()).is_pending());
+        }
+        let (mut accepted, _) = tokio::task::spawn_blocking(move || listener.accept())
+            .await
+            .unwrap()
+            .unwrap();
+
+        drop(stream);
+
+        let closed = tokio::task::spawn_blocking(move || {
+            use std::io::Read;
+            accepted
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            // Read past the request, if it was sent, to the end of the connection.
+            accepted.read_to_end(&mut Vec::new())
+        })
+        .await
+        .unwrap();
+        assert!(closed.is_ok(), "{:?}", closed);
+    }
+
+    #[tokio::test]
+    async fn test_stream_eager_connects_before_poll() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/stream", listener.local_addr().unwrap());
+        let client = ClientBuilder::for_url(&url).unwrap().build_http();
+
+        let _stream = client.stream_eager();
+
+        let accepted = tokio::time::timeout(
+            Duration::from_secs(5),
+            tokio::task::spawn_blocking(move || listener.accept()),
+        )
+        .await;
+        assert!(matches!(accepted, Ok(Ok(Ok(_)))));
+    }
+
+    #[tokio::test]
+    async fn test_no_content_ends_the_stream() {
+        let requests = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = requests.clone();
+        let service = hyper::service::service_fn(move |_: Request<Body>| {
+            let response = match counter.fetch_add(1, Ordering::SeqCst) {
+                0 => Response::builder().body(Body::from("data: a\n\n")),
+                _ => Response::builder().status(204).body(Body::empty()),
+            };
+            async move { response }
+        });
+        let client = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .reconnect(
+                ReconnectOptions::reconnect(true)
+                    .delay(Duration::from_millis(1))
+                    .build(),
+            )
+            .build_impl(service);
+        let mut request = Box::pin(ReconnectingRequest::new(
+            client.http,
+            client.request_props,
+            None,
+        ));
+
+        assert!(matches!(request.next().await, Some(Ok(SSE::Event(e))) if e.data == "a"));
+        assert!(matches!(request.next().await, Some(Err(Error::Eof))));
+        assert!(request.next().await.is_none());
+        assert_eq!(request.last_status(), Some(StatusCode::NO_CONTENT));
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[test_case(0, 0; "zero")]
+    #[test_case(3, 2; "across reconnects")]
+    #[tokio::test]
+    async fn test_max_events(max: u64, connections: usize) {
+        let requests = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = requests.clone();
+        let service = hyper::service::service_fn(move |_: Request<Body>| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async {
+                Ok::<_, std::convert::Infallible>(Response::new(Body::from(
+                    "data: a\n\n:comment\ndata: b\n\n",
+                )))
+            }
+        });
+        let client = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .reconnect(
+                ReconnectOptions::reconnect(true)
+                    .delay(Duration::from_millis(1))
+                    .build(),
+            )
+            .max_events(max)
+            .build_with_service(service);
+
+        let items: Vec<_> = client.stream().collect().await;
+        let events = items
+            .iter()
+            .filter(|item| matches!(item, Ok(SSE::Event(_))))
+            .count();
+        assert_eq!(events as u64, max);
+        // The stream ends straight after the last event.
+        assert!(max == 0 || matches!(items.last(), Some(Ok(SSE::Event(_)))));
+        assert_eq!(requests.load(Ordering::SeqCst), connections);
+    }
+
+    #[tokio::test]
+    async fn test_on_response_sees_every_connection() {
+        let requests = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let service = hyper::service::service_fn(move |_: Request<Body>| {
+            let request = requests.fetch_add(1, Ordering::SeqCst);
+            let response = Response::builder()
+                .header("content-type", "text/event-stream")
+                .header("x-request", request)
+                .body(Body::from("data: hello\n\n"));
+            async move { response }
+        });
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = seen.clone();
+        let client = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .reconnect(
+                ReconnectOptions::reconnect(true)
+                    .delay(Duration::from_millis(1))
+                    .build(),
+            )
+            .on_response(move |response| {
+                recorded.lock().unwrap().push((
+                    response.status(),
+                    response.version(),
+                    response.headers()["x-request"].clone(),
+                ));
+            })
+            .build_with_service(service);
+        let mut stream = client.stream();
+
+        let mut events = 0;
+        while events < 2 {
+            match stream.next().await {
+                Some(Ok(SSE::Event(_))) => events += 1,
+                Some(Err(Error::Eof)) => (),
+                other => panic!("unexpected item {:?}", other),
+            }
+        }
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![
+                (
+                    StatusCode::OK,
+                    hyper::Version::HTTP_11,
+                    HeaderValue::from(0)
+                ),
+                (
+                    StatusCode::OK,
+                    hyper::Version::HTTP_11,
+                    HeaderValue::from(1)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_before_send_runs_for_every_request() {
+        let attempts = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = attempts.clone();
+        let builder = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .before_send(move |request| {
+                let attempt = counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+                request
+                    .headers_mut()
+                    .insert("x-attempt", HeaderValue::from(attempt));
+            });
+        let request = reconnecting_request(builder);
+
+        for attempt in 1..=2 {
+            let built = request.build_request().unwrap();
+            assert_eq!(
+                built.headers().get("x-attempt"),
+                Some(&HeaderValue::from(attempt))
+            );
+        }
+    }
+
+    #[test]
+    fn test_request_id_is_generated_for_every_request() {
+        let next_id = std::sync::atomic::AtomicUsize::new(1);
+        let builder = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .header("X-Request-Id", "static")
+            .unwrap()
+            .request_id("x-request-id", move || {
+                let id = next_id.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                if id < 3 {
+                    format!("req-{}", id)
+                } else {
+                    "not\na header".to_string()
+                }
+            })
+            .unwrap();
+        let request = reconnecting_request(builder);
+
+        for id in ["req-1", "req-2"] {
+            let built = request.build_request().unwrap();
+            let values: Vec<_> = built.headers().get_all("x-request-id").iter().collect();
+            assert_eq!(values, vec![id]);
+        }
+        assert!(matches!(
+            request.build_request(),
+            Err(Error::InvalidParameter(_))
+        ));
+        assert!(ClientBuilder::for_url("http://example.com")
+            .unwrap()
+            .request_id("bad header", String::new)
+            .is_err());
+    }
+
+    #[test_case(false, 2; "counting all reconnects")]
+    #[test_case(true, 0; "reset on connect")]
+    fn test_reconnect_attempt_header(reset: bool, after_connect: u32) {
+        let builder = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .reconnect_attempt_header(true)
+            .reset_reconnect_attempts(reset);
+        let mut request = Box::pin(reconnecting_request(builder));
+        let attempt = |request: &Pin<Box<ReconnectingRequest<_>>>| {
+            request.build_request().unwrap().headers()[RECONNECT_ATTEMPT_HEADER].clone()
+        };
+
+        request.as_mut().record_connect_attempt(Instant::now());
+        assert_eq!(attempt(&request), HeaderValue::from(0));
+
+        // a redirect is part of the same attempt
+        *request.as_mut().project().redirect_count = 1;
+        request.as_mut().record_connect_attempt(Instant::now());
+        assert_eq!(attempt(&request), HeaderValue::from(0));
+        request.as_mut().reset_redirects();
+
+        for expected in 1..=2 {
+            request.as_mut().record_connect_attempt(Instant::now());
+            assert_eq!(attempt(&request), HeaderValue::from(expected));
+        }
+
+        request.as_mut().reset_backoff();
+        assert_eq!(attempt(&request), HeaderValue::from(after_connect));
+    }
+
+    #[test_case(hyper::Version::HTTP_11, true; "http1")]
+    #[test_case(hyper::Version::HTTP_2, false; "http2")]
+    fn test_keep_alive_header(version: hyper::Version, expected: bool) {
+        let builder = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .keep_alive_header(true)
+            .before_send(move |request| *request.version_mut() = version);
+        let request = reconnecting_request(builder);
+
+        let built = request.build_request().unwrap();
+        assert_eq!(
+            built.headers().get(hyper::header::CONNECTION)
+                == Some(&HeaderValue::from_static("keep-alive")),
+            expected
+        );
+    }
+
+    #[derive(Clone)]
+    struct FixedResolver(SocketAddr);
+
+    impl Service<Name> for FixedResolver {
+        type Response = std::vec::IntoIter<SocketAddr>;
+        type Error = std::io::Error;
+        type Future = futures::future::Ready<std::result::Result<Self::Response, Self::Error>>;
+
+        fn poll_ready(
+            &mut self,
+            _cx: &mut Context<'_>,
+        ) -> Poll<std::result::Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _name: Name) -> Self::Future {
+            futures::future::ready(Ok(vec![self.0].into_iter()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_build_http_with_resolver_uses_resolver() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let url = format!("http://stream.example.invalid:{}/", addr.port());
+        let client = ClientBuilder::for_url(&url)
+            .unwrap()
+            .build_http_with_resolver(FixedResolver(addr));
+
+        let _stream = client.stream_eager();
+
+        let accepted = tokio::time::timeout(
+            Duration::from_secs(5),
+            tokio::task::spawn_blocking(move || listener.accept()),
+        )
+        .await;
+        assert!(matches!(accepted, Ok(Ok(Ok(_)))));
+    }
+
+    #[cfg(feature = "rustls")]
+    #[test_case(TlsVersion::Tls12, &[ProtocolVersion::TLSv1_3, ProtocolVersion::TLSv1_2]; "tls 1.2")]
+    #[test_case(TlsVersion::Tls13, &[ProtocolVersion::TLSv1_3]; "tls 1.3")]
+    fn test_min_tls_version(min_version: TlsVersion, expected: &[ProtocolVersion]) {
+        assert_eq!(tls_versions(min_version), expected);
+    }
+
+    #[tokio::test]
+    async fn test_http_1_0_responses_back_off() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            use std::io::{Read, Write};
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = [0; 1024];
+                let _ = stream.read(&mut request).unwrap();
+                // No content length or keep-alive: the body ends when the connection closes.
+                let _ = write!(
+                    stream,
+                    "HTTP/1.0 200 OK\r\ncontent-type: text/event-stream\r\n\r\ndata: hello\n\n"
+                );
+            }
+        });
+        let sleeps = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let client = ClientBuilder::for_url(&format!("http://{}/stream", addr))
+            .unwrap()
+            .reconnect(
+                ReconnectOptions::reconnect(true)
+                    .delay(Duration::from_millis(100))
+                    .backoff_factor(2)
+                    .delay_max(Duration::from_secs(60))
+                    .build(),
+            )
+            .timer(ImmediateTimer(sleeps.clone()))
+            .build_http();
+        let mut stream = client.stream();
+
+        for _ in 0..4 {
+            let received = tokio::time::timeout(Duration::from_secs(5), async {
+                (stream.next().await, stream.next().await)
+            })
+            .await
+            .unwrap();
+            assert!(
+                matches!(&received, (Some(Ok(SSE::Event(e))), Some(Err(Error::Eof))) if e.data == "hello"),
+                "{:?}",
+                received
+            );
+        }
+
+        // Each connection was brief, so the delay kept growing instead of starting again from
+        // the base delay. With jitter, each delay is at least half of the unjittered one.
+        let sleeps = sleeps.lock().unwrap();
+        assert_eq!(sleeps.len(), 4);
+        for (i, sleep) in sleeps.iter().enumerate() {
+            let minimum = Duration::from_millis(50) * 2u32.pow(i as u32);
+            assert!(*sleep >= minimum, "{:?}", sleeps);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_build_with_boxed_conn() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            use std::io::{Read, Write};
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = [0; 1024];
+                let _ = stream.read(&mut request).unwrap();
+                let body = "data: hello\n\n";
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\ncontent-length: {}\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+
+        // Different connector types, boxed into one.
+        let connectors = vec![
+            (
+                format!("http://{}/stream", addr),
+                BoxConnector::new(HttpConnector::new()),
+            ),
+            (
+                format!("http://stream.example.invalid:{}/stream", addr.port()),
+                BoxConnector::new(HttpConnector::new_with_resolver(FixedResolver(addr))),
+            ),
+        ];
+        for (url, connector) in connectors {
+            let client: Box<dyn Client> = ClientBuilder::for_url(&url)
+                .unwrap()
+                .build_with_boxed_conn(connector);
+            match client.stream().next().await {
+                Some(Ok(SSE::Event(event))) => assert_eq!(event.data, "hello"),
+                other => panic!("expected an event, got {:?}", other),
+            }
+        }
+    }
+
+    #[derive(Clone)]
+    struct FailingResolver;
+
+    impl Service<Name> for FailingResolver {
+        type Response = std::vec::IntoIter<SocketAddr>;
+        type Error = std::io::Error;
+        type Future = futures::future::Ready<std::result::Result<Self::Response, Self::Error>>;
+
+        fn poll_ready(
+            &mut self,
+            _cx: &mut Context<'_>,
+        ) -> Poll<std::result::Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _name: Name) -> Self::Future {
+            futures::future::ready(Err(std::io::Error::other("no such host")))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_is_dns_error() {
+        let resolving = hyper::Client::builder()
+            .build::<_, Body>(HttpConnector::new_with_resolver(FailingResolver))
+            .get("http://stream.example.invalid/".parse().unwrap())
+            .await;
+        assert!(is_dns_error(&resolving.unwrap_err()));
+
+        let connecting = hyper::Client::new()
+            .get(closed_port_url().parse().unwrap())
+            .await;
+        assert!(!is_dns_error(&connecting.unwrap_err()));
+
+        // Only connect errors are considered, whatever their message.
+        assert!(!is_dns_error(&std::io::Error::other(
+            "dns error: not really"
+        )));
+    }
+
+    #[test_case(true, Duration::from_secs(50), Duration::from_secs(100); "dns failure")]
+    #[test_case(false, Duration::from_millis(500), Duration::from_secs(1); "other failure")]
+    fn test_dns_failures_use_separate_backoff(dns_failure: bool, min: Duration, max: Duration) {
+        let builder = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .reconnect(
+                ReconnectOptions::reconnect(true)
+                    .delay(Duration::from_secs(1))
+                    .dns_retry_delay(Duration::from_secs(100))
+                    .dns_delay_max(Duration::from_secs(600))
+                    .build(),
+            );
+        let mut request = Box::pin(reconnecting_request(builder));
+
+        let delay = request.as_mut().next_retry_delay(dns_failure);
+        assert!(min <= delay && delay <= max, "{:?}", delay);
+    }
+
+    #[test]
+    fn test_time_to_first_event() {
+        let builder = ClientBuilder::for_url("http://example.com/stream").unwrap();
+        let mut request = Box::pin(builder.build_stream_with_http_client(hyper::Client::new()));
+        assert_eq!(request.time_to_first_event(), None);
+
+        let started = Instant::now() - Duration::from_secs(1);
+        request.as_mut().record_connect_attempt(started);
+        request
+            .as_mut()
+            .project()
+            .event_parser
+            .process_bytes(Bytes::from(":hi\ndata: first\n\ndata: second\n\n"))
+            .unwrap();
+
+        assert!(matches!(
+            block_on(request.next()),
+            Some(Ok(SSE::Comment(_)))
+        ));
+        assert_eq!(request.time_to_first_event(), None);
+
+        assert!(matches!(block_on(request.next()), Some(Ok(SSE::Event(_)))));
+        let first = request.time_to_first_event().unwrap();
+        assert!(first >= Duration::from_secs(1));
+
+        request.as_mut().record_connect_attempt(Instant::now());
+        assert!(matches!(block_on(request.next()), Some(Ok(SSE::Event(_)))));
+        assert_eq!(request.time_to_first_event(), Some(first));
+    }
+
+    #[test]
+    fn test_event_rate_counts_delivered_events() {
+        let builder = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .event_rate_window(Duration::from_secs(100));
+        let mut request = Box::pin(reconnecting_request(builder));
+        assert_eq!(request.event_rate(), 0.0);
+
+        request
+            .as_mut()
+            .project()
+            .event_parser
+            .process_bytes(Bytes::from("data: one\n\n:comment\n\ndata: two\n\n"))
+            .unwrap();
+        for _ in 0..3 {
+            assert!(block_on(request.next()).unwrap().is_ok());
+        }
+
+        // Two events, each adding 1/100th of an event per second.
+        let rate = request.event_rate();
+        assert!(rate > 0.019 && rate <= 0.02, "{}", rate);
+    }
+
+    fn closed_port_url() -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://{}/", listener.local_addr().unwrap())
+    }
+
+    #[test_case(ErrorAction::Fail; "fail")]
+    #[test_case(ErrorAction::End; "end")]
+    #[test_case(ErrorAction::Reconnect; "reconnect")]
+    #[tokio::test]
+    async fn test_on_error_decides_action(action: ErrorAction) {
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = seen.clone();
+        let client = ClientBuilder::for_url(&closed_port_url())
+            .unwrap()
+            .reconnect(
+                ReconnectOptions::reconnect(true)
+                    .delay(Duration::from_millis(1))
+                    .build(),
+            )
+            .on_error(move |error| {
+                recorded.lock().unwrap().push(error.is_http_stream_error());
+                action
+            })
+            .build_http();
+        let mut stream = client.stream();
+
+        let first = stream.next().await;
+        let second = stream.next().await;
+        match action {
+            ErrorAction::Fail => {
+                assert!(matches!(first, Some(Err(Error::HttpStream(_)))));
+                assert!(matches!(second, Some(Err(Error::StreamClosed))));
+                assert_eq!(*seen.lock().unwrap(), vec![true]);
+            }
+            ErrorAction::End => {
+                assert!(first.is_none());
+                assert_eq!(*seen.lock().unwrap(), vec![true]);
+            }
+            ErrorAction::Reconnect => {
+                assert!(matches!(first, Some(Err(Error::HttpStream(_)))));
+                assert!(matches!(second, Some(Err(Error::HttpStream(_)))));
+                assert_eq!(*seen.lock().unwrap(), vec![true, true]);
+            }
+        }
+    }
+
+    struct ImmediateTimer(Arc<std::sync::Mutex<Vec<Duration>>>);
+
+    impl Timer for ImmediateTimer {
+        fn sleep(&self, duration: Duration) -> SleepFuture {
+            self.0.lock().unwrap().push(duration);
+            Box::pin(futures::future::ready(()))
+        }
+    }
+
+    #[test]
+    fn test_rate_limit_delays_events_beyond_the_limit() {
+        let sleeps = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let builder = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .timer(ImmediateTimer(sleeps.clone()))
+            .rate_limit(2);
+        let mut request = Box::pin(reconnecting_request(builder));
+
+        request
+            .as_mut()
+            .project()
+            .event_parser
+            .process_bytes(Bytes::from(
+                "data: 1\n\ndata: 2\n\n:ping\n\ndata: 3\n\ndata: 4\n\n",
+            ))
+            .unwrap();
+
+        let mut delivered = Vec::new();
+        for _ in 0..5 {
+            match block_on(request.next()) {
+                Some(Ok(SSE::Event(event))) => delivered.push(event.data),
+                Some(Ok(SSE::Comment(_))) => assert_eq!(sleeps.lock().unwrap().len(), 0),
+                other => panic!("unexpected item {:?}", other),
+            }
+        }
+        assert_eq!(delivered, vec!["1", "2", "3", "4"]);
+
+        let sleeps = sleeps.lock().unwrap();
+        assert_eq!(sleeps.len(), 2);
+        assert!(sleeps[0] <= Duration::from_millis(500), "{:?}", sleeps);
+        assert!(sleeps[1] > sleeps[0] && sleeps[1] <= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_rate_limit_only_counts_delivered_events() {
+        let sleeps = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let builder = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .timer(ImmediateTimer(sleeps.clone()))
+            .rate_limit(2)
+            .skip_consecutive_duplicates(true)
+            .error_event_type("error");
+        let mut request = Box::pin(reconnecting_request(builder));
+
+        request.as_mut().receive_for_test(
+            "data: 1\n\ndata: 1\n\nevent: error\ndata: oops\n\ndata: 2\n\ndata: 3\n\n",
+        );
+
+        assert!(matches!(block_on(request.next()), Some(Ok(SSE::Event(e))) if e.data == "1"));
+        assert!(matches!(
+            block_on(request.next()),
+            Some(Err(Error::ServerEvent(_)))
+        ));
+        assert!(matches!(block_on(request.next()), Some(Ok(SSE::Event(e))) if e.data == "2"));
+        assert!(sleeps.lock().unwrap().is_empty());
+        assert!(matches!(block_on(request.next()), Some(Ok(SSE::Event(e))) if e.data == "3"));
+        assert_eq!(sleeps.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_timer_clock_reflects_timer() {
+        let builder = ClientBuilder::for_url("http://example.com/stream").unwrap();
+        assert_eq!(
+            reconnecting_request(builder).timer_clock(),
+            TimerClock::Monotonic
+        );
+
+        let builder = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .timer(crate::WallClockTimer::default());
+        assert_eq!(
+            reconnecting_request(builder).timer_clock(),
+            TimerClock::WallClock
+        );
+    }
+
+    #[tokio::test]
+    async fn test_custom_timer_is_used_between_attempts() {
+        let sleeps = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let client = ClientBuilder::for_url(&closed_port_url())
+            .unwrap()
+            .reconnect(
+                ReconnectOptions::reconnect(true)
+                    .delay(Duration::from_secs(60))
+                    .build(),
+            )
+            .on_error(|_| ErrorAction::Reconnect)
+            .timer(ImmediateTimer(sleeps.clone()))
+            .build_http();
+        let mut stream = client.stream();
+
+        // With tokio's timer the second attempt would wait a minute.
+        let attempts = tokio::time::timeout(Duration::from_secs(5), async {
+            assert!(matches!(
+                stream.next().await,
+                Some(Err(Error::HttpStream(_)))
+            ));
+            assert!(matches!(
+                stream.next().await,
+                Some(Err(Error::HttpStream(_)))
+            ));
+        });
+        attempts.await.expect("custom timer was not used");
+        // each failed attempt schedules a reconnect
+        assert_eq!(sleeps.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_grace_applies_after_a_lost_connection() {
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = calls.clone();
+        let service = hyper::service::service_fn(move |_request: Request<Body>| {
+            let call = counter.fetch_add(1, Ordering::SeqCst);
+            async move {
+                if call == 1 {
+                    return Err(std::io::Error::new(ErrorKind::ConnectionRefused, "refused"));
+                }
+                Ok(Response::builder()
+                    .header(hyper::header::CONTENT_TYPE, "text/event-stream")
+                    .body(Body::from(format!("data: {}\n\n", call)))
+                    .unwrap())
+            }
+        });
+        let sleeps = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let client = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .reconnect(
+                ReconnectOptions::reconnect(true)
+                    .retry_initial(true)
+                    .delay(Duration::from_millis(10))
+                    .delay_max(Duration::from_secs(1))
+                    .reconnect_grace(Duration::from_secs(5))
+                    .build(),
+            )
+            .timer(ImmediateTimer(sleeps.clone()))
+            .build_with_service(service);
+        let mut stream = client.stream();
+
+        let mut events = Vec::new();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while events.len() < 2 {
+                if let Some(Ok(SSE::Event(event))) = stream.next().await {
+                    events.push(event.data);
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(events, vec!["0", "2"]);
+
+        // The grace follows the lost connection; the failed attempt after it backs off as usual.
+        let sleeps = sleeps.lock().unwrap();
+        assert_eq!(sleeps.len(), 2, "{:?}", sleeps);
+        assert_eq!(sleeps[0], Duration::from_secs(5));
+        assert!(sleeps[1] < Duration::from_secs(1), "{:?}", sleeps);
+    }
+
+    #[tokio::test]
+    async fn test_build_with_service() {
+        let service = hyper::service::service_fn(|request: Request<Body>| async move {
+            let data = format!("data: {}\n\n", request.uri().path());
+            Ok::<_, std::convert::Infallible>(
+                Response::builder()
+                    .header(hyper::header::CONTENT_TYPE, "text/event-stream")
+                    .body(Body::from(data))
+                    .unwrap(),
+            )
+        });
+        let client = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .build_with_service(service);
+
+        match client.stream().next().await {
+            Some(Ok(SSE::Event(event))) => assert_eq!(event.data, "/stream"),
+            other => panic!("expected an event, got {:?}", other),
+        }
+    }
+
+    fn preflight_service(
+        preflights: Arc<std::sync::atomic::AtomicU32>,
+    ) -> impl Service<
+        Request<Body>,
+        Response = Response<Body>,
+        Error = std::convert::Infallible,
+        Future = impl Future<Output = std::result::Result<Response<Body>, std::convert::Infallible>>
+                     + Send
+                     + Sync,
+    > + Clone {
+        hyper::service::service_fn(move |request: Request<Body>| {
+            let preflights = preflights.clone();
+            async move {
+                let authorized = request.headers().get("authorization")
+                    == Some(&HeaderValue::from_static("Bearer token"));
+                let response = if request.uri().path() == "/session" {
+                    preflights.fetch_add(1, Ordering::SeqCst);
+                    assert_eq!(request.method(), hyper::Method::HEAD);
+                    Response::builder()
+                        .status(if authorized { 200 } else { 401 })
+                        .header("x-session", "abc")
+                        .body(Body::empty())
+                } else {
+                    let session = request.headers().get("x-session").cloned();
+                    Response::builder()
+                        .header(hyper::header::CONTENT_TYPE, "text/event-stream")
+                        .body(Body::from(format!("data: {:?}\n\n", session)))
+                };
+                Ok(response.unwrap())
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn test_preflight_headers_are_sent_with_every_request() {
+        let preflights = Arc::new(std::sync::atomic::AtomicU32::new(0));
+        let client = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .header("Authorization", "Bearer token")
+            .unwrap()
+            .reconnect(
+                ReconnectOptions::reconnect(true)
+                    .delay(Duration::from_millis(1))
+                    .build(),
+            )
+            .preflight("HEAD", "/session", |response, headers| {
+                headers.insert("x-session", response.headers()["x-session"].clone());
+            })
+            .unwrap()
+            .build_with_service(preflight_service(preflights.clone()));
+        let mut stream = client.stream();
+
+        // Each response ends after one event, so the stream reconnects between them.
+        let mut events = 0;
+        while events < 2 {
+            match stream.next().await {
+                Some(Ok(SSE::Event(event))) => {
+                    assert_eq!(event.data, "Some(\"abc\")");
+                    events += 1;
+                }
+                Some(Err(Error::Eof)) => (),
+                other => panic!("unexpected item {:?}", other),
+            }
+        }
+        assert_eq!(preflights.load(Ordering::SeqCst), 1);
+    }
+
+    #[cfg(feature = "cookies")]
+    #[tokio::test]
+    async fn test_cookies_are_echoed_on_reconnect() {
+        let visits = Arc::new(std::sync::atomic::AtomicU32::new(0));
+        let service = hyper::service::service_fn(move |request: Request<Body>| {
+            let visit = visits.fetch_add(1, Ordering::SeqCst) + 1;
+            async move {
+                let cookie = request.headers().get(hyper::header::COOKIE).cloned();
+                Response::builder()
+                    .header(hyper::header::CONTENT_TYPE, "text/event-stream")
+                    .header(
+                        hyper::header::SET_COOKIE,
+                        format!("visits={}; Path=/", visit),
+                    )
+                    .body(Body::from(format!("data: {:?}\n\n", cookie)))
+            }
+        });
+        let jar = CookieJar::new();
+        jar.set("user", "me");
+        let client = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .reconnect(
+                ReconnectOptions::reconnect(true)
+                    .delay(Duration::from_millis(1))
+                    .build(),
+            )
+            .cookie_jar(jar.clone())
+            .build_with_service(service);
+        let mut stream = client.stream();
+
+        let mut data = Vec::new();
+        while data.len() < 2 {
+            match stream.next().await {
+                Some(Ok(SSE::Event(event))) => data.push(event.data),
+                Some(Err(Error::Eof)) => (),
+                other => panic!("unexpected item {:?}", other),
+            }
+        }
+        assert_eq!(
+            data,
+            vec!["Some(\"user=me\")", "Some(\"user=me; visits=1\")"]
+        );
+        assert_eq!(jar.get("visits").as_deref(), Some("2"));
+    }
+
+    #[cfg(feature = "cookies")]
+    #[tokio::test]
+    async fn test_cookies_are_not_sent_to_another_host_after_a_redirect() {
+        let service = hyper::service::service_fn(|request: Request<Body>| async move {
+            let cookie = request.headers().get(hyper::header::COOKIE).cloned();
+            if request.uri().host() == Some("example.com") {
+                Response::builder()
+                    .status(307)
+                    .header(hyper::header::SET_COOKIE, "session=abc; Path=/")
+                    .header(hyper::header::LOCATION, "http://other.example/stream")
+                    .body(Body::empty())
+            } else {
+                Response::builder()
+                    .header(hyper::header::CONTENT_TYPE, "text/event-stream")
+                    .body(Body::from(format!("data: {:?}\n\n", cookie)))
+            }
+        });
+        let jar = CookieJar::new();
+        jar.set("user", "me");
+        let client = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .cookie_jar(jar.clone())
+            .build_with_service(service);
+        let mut stream = client.stream();
+
+        match stream.next().await {
+            Some(Ok(SSE::Event(event))) => assert_eq!(event.data, "None"),
+            other => panic!("expected an event, got {:?}", other),
+        }
+        assert_eq!(jar.get("session").as_deref(), Some("abc"));
+    }
+
+    #[tokio::test]
+    async fn test_rejected_preflight_closes_the_stream() {
+        let preflights = Arc::new(std::sync::atomic::AtomicU32::new(0));
+        let client = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .reconnect(
+                ReconnectOptions::reconnect(true)
+                    .retry_initial(true)
+                    .build(),
+            )
+            .preflight("HEAD", "/session", |_, _| panic!("preflight was rejected"))
+            .unwrap()
+            .build_with_service(preflight_service(preflights.clone()));
+        let mut stream = client.stream();
+
+        assert!(matches!(
+            stream.next().await,
+            Some(Err(Error::PreflightRejected(status))) if status == 401
+        ));
+        assert!(matches!(
+            stream.next().await,
+            Some(Err(Error::StreamClosed))
+        ));
+        assert_eq!(preflights.load(Ordering::SeqCst), 1);
+    }
+
+    #[test_case(false; "lazy")]
+    #[test_case(true; "eager")]
+    #[tokio::test]
+    async fn test_prepended_events_come_first(eager: bool) {
+        let service = hyper::service::service_fn(|request: Request<Body>| async move {
+            let data = format!(
+                "data: resumed from {:?}\n\n",
+                request.headers()["last-event-id"]
+            );
+            Ok::<_, std::convert::Infallible>(
+                Response::builder()
+                    .header(hyper::header::CONTENT_TYPE, "text/event-stream")
+                    .body(Body::from(data))
+                    .unwrap(),
+            )
+        });
+        let cached = |id: &str| Event {
+            event_type: "message".to_string(),
+            data: format!("cached {}", id),
+            id: Some(id.to_string()),
+            retry: None,
+        };
+        let client = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .prepend_events(vec![cached("1"), cached("2")])
+            .build_with_service(service);
+        let stream = if eager {
+            client.stream_eager()
+        } else {
+            client.stream()
+        };
+
+        let data: Vec<_> = stream
+            .take(3)
+            .map(|item| match item {
+                Ok(SSE::Event(event)) => event.data,
+                other => panic!("expected an event, got {:?}", other),
+            })
+            .collect()
+            .await;
+        assert_eq!(data, vec!["cached 1", "cached 2", "resumed from \"2\""]);
+    }
+
+    #[test]
+    fn test_prepended_events_are_handled_like_received_ones() {
+        let cached = |event_type: &str, data: &str, id: &str| Event {
+            event_type: event_type.to_string(),
+            data: data.to_string(),
+            id: Some(id.to_string()),
+            retry: None,
+        };
+        let builder = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .prepend_events(vec![
+                cached("message", "olleh", "1"),
+                cached("message", "olleh", "1"),
+                cached("error", "deliaf", "2"),
+            ])
+            .skip_seen_ids(10)
+            .error_event_type("error")
+            .transform_data(
+                |mut data| {
+                    data.reverse();
+                    Ok(data)
+                },
+                ErrorAction::Fail,
+            );
+        let mut request = Box::pin(reconnecting_request(builder));
+
+        assert!(matches!(block_on(request.next()), Some(Ok(SSE::Event(e))) if e.data == "hello"));
+        match block_on(request.next()) {
+            Some(Err(Error::ServerEvent(data))) => assert_eq!(data, "failed"),
+            other => panic!("expected a server error event, got {:?}", other),
+        }
+        assert_eq!(request.last_event_id.as_deref(), Some("2"));
+    }
+
+    #[tokio::test]
+    async fn test_empty_data_frames_are_ignored() {
+        let service = hyper::service::service_fn(|_: Request<Body>| async {
+            let (mut sender, body) = Body::channel();
+            tokio::spawn(async move {
+                for chunk in ["", "data: hel", "", "lo\n", "", "\n", ""] {
+                    sender.send_data(chunk.into()).await.unwrap();
+                }
+                // Keep the body open.
+                futures::future::pending::<()>().await;
+                drop(sender);
+            });
+            Ok::<_, std::convert::Infallible>(
+                Response::builder()
+                    .header(hyper::header::CONTENT_TYPE, "text/event-stream")
+                    .body(body)
+                    .unwrap(),
+            )
+        });
+        let client = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .build_with_service(service);
+        let mut stream = client.stream();
+
+        match stream.next().await {
+            Some(Ok(SSE::Event(event))) => assert_eq!(event.data, "hello"),
+            other => panic!("expected an event, got {:?}", other),
+        }
+        let next = tokio::time::timeout(Duration::from_millis(50), stream.next()).await;
+        assert!(next.is_err(), "unexpected item {:?}", next);
+    }
+
+    #[tokio::test]
+    async fn test_heartbeats_are_interleaved_with_events() {
+        let service = hyper::service::service_fn(|_: Request<Body>| async {
+            let (mut sender, body) = Body::channel();
+            tokio::spawn(async move {
+                sender.send_data("data: a\n\n".into()).await.unwrap();
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                sender.send_data("data: b\n\n".into()).await.unwrap();
+                // Keep the body open.
+                futures::future::pending::<()>().await;
+                drop(sender);
+            });
+            Ok::<_, std::convert::Infallible>(
+                Response::builder()
+                    .header(hyper::header::CONTENT_TYPE, "text/event-stream")
+                    .body(body)
+                    .unwrap(),
+            )
+        });
+        let client = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .heartbeat_interval(Duration::from_millis(20))
+            .build_with_service(service);
+        let mut stream = client.stream();
+
+        match stream.next().await {
+            Some(Ok(SSE::Event(event))) => assert_eq!(event.data, "a"),
+            other => panic!("expected an event, got {:?}", other),
+        }
+        let mut heartbeats = 0;
+        loop {
+            match stream.next().await {
+                Some(Ok(SSE::Heartbeat)) => heartbeats += 1,
+                Some(Ok(SSE::Event(event))) => {
+                    assert_eq!(event.data, "b");
+                    break;
+                }
+                other => panic!("unexpected item {:?}", other),
+            }
+        }
+        assert!(heartbeats >= 2, "{} heartbeats", heartbeats);
+        assert!(matches!(stream.next().await, Some(Ok(SSE::Heartbeat))));
+    }
+
+    #[test_case(ReadTimeoutPolicy::EventsOnly, true; "events only")]
+    #[test_case(ReadTimeoutPolicy::EventsAndComments, false; "events and comments")]
+    #[tokio::test]
+    async fn test_read_timeout_policy(policy: ReadTimeoutPolicy, times_out: bool) {
+        // Send a keep-alive comment every 10ms, and never an event.
+        let service = hyper::service::service_fn(|_: Request<Body>| async {
+            let (mut sender, body) = Body::channel();
+            tokio::spawn(async move {
+                while sender.send_data(":ping\n".into()).await.is_ok() {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            });
+            Ok::<_, std::convert::Infallible>(
+                Response::builder()
+                    .header(hyper::header::CONTENT_TYPE, "text/event-stream")
+                    .body(body)
+                    .unwrap(),
+            )
+        });
+        let client = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .reconnect(ReconnectOptions::reconnect(false).build())
+            .read_timeout(Duration::from_millis(50))
+            .read_timeout_policy(policy)
+            .build_with_service(service);
+        let mut stream = client.stream();
+
+        let timed_out = tokio::time::timeout(Duration::from_millis(300), async {
+            loop {
+                match stream.next().await {
+                    Some(Ok(SSE::Comment(_))) => continue,
+                    Some(Err(Error::TimedOut)) => break,
+                    other => panic!("unexpected item {:?}", other),
+                }
+            }
+        })
+        .await
+        .is_ok();
+        assert_eq!(timed_out, times_out);
+        if times_out {
+            assert!(matches!(
+                stream.next().await,
+                Some(Err(Error::StreamClosed))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_oversized_line_drops_the_connection() {
+        let last_event_ids = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = last_event_ids.clone();
+        let service = hyper::service::service_fn(move |request: Request<Body>| {
+            let first = seen.lock().unwrap().is_empty();
+            seen.lock()
+                .unwrap()
+                .push(request.headers().get("last-event-id").cloned());
+            async move {
+                // The tail of the oversized line, in a chunk of its own, looks like fields.
+                let chunks: Vec<&'static str> = if first {
+                    vec!["data: much too long", " id: evil\ndata: injected\n\n"]
+                } else {
+                    vec!["data: ok\n\n"]
+                };
+                let (mut sender, body) = Body::channel();
+                tokio::spawn(async move {
+                    for chunk in chunks {
+                        let _ = sender.send_data(chunk.into()).await;
+                    }
+                });
+                Ok::<_, std::convert::Infallible>(
+                    Response::builder()
+                        .header(hyper::header::CONTENT_TYPE, "text/event-stream")
+                        .body(body)
+                        .unwrap(),
+                )
+            }
+        });
+        let sleeps = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let client = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .max_line_length(8)
+            .timer(ImmediateTimer(sleeps.clone()))
+            .build_with_service(service);
+        let mut stream = client.stream();
+
+        assert!(matches!(
+            stream.next().await,
+            Some(Err(Error::InvalidLine(_)))
+        ));
+        match stream.next().await {
+            Some(Ok(SSE::Event(event))) => {
+                assert_eq!(event.data, "ok");
+                assert_eq!(event.id, None);
+            }
+            other => panic!("expected an event, got {:?}", other),
+        }
+        assert_eq!(*last_event_ids.lock().unwrap(), vec![None, None]);
+        assert_eq!(sleeps.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_build_stream_http_applies_the_read_timeout() {
+        // The server sends one event and then nothing, holding the connection open.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            use std::io::{Read, Write};
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request).unwrap();
+            let _ = write!(
+                stream,
+                "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\n\r\ndata: hello\n\n"
+            );
+            std::thread::sleep(Duration::from_secs(5));
+        });
+        let mut stream = Box::pin(
+            ClientBuilder::for_url(&format!("http://{}/stream", addr))
+                .unwrap()
+                .reconnect(ReconnectOptions::reconnect(false).build())
+                .read_timeout(Duration::from_millis(100))
+                .build_stream_http(),
+        );
+
+        let received = tokio::time::timeout(Duration::from_secs(3), async {
+            (stream.next().await, stream.next().await)
+        })
+        .await
+        .expect("the read timeout was not applied");
+        assert!(
+            matches!(&received, (Some(Ok(SSE::Event(e))), Some(Err(Error::TimedOut))) if e.data == "hello"),
+            "{:?}",
+            received
+        );
+        assert!(stream.time_to_first_event().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_first_event_timeout() {
+        // Keep each response's body open, sending only a comment.
+        let senders = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let service = hyper::service::service_fn(move |_: Request<Body>| {
+            let (mut sender, body) = Body::channel();
+            sender.try_send_data(":ping\n\n".into()).unwrap();
+            senders.lock().unwrap().push(sender);
+            async move {
+                Ok::<_, std::convert::Infallible>(
+                    Response::builder()
+                        .header(hyper::header::CONTENT_TYPE, "text/event-stream")
+                        .body(body)
+                        .unwrap(),
+                )
+            }
+        });
+        let client = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .reconnect(ReconnectOptions::reconnect(false).build())
+            .first_event_timeout(Duration::from_millis(50))
+            .build_with_service(service);
+        let mut stream = client.stream();
+
+        assert!(matches!(stream.next().await, Some(Ok(SSE::Comment(_)))));
+        assert!(matches!(
+            stream.next().await,
+            Some(Err(Error::FirstEventTimeout))
+        ));
+        assert!(matches!(
+            stream.next().await,
+            Some(Err(Error::StreamClosed))
+        ));
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_reconnect_log_records_retries() {
+        let builder = ClientBuilder::for_url(&closed_port_url())
+            .unwrap()
+            .reconnect(
+                ReconnectOptions::reconnect(true)
+                    .retry_initial(true)
+                    .delay(Duration::from_millis(1))
+                    .delay_max(Duration::from_millis(8))
+                    .build(),
+            );
+        let mut request = Box::pin(reconnecting_request(builder));
+
+        // Failed initial connections are retried without yielding anything.
+        let polled = tokio::time::timeout(Duration::from_millis(200), request.next()).await;
+        assert!(polled.is_err());
+
+        let log = request.reconnect_log();
+        assert!(log.len() > 2);
+        for (i, record) in log.iter().enumerate() {
+            assert_eq!(record.kind, ReconnectKind::Retry);
+            assert_eq!(record.attempt, i as u32 + 1);
+            assert!(record.delay <= Duration::from_millis(8));
+        }
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_min_reconnect_interval_wait_is_not_logged_as_a_reconnect() {
+        let service = hyper::service::service_fn(|_| async {
+            Ok::<_, std::convert::Infallible>(Response::new(Body::from("data: hello\n\n")))
+        });
+        let client = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .reconnect(
+                ReconnectOptions::reconnect(true)
+                    .delay(Duration::from_millis(1))
+                    .min_reconnect_interval(Duration::from_millis(50))
+                    .build(),
+            )
+            .build_impl(service);
+        let mut request = Box::pin(ReconnectingRequest::new(
+            client.http,
+            client.request_props,
+            None,
+        ));
+
+        assert!(matches!(request.next().await, Some(Ok(SSE::Event(_)))));
+        assert!(matches!(request.next().await, Some(Err(Error::Eof))));
+        assert!(matches!(request.next().await, Some(Ok(SSE::Event(_)))));
+        assert_eq!(request.reconnect_log().len(), 1);
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_build_from_file_replays_capture() {
+        let path = std::env::temp_dir().join(format!("es-replay-{}.sse", std::process::id()));
+        std::fs::write(&path, "data: one\n\n: ping\ndata: two\n\n").unwrap();
+
+        let client = ClientBuilder::for_url("http://replay.invalid/")
+            .unwrap()
+            .reconnect(
+                ReconnectOptions::reconnect(true)
+                    .delay(Duration::from_millis(1))
+                    .build(),
+            )
+            .build_from_file(&path);
+        let mut stream = client.stream();
+
+        let mut items = Vec::new();
+        for _ in 0..5 {
+            items.push(stream.next().await.unwrap());
+        }
+        std::fs::remove_file(&path).unwrap();
+
+        let data = |item: &Result<SSE>| match item {
+            Ok(SSE::Event(event)) => event.data.clone(),
+            other => panic!("expected an event, got {:?}", other),
+        };
+        assert_eq!(data(&items[0]), "one");
+        assert!(matches!(items[1], Ok(SSE::Comment(_))));
+        assert_eq!(data(&items[2]), "two");
+        assert!(matches!(items[3], Err(Error::Eof)));
+        assert_eq!(data(&items[4]), "one");
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_build_from_missing_file_fails_to_connect() {
+        let path = std::env::temp_dir().join("es-replay-missing.sse");
+        let client = ClientBuilder::for_url("http://replay.invalid/")
+            .unwrap()
+            .build_from_file(path);
+
+        assert!(matches!(
+            client.stream().next().await,
+            Some(Err(Error::HttpStream(_)))
+        ));
+    }
+
+    #[test_case(false; "discarded")]
+    #[test_case(true; "dispatched")]
+    #[tokio::test]
+    async fn test_dispatch_on_eof(dispatch: bool) {
+        let service = hyper::service::service_fn(|_: Request<Body>| async {
+            Ok::<_, std::convert::Infallible>(Response::new(Body::from("data: one\n\ndata: two")))
+        });
+        let client = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .dispatch_on_eof(dispatch)
+            .build_with_service(service);
+        let mut stream = client.stream();
+
+        assert!(matches!(stream.next().await, Some(Ok(SSE::Event(_)))));
+        if dispatch {
+            match stream.next().await {
+                Some(Ok(SSE::Event(event))) => assert_eq!(event.data, "two"),
+                other => panic!("expected the final event, got {:?}", other),
+            }
+            assert!(matches!(stream.next().await, Some(Err(Error::Eof))));
+        } else {
+            assert!(matches!(
+                stream.next().await,
+                Some(Err(Error::UnexpectedEof))
+            ));
+        }
+    }
+
+    #[test_case(false, true; "reconnect disabled")]
+    #[test_case(true, false; "reconnect enabled")]
+    #[tokio::test]
+    async fn test_error_on_unexpected_eof(reconnect: bool, closes: bool) {
+        let requests = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let count = requests.clone();
+        let service = hyper::service::service_fn(move |_: Request<Body>| {
+            count.fetch_add(1, Ordering::SeqCst);
+            async { Ok::<_, std::convert::Infallible>(Response::new(Body::from("data: one\n\n"))) }
+        });
+        let client = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .reconnect(
+                ReconnectOptions::reconnect(reconnect)
+                    .delay(Duration::from_millis(1))
+                    .build(),
+            )
+            .error_on_unexpected_eof(true)
+            .build_with_service(service);
+        let mut stream = client.stream();
+
+        assert!(matches!(stream.next().await, Some(Ok(SSE::Event(_)))));
+        assert!(matches!(stream.next().await, Some(Err(Error::Eof))));
+        if closes {
+            assert!(matches!(
+                stream.next().await,
+                Some(Err(Error::StreamClosed))
+            ));
+            assert_eq!(requests.load(Ordering::SeqCst), 1);
+        } else {
+            assert!(matches!(stream.next().await, Some(Ok(SSE::Event(_)))));
+            assert_eq!(requests.load(Ordering::SeqCst), 2);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_max_bytes_per_connection() {
+        let requests = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = requests.clone();
+        let service = hyper::service::service_fn(move |_: Request<Body>| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async move {
+                let (mut sender, body) = Body::channel();
+                tokio::spawn(async move {
+                    for chunk in ["data: one\n\n", "data: two\n\n", "data: three\n\n"] {
+                        if sender.send_data(chunk.into()).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+                Ok::<_, std::convert::Infallible>(Response::new(body))
+            }
+        });
+        let client = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .max_bytes_per_connection(25, ErrorAction::Reconnect)
+            .reconnect(
+                ReconnectOptions::reconnect(true)
+                    .delay(Duration::from_millis(1))
+                    .build(),
+            )
+            .build_with_service(service);
+        let mut stream = client.stream();
+
+        for _ in 0..2 {
+            for expected in ["one", "two"] {
+                match stream.next().await {
+                    Some(Ok(SSE::Event(event))) => assert_eq!(event.data, expected),
+                    other => panic!("expected an event, got {:?}", other),
+                }
+            }
+            assert!(matches!(
+                stream.next().await,
+                Some(Err(Error::ConnectionByteLimit(25)))
+            ));
+        }
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_now_replaces_connection() {
+        // Keep each response's body open, sending a comment naming the request.
+        let senders = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let service = hyper::service::service_fn(move |request: Request<Body>| {
+            let (mut sender, body) = Body::channel();
+            let token = request.headers()["x-token"].to_str().unwrap().to_string();
+            sender
+                .try_send_data(format!(":{}\n", token).into())
+                .unwrap();
+            senders.lock().unwrap().push(sender);
+            async move { Ok::<_, std::convert::Infallible>(Response::new(body)) }
+        });
+        let tokens = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let client = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .before_send(move |request| {
+                let token = tokens.fetch_add(1, Ordering::SeqCst);
+                request
+                    .headers_mut()
+                    .insert("x-token", HeaderValue::from(token));
+            })
+            .build_impl(service);
+        let mut request = Box::pin(ReconnectingRequest::new(
+            client.http,
+            client.request_props,
+            None,
+        ));
+
+        assert_eq!(
+            request.next().await.unwrap().unwrap(),
+            SSE::Comment("0".into())
+        );
+
+        let handle = request.reconnect_handle();
+        tokio::spawn(async move { handle.reconnect_now() })
+            .await
+            .unwrap();
+        assert_eq!(
+            request.next().await.unwrap().unwrap(),
+            SSE::Comment("1".into())
+        );
+        assert_eq!(
+            request.last_reconnect_reason(),
+            Some(ReconnectReason::ClientRequested)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_emit_resuming_announces_each_connection() {
+        let service = hyper::service::service_fn(|_| async {
+            Ok::<_, std::convert::Infallible>(Response::new(Body::from("id: 7\ndata: one\n\n")))
+        });
+        let client = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .last_event_id("3".into())
+            .reconnect(
+                ReconnectOptions::reconnect(true)
+                    .delay(Duration::from_millis(1))
+                    .build(),
+            )
+            .emit_resuming(true)
+            .build_impl(service);
+        let mut stream = client.stream();
+
+        assert_eq!(
+            stream.next().await.unwrap().unwrap(),
+            SSE::Resuming {
+                last_event_id: Some("3".into())
+            }
+        );
+        assert!(matches!(stream.next().await, Some(Ok(SSE::Event(_)))));
+        assert!(matches!(stream.next().await, Some(Err(Error::Eof))));
+        assert_eq!(
+            stream.next().await.unwrap().unwrap(),
+            SSE::Resuming {
+                last_event_id: Some("7".into())
+            }
+        );
+        assert!(matches!(stream.next().await, Some(Ok(SSE::Event(_)))));
+    }
+
+    #[tokio::test]
+    async fn test_last_reconnect_reason_after_server_close() {
+        let service = hyper::service::service_fn(|_| async {
+            Ok::<_, std::convert::Infallible>(Response::new(Body::from("data: one\n\n")))
+        });
+        let client = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .build_impl(service);
+        let mut request = Box::pin(ReconnectingRequest::new(
+            client.http,
+            client.request_props,
+            None,
+        ));
+        assert_eq!(request.last_reconnect_reason(), None);
+
+        assert!(matches!(request.next().await, Some(Ok(SSE::Event(_)))));
+        assert!(matches!(request.next().await, Some(Err(Error::Eof))));
+        assert_eq!(
+            request.last_reconnect_reason(),
+            Some(ReconnectReason::ServerClosed)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_last_reconnect_reason_after_transport_error() {
+        let builder = ClientBuilder::for_url(&closed_port_url()).unwrap();
+        let mut request = Box::pin(reconnecting_request(builder));
+
+        assert!(matches!(
+            request.next().await,
+            Some(Err(Error::HttpStream(_)))
+        ));
+        assert_eq!(
+            request.last_reconnect_reason(),
+            Some(ReconnectReason::TransportError)
+        );
     }
-}
 
-impl<C> Stream for ReconnectingRequest<C>
-where
-    C: Connect + Clone + Send + Sync + 'static,
-{
-    type Item = Result<SSE>;
+    #[tokio::test]
+    async fn test_last_status_tracks_successful_connections() {
+        // A fresh stream gets a 200; a resumed one, sending a last event ID, gets a 206.
+        let service = hyper::service::service_fn(|request: Request<Body>| async move {
+            let status = if request.headers().contains_key("last-event-id") {
+                206
+            } else {
+                200
+            };
+            Ok::<_, std::convert::Infallible>(
+                Response::builder()
+                    .status(status)
+                    .header(hyper::header::CONTENT_TYPE, "text/event-stream")
+                    .body(Body::from("id: 1\ndata: hello\n\n"))
+                    .unwrap(),
+            )
+        });
+        let builder = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .reconnect(
+                ReconnectOptions::reconnect(true)
+                    .delay(Duration::from_millis(1))
+                    .build(),
+            );
+        let client = builder.build_impl(service);
+        let mut request = Box::pin(ReconnectingRequest::new(
+            client.http,
+            client.request_props,
+            None,
+        ));
+        assert_eq!(request.last_status(), None);
 
-    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        trace!("ReconnectingRequest::poll({:?})", &self.state);
+        assert!(matches!(request.next().await, Some(Ok(SSE::Event(_)))));
+        assert_eq!(request.last_status(), Some(StatusCode::OK));
 
-        loop {
-            let this = self.as_mut().project();
-            if let Some(event) = this.event_parser.get_event() {
-                return match event {
-                    SSE::Event(ref evt) => {
-                        *this.last_event_id = evt.id.clone();
+        assert!(matches!(request.next().await, Some(Err(Error::Eof))));
+        assert!(matches!(request.next().await, Some(Ok(SSE::Event(_)))));
+        assert_eq!(request.last_status(), Some(StatusCode::PARTIAL_CONTENT));
+    }
 
-                        if let Some(retry) = evt.retry {
-                            this.retry_strategy
-                                .change_base_delay(Duration::from_millis(retry));
-                        }
-                        Poll::Ready(Some(Ok(event)))
-                    }
-                    SSE::Comment(_) => Poll::Ready(Some(Ok(event))),
-                };
+    #[tokio::test]
+    async fn test_retry_budget_closes_the_stream() {
+        let requests = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = requests.clone();
+        let service = hyper::service::service_fn(move |_: Request<Body>| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async {
+                Ok::<_, std::convert::Infallible>(Response::new(Body::from("data: hello\n\n")))
             }
+        });
+        let client = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .reconnect(
+                ReconnectOptions::reconnect(true)
+                    .delay(Duration::from_millis(1))
+                    .retry_budget(2, Duration::from_secs(60))
+                    .build(),
+            )
+            .build_with_service(service);
+        let mut stream = client.stream();
 
-            trace!("ReconnectingRequest::poll loop({:?})", &this.state);
+        for _ in 0..3 {
+            assert!(matches!(stream.next().await, Some(Ok(SSE::Event(_)))));
+            assert!(matches!(stream.next().await, Some(Err(Error::Eof))));
+        }
+        assert!(matches!(
+            stream.next().await,
+            Some(Err(Error::RetryBudgetExhausted))
+        ));
+        assert!(matches!(
+            stream.next().await,
+            Some(Err(Error::StreamClosed))
+        ));
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
 
-            let state = this.state.project();
-            match state {
-                StateProj::StreamClosed => return Poll::Ready(Some(Err(Error::StreamClosed))),
-                // New immediately transitions to Connecting, and exists only
-                // to ensure that we only connect when polled.
-                StateProj::New => {
-                    *self.as_mut().project().event_parser = EventParser::new();
-                    match self.send_request() {
-                        Ok(resp) => {
-                            let retry = self.props.reconnect_opts.retry_initial;
-                            self.as_mut()
-                                .project()
-                                .state
-                                .set(State::Connecting { resp, retry })
-                        }
-                        Err(e) => {
-                            // This error seems to be unrecoverable. So we should just shut down the
-                            // stream.
-                            self.as_mut().project().state.set(State::StreamClosed);
-                            return Poll::Ready(Some(Err(e)));
-                        }
-                    }
-                }
-                StateProj::Connecting { retry, resp } => match ready!(resp.poll(cx)) {
-                    Ok(resp) => {
-                        debug!("HTTP response: {:#?}", resp);
+    #[tokio::test]
+    async fn test_retry_budget_counts_each_reconnect_once_when_emitting_resuming() {
+        let requests = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = requests.clone();
+        let service = hyper::service::service_fn(move |_: Request<Body>| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async {
+                Ok::<_, std::convert::Infallible>(Response::new(Body::from("data: hello\n\n")))
+            }
+        });
+        let client = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .reconnect(
+                ReconnectOptions::reconnect(true)
+                    .delay(Duration::from_millis(1))
+                    .retry_budget(2, Duration::from_secs(60))
+                    .build(),
+            )
+            .emit_resuming(true)
+            .build_with_service(service);
+        let mut stream = client.stream();
 
-                        if resp.status().is_success() {
-                            self.as_mut().project().retry_strategy.reset(Instant::now());
-                            self.as_mut().reset_redirects();
-                            self.as_mut()
-                                .project()
-                                .state
-                                .set(State::Connected(resp.into_body()));
-                            continue;
-                        }
+        for _ in 0..3 {
+            assert!(matches!(
+                stream.next().await,
+                Some(Ok(SSE::Resuming { .. }))
+            ));
+            assert!(matches!(stream.next().await, Some(Ok(SSE::Event(_)))));
+            assert!(matches!(stream.next().await, Some(Err(Error::Eof))));
+        }
+        assert!(matches!(
+            stream.next().await,
+            Some(Ok(SSE::Resuming { .. }))
+        ));
+        assert!(matches!(
+            stream.next().await,
+            Some(Err(Error::RetryBudgetExhausted))
+        ));
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
 
-                        if resp.status() == 301 || resp.status() == 307 {
-                            debug!("got redirected ({})", resp.status());
+    #[tokio::test]
+    async fn test_circuit_breaker_pauses_reconnects() {
+        // The first two requests are turned away, and the third connects.
+        let requests = Arc::new(std::sync::atomic::AtomicU32::new(0));
+        let service = hyper::service::service_fn(move |_: Request<Body>| {
+            let status = match requests.fetch_add(1, Ordering::SeqCst) {
+                0 | 1 => 503,
+                _ => 200,
+            };
+            async move {
+                Response::builder()
+                    .status(status)
+                    .header(hyper::header::CONTENT_TYPE, "text/event-stream")
+                    .body(Body::from("data: hello\n\n"))
+            }
+        });
+        let cooldown = Duration::from_millis(100);
+        let builder = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .reconnect(
+                ReconnectOptions::reconnect(true)
+                    .retry_initial(true)
+                    .delay(Duration::from_millis(1))
+                    .circuit_breaker(CircuitConfig::new(2, Duration::from_secs(60), cooldown))
+                    .build(),
+            );
+        let client = builder.build_impl(service);
+        let mut request = Box::pin(ReconnectingRequest::new(
+            client.http,
+            client.request_props,
+            None,
+        ));
 
-                            if self.as_mut().increment_redirect_counter() {
-                                debug!("following redirect {}", self.redirect_count);
+        assert!(matches!(
+            request.next().await,
+            Some(Err(Error::UnexpectedResponse(_)))
+        ));
+        assert_eq!(request.circuit_state(), CircuitState::Closed);
+        assert!(matches!(
+            request.next().await,
+            Some(Err(Error::UnexpectedResponse(_)))
+        ));
+        assert_eq!(request.circuit_state(), CircuitState::Open);
 
-                                self.as_mut().project().state.set(State::FollowingRedirect(
-                                    resp.headers().get(hyper::header::LOCATION).cloned(),
-                                ));
-                                continue;
-                            } else {
-                                debug!("redirect limit reached ({})", self.props.max_redirects);
+        let opened = Instant::now();
+        assert!(matches!(request.next().await, Some(Ok(SSE::Event(_)))));
+        assert!(opened.elapsed() >= cooldown);
+        assert_eq!(request.circuit_state(), CircuitState::Closed);
+    }
 
-                                self.as_mut().project().state.set(State::StreamClosed);
-                                return Poll::Ready(Some(Err(Error::MaxRedirectLimitReached(
-                                    self.props.max_redirects,
-                                ))));
-                            }
-                        }
+    #[test_case(ReconnectStrategy::Exponential, false; "exponential")]
+    #[test_case(ReconnectStrategy::Adaptive, true; "adaptive")]
+    #[tokio::test]
+    async fn test_average_uptime(strategy: ReconnectStrategy, learns: bool) {
+        let service = hyper::service::service_fn(|_: Request<Body>| async {
+            Ok::<_, std::convert::Infallible>(Response::new(Body::from("data: hello\n\n")))
+        });
+        let builder = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .reconnect(
+                ReconnectOptions::reconnect(true)
+                    .delay(Duration::from_millis(1))
+                    .strategy(strategy)
+                    .build(),
+            );
+        let client = builder.build_impl(service);
+        let mut request = Box::pin(ReconnectingRequest::new(
+            client.http,
+            client.request_props,
+            None,
+        ));
 
-                        self.as_mut().reset_redirects();
-                        self.as_mut().project().state.set(State::New);
-                        return Poll::Ready(Some(Err(Error::UnexpectedResponse(resp.status()))));
-                    }
-                    Err(e) => {
-                        // This seems basically impossible. AFAIK we can only get this way if we
-                        // poll after it was already ready
-                        warn!("request returned an error: {}", e);
-                        if !*retry {
-                            self.as_mut().project().state.set(State::New);
-                            return Poll::Ready(Some(Err(Error::HttpStream(Box::new(e)))));
-                        }
-                        let duration = self
-                            .as_mut()
-                            .project()
-                            .retry_strategy
-                            .next_delay(Instant::now());
-                        self.as_mut()
-                            .project()
-                            .state
-                            .set(State::WaitingToReconnect(delay(duration, "retrying")))
+        assert!(matches!(request.next().await, Some(Ok(SSE::Event(_)))));
+        assert_eq!(request.average_uptime(), None);
+        assert!(matches!(request.next().await, Some(Err(Error::Eof))));
+        assert_eq!(request.average_uptime().is_some(), learns);
+    }
+
+    #[test_case(EventTypePolicy::Warn; "warn")]
+    #[test_case(EventTypePolicy::Error; "error")]
+    fn test_unexpected_event_types(policy: EventTypePolicy) {
+        let builder = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .reset_event_type("reset")
+            .expected_event_types(&["put", "patch"], policy);
+        let mut request = Box::pin(reconnecting_request(builder));
+
+        request
+            .as_mut()
+            .project()
+            .event_parser
+            .process_bytes(Bytes::from(
+                "event: put\ndata: a\n\nevent: reset\ndata: b\n\nevent: delete\ndata: c\n\ndata: d\n\n",
+            ))
+            .unwrap();
+
+        let mut items = Vec::new();
+        for _ in 0..4 {
+            items.push(block_on(request.next()).unwrap());
+        }
+        assert!(matches!(&items[0], Ok(SSE::Event(e)) if e.event_type == "put"));
+        assert!(matches!(&items[1], Ok(SSE::Event(e)) if e.event_type == "reset"));
+        match policy {
+            EventTypePolicy::Warn => {
+                assert!(matches!(&items[2], Ok(SSE::Event(e)) if e.event_type == "delete"));
+                assert!(matches!(&items[3], Ok(SSE::Event(e)) if e.event_type == "message"));
+            }
+            EventTypePolicy::Error => {
+                assert!(matches!(&items[2], Err(Error::UnexpectedEventType(t)) if t == "delete"));
+                assert!(matches!(&items[3], Err(Error::UnexpectedEventType(t)) if t == "message"));
+            }
+        }
+        assert_eq!(request.unexpected_event_types(), 2);
+    }
+
+    #[test]
+    fn test_transform_data() {
+        let builder = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .error_event_type("error")
+            .transform_data(
+                |mut data| {
+                    if data == b"bad" {
+                        return Err("can't decode".into());
                     }
+                    data.reverse();
+                    Ok(data)
                 },
-                StateProj::FollowingRedirect(maybe_header) => match uri_from_header(maybe_header) {
-                    Ok(uri) => {
-                        *self.as_mut().project().current_url = uri;
-                        self.as_mut().project().state.set(State::New);
-                    }
-                    Err(e) => {
-                        self.as_mut().project().state.set(State::StreamClosed);
-                        return Poll::Ready(Some(Err(e)));
-                    }
+                ErrorAction::Fail,
+            );
+        let mut request = Box::pin(reconnecting_request(builder));
+        request
+            .as_mut()
+            .receive_for_test("data: olleh\n\nevent: error\ndata: deliaf\n\nid: 1\ndata: bad\n\n");
+
+        assert!(matches!(block_on(request.next()), Some(Ok(SSE::Event(e))) if e.data == "hello"));
+        match block_on(request.next()) {
+            Some(Err(Error::ServerEvent(data))) => assert_eq!(data, "failed"),
+            other => panic!("expected a server error event, got {:?}", other),
+        }
+        assert!(matches!(
+            block_on(request.next()),
+            Some(Err(Error::TransformFailed(_)))
+        ));
+        assert_eq!(request.last_event_id.as_deref(), Some("1"));
+        assert!(matches!(
+            block_on(request.next()),
+            Some(Err(Error::StreamClosed))
+        ));
+    }
+
+    #[cfg(feature = "data-encoding")]
+    #[test]
+    fn test_data_encoding() {
+        let builder = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .data_encoding(DataEncoding::Base64);
+        let mut request = Box::pin(reconnecting_request(builder));
+        request
+            .as_mut()
+            .receive_for_test("data: aGVsbG8=\n\nid: 1\ndata: ???\n\ndata: Ynll\n\n");
+
+        assert!(matches!(block_on(request.next()), Some(Ok(SSE::Event(e))) if e.data == "hello"));
+        assert!(matches!(
+            block_on(request.next()),
+            Some(Err(Error::InvalidDataEncoding(_)))
+        ));
+        assert_eq!(request.last_event_id.as_deref(), Some("1"));
+        assert!(matches!(block_on(request.next()), Some(Ok(SSE::Event(e))) if e.data == "bye"));
+    }
+
+    #[test]
+    fn test_cache_buster_changes_every_attempt() {
+        let builder = ClientBuilder::for_url("http://example.com/stream?topic=prices")
+            .unwrap()
+            .cache_buster("cb")
+            .unwrap();
+        let request = Box::pin(reconnecting_request(builder));
+
+        let buster = || {
+            let uri = request.build_request().unwrap().uri().clone();
+            assert_eq!(uri.path(), "/stream");
+            let query = uri.query().unwrap().to_string();
+            let value = query.strip_prefix("topic=prices&cb=").unwrap();
+            assert!(!value.is_empty());
+            value.to_string()
+        };
+        assert_ne!(buster(), buster());
+        assert_eq!(
+            request.to_client_builder().props.cache_buster.as_deref(),
+            Some("cb")
+        );
+
+        for name in ["", "a b", "a&b", "a=b"] {
+            assert!(ClientBuilder::for_url("http://example.com")
+                .unwrap()
+                .cache_buster(name)
+                .is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_skip_seen_ids_across_reconnects() {
+        let requests = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = requests.clone();
+        let service = hyper::service::service_fn(move |_: Request<Body>| {
+            // Each connection replays the last event of the one before.
+            let body = match counter.fetch_add(1, Ordering::SeqCst) {
+                0 => "id: 1\ndata: a\n\nid: 2\ndata: b\n\n",
+                _ => "id: 2\ndata: b\n\nid: 3\ndata: c\n\n",
+            };
+            async move { Ok::<_, std::convert::Infallible>(Response::new(Body::from(body))) }
+        });
+        let client = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .reconnect(
+                ReconnectOptions::reconnect(true)
+                    .delay(Duration::from_millis(1))
+                    .build(),
+            )
+            .skip_seen_ids(2)
+            .build_impl(service);
+        let mut request = Box::pin(ReconnectingRequest::new(
+            client.http,
+            client.request_props,
+            None,
+        ));
+
+        let mut data = Vec::new();
+        while data.len() < 3 {
+            match request.next().await {
+                Some(Ok(SSE::Event(event))) => data.push(event.data),
+                Some(Err(Error::Eof)) => (),
+                other => panic!("unexpected item {:?}", other),
+            }
+        }
+        assert_eq!(data, vec!["a", "b", "c"]);
+        assert_eq!(request.seen_ids(), (2, 1));
+    }
+
+    #[test_case(false, &["1", "1", "2", "2 other"]; "off")]
+    #[test_case(true, &["1", "2", "2 other"]; "on")]
+    fn test_skip_consecutive_duplicates(enabled: bool, expected: &[&str]) {
+        let builder = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .skip_consecutive_duplicates(enabled);
+        let mut request = Box::pin(reconnecting_request(builder));
+        request.as_mut().receive_for_test(concat!(
+            "id: 1\ndata: a\n\n",
+            "id: 1\ndata: a\n\n",
+            // The same data with a different ID or type isn't a duplicate.
+            "id: 2\ndata: a\n\n",
+            "id: 2\nevent: other\ndata: a\n\n",
+        ));
+
+        let received: Vec<_> = expected
+            .iter()
+            .map(|_| match block_on(request.next()) {
+                Some(Ok(SSE::Event(event))) => match event.event_type.as_str() {
+                    "message" => event.id.unwrap(),
+                    other => format!("{} {}", event.id.unwrap(), other),
                 },
-                StateProj::Connected(body) => match ready!(body.poll_data(cx)) {
-                    Some(Ok(result)) => {
-                        this.event_parser.process_bytes(result)?;
-                        continue;
-                    }
-                    Some(Err(e)) => {
-                        if self.props.reconnect_opts.reconnect {
-                            let duration = self
-                                .as_mut()
-                                .project()
-                                .retry_strategy
-                                .next_delay(Instant::now());
-                            self.as_mut()
-                                .project()
-                                .state
-                                .set(State::WaitingToReconnect(delay(duration, "reconnecting")));
-                        }
+                other => panic!("expected an event, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(received, expected);
+        assert!(!request.event_parser.was_processing());
+    }
 
-                        if let Some(cause) = e.source() {
-                            if let Some(downcast) = cause.downcast_ref::<std::io::Error>() {
-                                if let std::io::ErrorKind::TimedOut = downcast.kind() {
-                                    return Poll::Ready(Some(Err(Error::TimedOut)));
-                                }
-                            }
-                        } else {
-                            return Poll::Ready(Some(Err(Error::HttpStream(Box::new(e)))));
-                        }
-                    }
-                    None => {
-                        let duration = self
-                            .as_mut()
-                            .project()
-                            .retry_strategy
-                            .next_delay(Instant::now());
-                        self.as_mut()
-                            .project()
-                            .state
-                            .set(State::WaitingToReconnect(delay(duration, "retrying")));
+    #[test_case(false; "case sensitive")]
+    #[test_case(true; "case insensitive")]
+    fn test_case_insensitive_event_types(ignore_case: bool) {
+        let builder = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .reset_event_type("reset")
+            .error_event_type("error")
+            .expected_event_types(&["put"], EventTypePolicy::Error)
+            .case_insensitive_event_types(ignore_case);
+        let mut request = Box::pin(reconnecting_request(builder));
 
-                        if self.event_parser.was_processing() {
-                            return Poll::Ready(Some(Err(Error::UnexpectedEof)));
-                        }
-                        return Poll::Ready(Some(Err(Error::Eof)));
+        request.as_mut().receive_for_test(
+            "id: 1\nevent: PUT\ndata: a\n\nevent: Reset\ndata: b\n\nevent: Error\ndata: c\n\n",
+        );
+
+        let items: Vec<_> = (0..3).map(|_| block_on(request.next()).unwrap()).collect();
+        if ignore_case {
+            assert!(matches!(&items[0], Ok(SSE::Event(e)) if e.event_type == "PUT"));
+            assert!(matches!(&items[1], Ok(SSE::Event(e)) if e.event_type == "Reset"));
+            assert!(matches!(&items[2], Err(Error::ServerEvent(data)) if data == "c"));
+            assert_eq!(request.last_event_id.as_deref(), None);
+            assert_eq!(request.unexpected_event_types(), 0);
+        } else {
+            for (item, event_type) in items.iter().zip(["PUT", "Reset", "Error"]) {
+                assert!(
+                    matches!(item, Err(Error::UnexpectedEventType(t)) if t == event_type),
+                    "{:?}",
+                    item
+                );
+            }
+            assert_eq!(request.last_event_id.as_deref(), Some("1"));
+            assert_eq!(request.unexpected_event_types(), 3);
+        }
+    }
+
+    /// A writer that accepts at most `chunk` bytes per write.
+    struct SlowWriter {
+        written: Vec<u8>,
+        chunk: usize,
+    }
+
+    impl AsyncWrite for SlowWriter {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<std::io::Result<usize>> {
+            let n = buf.len().min(self.chunk);
+            self.written.extend_from_slice(&buf[..n]);
+            Poll::Ready(Ok(n))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[test]
+    fn test_pipe_to_writes_events() {
+        let builder = ClientBuilder::for_url("http://example.com/stream").unwrap();
+        let mut request = Box::pin(reconnecting_request(builder));
+        request.as_mut().receive_for_test(
+            "event: put\ndata: one\nid: 1\n\n:ping\n\ndata: two\ndata: lines\n\n",
+        );
+        request.as_mut().shutdown();
+
+        let mut writer = SlowWriter {
+            written: Vec::new(),
+            chunk: 3,
+        };
+        block_on(request.as_mut().pipe_to(&mut writer)).unwrap();
+
+        assert_eq!(
+            String::from_utf8(writer.written).unwrap(),
+            "event: put\ndata: one\nid: 1\n\nevent: message\ndata: two\ndata: lines\nid: 1\n\n"
+        );
+    }
+
+    #[test]
+    fn test_label_prefixes_log_messages() {
+        let builder = ClientBuilder::for_url("http://example.com/stream").unwrap();
+        let request = reconnecting_request(builder.label("flags"));
+        assert_eq!(request.props.log_prefix().to_string(), "[flags] ");
+
+        let copied = reconnecting_request(request.to_client_builder());
+        assert_eq!(copied.props.log_prefix().to_string(), "[flags] ");
+
+        let builder = ClientBuilder::for_url("http://example.com/stream").unwrap();
+        let unlabelled = reconnecting_request(builder);
+        assert_eq!(unlabelled.props.log_prefix().to_string(), "");
+    }
+
+    #[test]
+    fn test_shutdown_delivers_buffered_events() {
+        let builder = ClientBuilder::for_url("http://example.com/stream").unwrap();
+        let mut request = Box::pin(reconnecting_request(builder));
+
+        request
+            .as_mut()
+            .project()
+            .event_parser
+            .process_bytes(Bytes::from("data: one\n\ndata: two\n\ndata: partial"))
+            .unwrap();
+        request.as_mut().shutdown();
+
+        for expected in ["one", "two"] {
+            match block_on(request.next()) {
+                Some(Ok(SSE::Event(event))) => assert_eq!(event.data, expected),
+                other => panic!("expected an event, got {:?}", other),
+            }
+        }
+        assert!(block_on(request.next()).is_none());
+        assert!(block_on(request.next()).is_none());
+    }
+
+    #[test_case(LastEventIdOverflow::Truncate, Some("1234"); "truncate")]
+    #[test_case(LastEventIdOverflow::Ignore, Some("1"); "ignore")]
+    fn test_max_last_event_id_len(overflow: LastEventIdOverflow, expected: Option<&str>) {
+        let builder = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .max_last_event_id_len(4, overflow);
+        let mut request = Box::pin(reconnecting_request(builder));
+
+        request
+            .as_mut()
+            .project()
+            .event_parser
+            .process_bytes(Bytes::from("id: 1\ndata: a\n\nid: 123456\ndata: b\n\n"))
+            .unwrap();
+        assert!(matches!(block_on(request.next()), Some(Ok(SSE::Event(_)))));
+        match block_on(request.next()) {
+            Some(Ok(SSE::Event(event))) => assert_eq!(event.id.as_deref(), Some("123456")),
+            other => panic!("expected an event, got {:?}", other),
+        }
+        assert_eq!(request.last_event_id.as_deref(), expected);
+    }
+
+    #[derive(Default)]
+    struct RecordingObserver(std::sync::Mutex<Vec<String>>);
+
+    impl StreamObserver for RecordingObserver {
+        fn on_connecting(&self) {
+            self.0.lock().unwrap().push("connecting".into());
+        }
+
+        fn on_connected(&self, status: StatusCode, _headers: &HeaderMap) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("connected {}", status.as_u16()));
+        }
+
+        fn on_event(&self, event: &Event) {
+            self.0.lock().unwrap().push(format!("event {}", event.data));
+        }
+
+        fn on_disconnected(&self, reason: ReconnectReason) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("disconnected {:?}", reason));
+        }
+
+        fn on_reconnect_scheduled(&self, _delay: Duration) {
+            self.0.lock().unwrap().push("scheduled".into());
+        }
+
+        fn on_error(&self, error: &Error) {
+            let kind = match error {
+                Error::Eof => "eof",
+                Error::UnexpectedResponse(_) => "unexpected response",
+                _ => "other",
+            };
+            self.0.lock().unwrap().push(format!("error {}", kind));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_observer_sees_each_step() {
+        let requests = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = requests.clone();
+        let service = hyper::service::service_fn(move |_: Request<Body>| {
+            let response = match counter.fetch_add(1, Ordering::SeqCst) {
+                0 => Response::builder().body(Body::from("data: a\n\n")),
+                1 => Response::builder().status(503).body(Body::empty()),
+                _ => Response::builder().body(Body::from("data: b\n\n")),
+            };
+            async move { response }
+        });
+        let observer = Arc::new(RecordingObserver::default());
+        let client = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .reconnect(
+                ReconnectOptions::reconnect(true)
+                    .delay(Duration::from_millis(1))
+                    .build(),
+            )
+            .observer(observer.clone())
+            .build_impl(service);
+        let mut request = Box::pin(ReconnectingRequest::new(
+            client.http,
+            client.request_props,
+            None,
+        ));
+
+        for _ in 0..4 {
+            assert!(request.next().await.is_some());
+        }
+        assert_eq!(
+            *observer.0.lock().unwrap(),
+            vec![
+                "connecting",
+                "connected 200",
+                "event a",
+                "disconnected ServerClosed",
+                "scheduled",
+                "error eof",
+                "connecting",
+                // An error status is retried straight away, without a delay.
+                "error unexpected response",
+                "connecting",
+                "connected 200",
+                "event b",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_buffered_events_come_before_a_reconnect() {
+        let last_event_ids = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = last_event_ids.clone();
+        let service = hyper::service::service_fn(move |request: Request<Body>| {
+            let mut seen = seen.lock().unwrap();
+            seen.push(request.headers().get("last-event-id").cloned());
+            // The first connection's events all arrive in one chunk, so they're buffered
+            // together in the parser.
+            let body = match seen.len() {
+                1 => "id: 1\ndata: a\n\nid: 2\ndata: b\n\nid: 3\ndata: c\n\n",
+                _ => "id: 4\ndata: d\n\n",
+            };
+            async move { Ok::<_, std::convert::Infallible>(Response::new(Body::from(body))) }
+        });
+        let client = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .build_impl(service);
+        let mut request = Box::pin(ReconnectingRequest::new(
+            client.http,
+            client.request_props,
+            None,
+        ));
+
+        let mut data = Vec::new();
+        while data.len() < 4 {
+            match request.next().await {
+                Some(Ok(SSE::Event(event))) => {
+                    if event.data == "a" {
+                        // Reconnect while "b" and "c" are still buffered.
+                        request.reconnect_now();
                     }
-                },
-                StateProj::WaitingToReconnect(delay) => {
-                    ready!(delay.poll(cx));
-                    info!("Reconnecting");
-                    self.as_mut().project().state.set(State::New);
+                    data.push(event.data);
                 }
-            };
+                Some(Err(Error::Eof)) => (),
+                other => panic!("unexpected item {:?}", other),
+            }
         }
+        assert_eq!(data, vec!["a", "b", "c", "d"]);
+        assert_eq!(
+            last_event_ids.lock().unwrap()[..2],
+            [None, Some(HeaderValue::from_static("3"))]
+        );
     }
-}
 
-fn uri_from_header(maybe_header: &Option<HeaderValue>) -> Result<Uri> {
-    let header = maybe_header.as_ref().ok_or_else(|| {
-        Error::MalformedLocationHeader(Box::new(std::io::Error::new(
-            ErrorKind::NotFound,
-            "missing Location header",
-        )))
-    })?;
+    #[test_case(200; "accepted")]
+    #[test_case(401; "rejected")]
+    #[tokio::test]
+    async fn test_wait_for_connection(status: u16) {
+        let service = hyper::service::service_fn(move |_: Request<Body>| async move {
+            Response::builder()
+                .status(status)
+                .body(Body::from("data: first\n\n"))
+        });
+        let client = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .emit_resuming(true)
+            .build_impl(service);
+        let mut request = Box::pin(ReconnectingRequest::new(
+            client.http,
+            client.request_props,
+            None,
+        ));
 
-    let header_string = header
-        .to_str()
-        .map_err(|e| Error::MalformedLocationHeader(Box::new(e)))?;
+        let connected = request.as_mut().wait_for_connection().await;
+        if status != 200 {
+            assert!(matches!(connected, Err(Error::UnexpectedResponse(_))));
+            return;
+        }
+        assert!(connected.is_ok());
+        assert!(matches!(request.state, State::Connected(_)));
+        // The resume notice yielded while connecting is still delivered, before any events.
+        assert!(matches!(
+            request.next().await,
+            Some(Ok(SSE::Resuming {
+                last_event_id: None
+            }))
+        ));
+        assert!(matches!(request.next().await, Some(Ok(SSE::Event(e))) if e.data == "first"));
+        assert!(request.as_mut().wait_for_connection().await.is_ok());
+    }
 
-    header_string
-        .parse::<Uri>()
-        .map_err(|e| Error::MalformedLocationHeader(Box::new(e)))
-}
+    #[tokio::test]
+    async fn test_next_event_timeout_leaves_the_connection_open() {
+        let (mut sender, body) = Body::channel();
+        let body = Arc::new(std::sync::Mutex::new(Some(body)));
+        let requests = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = requests.clone();
+        let service = hyper::service::service_fn(move |_: Request<Body>| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            let body = body.lock().unwrap().take().unwrap_or_else(Body::empty);
+            async move { Ok::<_, std::convert::Infallible>(Response::new(body)) }
+        });
+        let client = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .build_impl(service);
+        let mut request = Box::pin(ReconnectingRequest::new(
+            client.http,
+            client.request_props,
+            None,
+        ));
 
-fn delay(dur: Duration, description: &str) -> Sleep {
-    info!("Waiting {:?} before {}", dur, description);
-    tokio::time::sleep(dur)
-}
+        sender
+            .send_data(Bytes::from(":comment\ndata: first\n\ndata: sec"))
+            .await
+            .unwrap();
+        let first = request
+            .as_mut()
+            .next_event_timeout(Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(first.map(|event| event.data).as_deref(), Some("first"));
+        let timed_out = request
+            .as_mut()
+            .next_event_timeout(Duration::from_millis(10))
+            .await
+            .unwrap();
+        assert!(timed_out.is_none());
 
-#[derive(Debug)]
-struct StatusError {
-    status: StatusCode,
-}
+        sender.send_data(Bytes::from("ond\n\n")).await.unwrap();
+        let second = request
+            .as_mut()
+            .next_event_timeout(Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(second.map(|event| event.data).as_deref(), Some("second"));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
 
-impl Display for StatusError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "Invalid status code: {}", self.status)
+    #[tokio::test]
+    async fn test_max_header_size() {
+        let requests = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = requests.clone();
+        let service = hyper::service::service_fn(move |_: Request<Body>| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            let body = format!("id: {}\ndata: a\n\n", "x".repeat(100));
+            async move { Ok::<_, std::convert::Infallible>(Response::new(Body::from(body))) }
+        });
+        let client = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .reconnect(
+                ReconnectOptions::reconnect(true)
+                    .delay(Duration::from_millis(1))
+                    .build(),
+            )
+            .max_header_size(100)
+            .build_with_service(service);
+        let mut stream = client.stream();
+
+        assert!(matches!(stream.next().await, Some(Ok(SSE::Event(_)))));
+        assert!(matches!(stream.next().await, Some(Err(Error::Eof))));
+        // The accept and cache-control headers are sent along with the ID.
+        let expected = "accept".len()
+            + "text/event-stream".len()
+            + 4
+            + "cache-control".len()
+            + "no-cache".len()
+            + 4
+            + "last-event-id".len()
+            + 100
+            + 4;
+        match stream.next().await {
+            Some(Err(Error::HeadersTooLarge(size))) => assert_eq!(size, expected),
+            other => panic!("expected HeadersTooLarge, got {:?}", other),
+        }
+        assert!(matches!(
+            stream.next().await,
+            Some(Err(Error::StreamClosed))
+        ));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_to_client_builder_resumes_from_last_event_id() {
+        let builder = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .header("X-Tenant", "acme")
+            .unwrap()
+            .read_timeout(Duration::from_secs(7))
+            .redirect_limit(2);
+        let mut request = Box::pin(reconnecting_request(builder));
+
+        request
+            .as_mut()
+            .project()
+            .event_parser
+            .process_bytes(Bytes::from("id: 42\ndata: hello\n\n"))
+            .unwrap();
+        assert!(matches!(block_on(request.next()), Some(Ok(SSE::Event(_)))));
+
+        let snapshot = request.to_client_builder().describe();
+        assert_eq!(snapshot.url, "http://example.com/stream");
+        assert_eq!(snapshot.last_event_id.as_deref(), Some("42"));
+        assert_eq!(snapshot.read_timeout, Some(Duration::from_secs(7)));
+        assert_eq!(snapshot.redirect_limit, 2);
+        assert!(snapshot
+            .headers
+            .contains(&("x-tenant".to_string(), "acme".to_string())));
+    }
+
+    #[test_case(301; "moved permanently")]
+    #[test_case(302; "found")]
+    #[tokio::test]
+    async fn test_redirect_without_location_is_fatal(status: u16) {
+        let service = hyper::service::service_fn(move |_: Request<Body>| async move {
+            Ok::<_, std::convert::Infallible>(
+                Response::builder()
+                    .status(status)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        });
+        let client = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .build_with_service(service);
+        let mut stream = client.stream();
+
+        match stream.next().await {
+            Some(Err(Error::RedirectWithoutLocation(code))) => assert_eq!(code, status),
+            other => panic!("expected a missing Location error, got {:?}", other),
+        }
+        assert!(matches!(
+            stream.next().await,
+            Some(Err(Error::StreamClosed))
+        ));
+    }
+
+    #[test]
+    fn test_no_default_headers() {
+        let builder = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .no_default_headers()
+            .header("Accept", "*/*")
+            .unwrap();
+        let request = reconnecting_request(builder);
+
+        let built = request.build_request().unwrap();
+        let headers: Vec<_> = built.headers().iter().collect();
+        assert_eq!(
+            headers,
+            vec![(&hyper::header::ACCEPT, &HeaderValue::from_static("*/*"))]
+        );
+    }
+
+    #[test]
+    fn test_origin_header() {
+        let builder = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .origin("https://app.example.com:8443")
+            .unwrap();
+        let request = reconnecting_request(builder);
+
+        let built = request.build_request().unwrap();
+        assert_eq!(
+            built.headers().get(hyper::header::ORIGIN),
+            Some(&HeaderValue::from_static("https://app.example.com:8443"))
+        );
+    }
+
+    #[test_case("https://example.com"; "scheme and host")]
+    #[test_case("null"; "opaque origin")]
+    fn test_origin_accepts(origin: &str) {
+        assert!(ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .origin(origin)
+            .is_ok());
+    }
+
+    #[test_case("example.com"; "missing scheme")]
+    #[test_case("https://example.com/app"; "with path")]
+    #[test_case("https://exa mple.com"; "unparseable")]
+    fn test_origin_rejects(origin: &str) {
+        let result = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .origin(origin);
+        assert!(matches!(result, Err(Error::InvalidParameter(_))));
     }
-}
 
-impl std::error::Error for StatusError {}
+    #[test]
+    fn test_describe_redacts_credentials() {
+        let builder = ClientBuilder::for_url("https://example.com/stream")
+            .unwrap()
+            .header("Authorization", "Bearer secret")
+            .unwrap()
+            .header("Cookie", "session=secret")
+            .unwrap()
+            .header("X-Tenant", "acme")
+            .unwrap()
+            .redirect_limit(3);
+
+        let snapshot = builder.describe();
+        assert_eq!(snapshot.url, "https://example.com/stream");
+        assert_eq!(snapshot.redirect_limit, 3);
+        assert!(snapshot
+            .headers
+            .contains(&("authorization".to_string(), REDACTED.to_string())));
+        assert!(snapshot
+            .headers
+            .contains(&("x-tenant".to_string(), "acme".to_string())));
+
+        let printed = format!("{} {:?}", snapshot, snapshot);
+        assert!(!printed.contains("secret"));
+        assert!(printed.contains("x-tenant: acme"));
+    }
+
+    #[test]
+    fn test_extensions_are_set_on_request() {
+        #[derive(Clone, Debug, PartialEq)]
+        struct Tenant(&'static str);
+
+        let builder = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .extension(Tenant("first"))
+            .extension(Tenant("second"))
+            .extension(42u32);
+        let request = reconnecting_request(builder).build_request().unwrap();
+
+        assert_eq!(request.extensions().get(), Some(&Tenant("second")));
+        assert_eq!(request.extensions().get(), Some(&42u32));
+    }
+
+    #[test]
+    fn test_backoff_change_is_reported_once_per_transition() {
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = events.clone();
+        let builder = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .reconnect(
+                ReconnectOptions::reconnect(true)
+                    .delay(Duration::from_millis(10))
+                    .backoff_factor(2)
+                    .delay_max(Duration::from_millis(20))
+                    .build(),
+            )
+            .on_backoff_change(move |event| recorded.lock().unwrap().push(event));
+        let mut request = Box::pin(reconnecting_request(builder));
+
+        request.as_mut().next_reconnect_delay();
+        assert!(events.lock().unwrap().is_empty());
+
+        request.as_mut().next_reconnect_delay();
+        request.as_mut().next_reconnect_delay();
+        assert_eq!(*events.lock().unwrap(), vec![BackoffEvent::ReachedMax]);
+
+        request.as_mut().reset_backoff();
+        request.as_mut().reset_backoff();
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![BackoffEvent::ReachedMax, BackoffEvent::Recovered]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_initial_reconnect_opts_until_stable() {
+        let opts = |secs| {
+            ReconnectOptions::reconnect(true)
+                .delay(Duration::from_secs(secs))
+                .delay_max(Duration::from_secs(secs * 2))
+                .build()
+        };
+        let builder = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .reconnect(opts(100))
+            .initial_reconnect(opts(1));
+        let mut request = Box::pin(reconnecting_request(builder));
+        let connected_for = |request: &mut Pin<Box<ReconnectingRequest<_>>>, secs| {
+            request.as_mut().reset_backoff();
+            *request.as_mut().project().connected_since =
+                Instant::now().checked_sub(Duration::from_secs(secs));
+        };
+
+        assert!(request.as_mut().next_retry_delay(false) <= Duration::from_secs(1));
+        connected_for(&mut request, 10);
+        assert!(request.as_mut().next_retry_delay(false) <= Duration::from_secs(2));
+
+        let builder = request.to_client_builder();
+        assert_eq!(builder.props.reconnect_opts.delay, Duration::from_secs(100));
+        assert_eq!(
+            builder.props.initial_reconnect_opts.map(|opts| opts.delay),
+            Some(Duration::from_secs(1))
+        );
+
+        connected_for(&mut request, 61);
+        let delay = request.as_mut().next_retry_delay(false);
+        assert!(delay >= Duration::from_secs(50), "{:?}", delay);
+        assert_eq!(request.props.reconnect_opts.delay, Duration::from_secs(100));
+        assert!(request
+            .to_client_builder()
+            .props
+            .initial_reconnect_opts
+            .is_none());
+
+        // The first reconnect after a stable connection the server closed uses the steady
+        // options too.
+        let sleeps = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let service = hyper::service::service_fn(|_| async {
+            Ok::<_, std::convert::Infallible>(Response::new(Body::from("data: hello\n\n")))
+        });
+        let client = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .reconnect(opts(100))
+            .initial_reconnect(opts(1))
+            .timer(ImmediateTimer(sleeps.clone()))
+            .build_impl(service);
+        let mut request = Box::pin(ReconnectingRequest::new(
+            client.http,
+            client.request_props,
+            None,
+        ));
+        assert!(matches!(request.next().await, Some(Ok(SSE::Event(_)))));
+        *request.as_mut().project().connected_since =
+            Instant::now().checked_sub(Duration::from_secs(61));
+        assert!(matches!(request.next().await, Some(Err(Error::Eof))));
+        let delay = *sleeps.lock().unwrap().last().unwrap();
+        assert!(delay >= Duration::from_secs(50), "{:?}", delay);
+    }
+
+    #[test]
+    fn test_update_reconnect_opts_applies_to_next_delay() {
+        let builder = ClientBuilder::for_url("http://example.com/stream")
+            .unwrap()
+            .reconnect(
+                ReconnectOptions::reconnect(true)
+                    .delay(Duration::from_secs(10))
+                    .backoff_factor(2)
+                    .delay_max(Duration::from_secs(60))
+                    .build(),
+            );
+        let mut request = Box::pin(reconnecting_request(builder));
+        request.as_mut().next_reconnect_delay();
+        request.as_mut().next_reconnect_delay();
+
+        let sleep: SleepFuture = Box::pin(futures::future::pending());
+        request
+            .as_mut()
+            .project()
+            .state
+            .set(State::WaitingToReconnect(sleep));
+
+        request.as_mut().update_reconnect_opts(
+            ReconnectOptions::reconnect(false)
+                .delay(Duration::from_secs(10))
+                .backoff_factor(2)
+                .delay_max(Duration::from_secs(15))
+                .build(),
+        );
+
+        assert!(matches!(request.state, State::WaitingToReconnect(_)));
+        assert!(!request.props.reconnect_opts.reconnect);
+        let delay = request.as_mut().next_reconnect_delay();
+        assert!(delay <= Duration::from_secs(15), "{:?}", delay);
+    }
+
+    #[test_case(Some("text/event-stream"), true; "plain")]
+    #[test_case(Some("text/event-stream; charset=utf-8"), true; "with parameters")]
+    #[test_case(Some("Text/Event-Stream"), true; "mixed case")]
+    #[test_case(Some("TEXT/EVENT-STREAM; CHARSET=UTF-8"), true; "uppercase")]
+    #[test_case(Some("  text/event-stream ; charset=UTF-8"), true; "extra whitespace")]
+    #[test_case(Some("text/event-stream\t;charset=utf-8"), true; "tab before parameters")]
+    #[test_case(Some("text/event-stream; charset=utf-8; q=\"a;b\""), true; "multiple parameters")]
+    #[test_case(Some("text/event-stream;"), true; "empty parameters")]
+    #[test_case(Some("text/event-streams"), false; "longer subtype")]
+    #[test_case(Some("text/plain; type=text/event-stream"), false; "type in parameter")]
+    #[test_case(Some("event-stream"), false; "no type")]
+    #[test_case(Some("text/html"), false; "html")]
+    #[test_case(None, false; "missing")]
+    fn test_is_event_stream(content_type: Option<&'static str>, expected: bool) {
+        assert_eq!(
+            is_event_stream(&response_with_content_type(content_type)),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_is_event_stream_allows_non_ascii_parameters() {
+        let resp = hyper::Response::builder()
+            .header(
+                hyper::header::CONTENT_TYPE,
+                HeaderValue::from_bytes(b"text/event-stream; title=\"caf\xc3\xa9\"").unwrap(),
+            )
+            .body(())
+            .unwrap();
+        assert!(is_event_stream(&resp));
+    }
+
+    #[test]
+    fn test_is_event_stream_ignores_content_encoding() {
+        let resp = hyper::Response::builder()
+            .header(hyper::header::CONTENT_TYPE, "text/event-stream")
+            .header(hyper::header::CONTENT_ENCODING, "gzip")
+            .body(())
+            .unwrap();
+        assert!(is_event_stream(&resp));
+    }
+}
 
 mod private {
     use crate::client::ClientImpl;
 
     pub trait Sealed {}
-    impl<C> Sealed for ClientImpl<C> {}
+    impl<S> Sealed for ClientImpl<S> {}
 }
@@ -1,4 +1,12 @@
-use std::time::Duration;
+use std::{
+    env,
+    time::{Duration, Instant},
+};
+
+use hyper::StatusCode;
+
+use crate::error::{Error, Result};
+use crate::retry::{BackoffRetry, RetryStrategy};
 
 /// Configuration for a [`Client`]'s reconnect behaviour.
 ///
@@ -27,6 +35,15 @@ pub struct ReconnectOptions {
     pub(crate) delay: Duration,
     pub(crate) backoff_factor: u32,
     pub(crate) delay_max: Duration,
+    pub(crate) honor_server_retry: bool,
+    pub(crate) min_reconnect_interval: Duration,
+    pub(crate) reconnect_grace: Duration,
+    pub(crate) dns_retry_delay: Option<Duration>,
+    pub(crate) dns_delay_max: Option<Duration>,
+    pub(crate) accept_statuses: Option<Vec<StatusCode>>,
+    pub(crate) circuit_breaker: Option<CircuitConfig>,
+    pub(crate) strategy: ReconnectStrategy,
+    pub(crate) retry_budget: Option<(u32, Duration)>,
 }
 
 impl ReconnectOptions {
@@ -41,6 +58,191 @@ impl ReconnectOptions {
     pub fn reconnect(reconnect: bool) -> ReconnectOptionsBuilder {
         ReconnectOptionsBuilder::new(reconnect)
     }
+
+    /// Read reconnect options from environment variables named with the given prefix, using the
+    /// [default] for any that are unset:
+    ///
+    /// | Variable                             | Setting                      |
+    /// |--------------------------------------|------------------------------|
+    /// | `{prefix}_RECONNECT`                 | [`reconnect`]                |
+    /// | `{prefix}_RETRY_INITIAL`             | [`retry_initial`]            |
+    /// | `{prefix}_DELAY_MS`                  | [`delay`]                    |
+    /// | `{prefix}_DELAY_MAX_MS`              | [`delay_max`]                |
+    /// | `{prefix}_BACKOFF_FACTOR`            | [`backoff_factor`]           |
+    /// | `{prefix}_HONOR_SERVER_RETRY`        | [`honor_server_retry`]       |
+    /// | `{prefix}_MIN_RECONNECT_INTERVAL_MS` | [`min_reconnect_interval`]   |
+    /// | `{prefix}_RECONNECT_GRACE_MS`        | [`reconnect_grace`]          |
+    /// | `{prefix}_DNS_RETRY_DELAY_MS`        | [`dns_retry_delay`]          |
+    /// | `{prefix}_DNS_DELAY_MAX_MS`          | [`dns_delay_max`]            |
+    /// | `{prefix}_ACCEPT_STATUSES`           | [`accept_statuses`]          |
+    ///
+    /// Durations are whole milliseconds, booleans are `true`/`false` or `1`/`0`, and statuses are
+    /// a comma-separated list of codes such as `200,206`. A value that
+    /// doesn't parse, a backoff factor below 1, or a maximum delay shorter than the delay is
+    /// reported as [`Error::InvalidParameter`] naming the variable.
+    ///
+    /// [default]: #method.default
+    /// [`reconnect`]: #method.reconnect
+    /// [`retry_initial`]: struct.ReconnectOptionsBuilder.html#method.retry_initial
+    /// [`delay`]: struct.ReconnectOptionsBuilder.html#method.delay
+    /// [`delay_max`]: struct.ReconnectOptionsBuilder.html#method.delay_max
+    /// [`backoff_factor`]: struct.ReconnectOptionsBuilder.html#method.backoff_factor
+    /// [`honor_server_retry`]: struct.ReconnectOptionsBuilder.html#method.honor_server_retry
+    /// [`min_reconnect_interval`]: struct.ReconnectOptionsBuilder.html#method.min_reconnect_interval
+    /// [`reconnect_grace`]: struct.ReconnectOptionsBuilder.html#method.reconnect_grace
+    /// [`dns_retry_delay`]: struct.ReconnectOptionsBuilder.html#method.dns_retry_delay
+    /// [`dns_delay_max`]: struct.ReconnectOptionsBuilder.html#method.dns_delay_max
+    /// [`accept_statuses`]: struct.ReconnectOptionsBuilder.html#method.accept_statuses
+    pub fn from_env(prefix: &str) -> Result<ReconnectOptions> {
+        Self::from_lookup(prefix, |key| env::var(key))
+    }
+
+    /// Like [`from_env`](ReconnectOptions::from_env), but reading each variable with `lookup`.
+    fn from_lookup<L>(prefix: &str, lookup: L) -> Result<ReconnectOptions>
+    where
+        L: Fn(&str) -> std::result::Result<String, env::VarError>,
+    {
+        let defaults = ReconnectOptions::default();
+        let millis = |value: &str| value.parse().ok().map(Duration::from_millis);
+
+        let opts = ReconnectOptions {
+            reconnect: env_value(&lookup, prefix, "RECONNECT", "a boolean", parse_bool)?
+                .unwrap_or(defaults.reconnect),
+            retry_initial: env_value(&lookup, prefix, "RETRY_INITIAL", "a boolean", parse_bool)?
+                .unwrap_or(defaults.retry_initial),
+            delay: env_value(&lookup, prefix, "DELAY_MS", "milliseconds", millis)?
+                .unwrap_or(defaults.delay),
+            delay_max: env_value(&lookup, prefix, "DELAY_MAX_MS", "milliseconds", millis)?
+                .unwrap_or(defaults.delay_max),
+            backoff_factor: env_value(
+                &lookup,
+                prefix,
+                "BACKOFF_FACTOR",
+                "an integer of at least 1",
+                |v| v.parse().ok().filter(|factor| *factor >= 1),
+            )?
+            .unwrap_or(defaults.backoff_factor),
+            honor_server_retry: env_value(
+                &lookup,
+                prefix,
+                "HONOR_SERVER_RETRY",
+                "a boolean",
+                parse_bool,
+            )?
+            .unwrap_or(defaults.honor_server_retry),
+            min_reconnect_interval: env_value(
+                &lookup,
+                prefix,
+                "MIN_RECONNECT_INTERVAL_MS",
+                "milliseconds",
+                millis,
+            )?
+            .unwrap_or(defaults.min_reconnect_interval),
+            reconnect_grace: env_value(
+                &lookup,
+                prefix,
+                "RECONNECT_GRACE_MS",
+                "milliseconds",
+                millis,
+            )?
+            .unwrap_or(defaults.reconnect_grace),
+            dns_retry_delay: env_value(
+                &lookup,
+                prefix,
+                "DNS_RETRY_DELAY_MS",
+                "milliseconds",
+                millis,
+            )?,
+            dns_delay_max: env_value(&lookup, prefix, "DNS_DELAY_MAX_MS", "milliseconds", millis)?,
+            accept_statuses: env_value(
+                &lookup,
+                prefix,
+                "ACCEPT_STATUSES",
+                "HTTP status codes",
+                |v| {
+                    v.split(',')
+                        .map(|code| StatusCode::from_bytes(code.trim().as_bytes()).ok())
+                        .collect()
+                },
+            )?,
+            // The circuit breaker, strategy and retry budget can only be configured in code.
+            circuit_breaker: defaults.circuit_breaker,
+            strategy: defaults.strategy,
+            retry_budget: defaults.retry_budget,
+        };
+
+        if opts.delay_max < opts.delay {
+            return Err(invalid_env(format!(
+                "{}_DELAY_MAX_MS: maximum delay {:?} is shorter than the delay {:?}",
+                prefix, opts.delay_max, opts.delay
+            )));
+        }
+
+        Ok(opts)
+    }
+
+    /// The delays the client would wait before each of the first `attempts` reconnects, if every
+    /// attempt failed, for tuning [`delay`](ReconnectOptionsBuilder::delay),
+    /// [`backoff_factor`](ReconnectOptionsBuilder::backoff_factor) and
+    /// [`delay_max`](ReconnectOptionsBuilder::delay_max).
+    ///
+    /// The client randomizes each delay to between half and all of the value shown, so these are
+    /// the longest delays it will wait. They don't account for a `retry:` sent by the server,
+    /// the [`min_reconnect_interval`](ReconnectOptionsBuilder::min_reconnect_interval), or
+    /// separate [`dns_retry_delay`](ReconnectOptionsBuilder::dns_retry_delay) settings, and
+    /// are those of [`ReconnectStrategy::Exponential`] whichever strategy is configured.
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use eventsource_client::ReconnectOptions;
+    /// let opts = ReconnectOptions::reconnect(true)
+    ///     .delay(Duration::from_secs(1))
+    ///     .backoff_factor(2)
+    ///     .delay_max(Duration::from_secs(5))
+    ///     .build();
+    /// let secs: Vec<_> = opts.preview_schedule(5).iter().map(Duration::as_secs).collect();
+    /// assert_eq!(secs, vec![1, 2, 4, 5, 5]);
+    /// ```
+    pub fn preview_schedule(&self, attempts: usize) -> Vec<Duration> {
+        let mut strategy =
+            BackoffRetry::new(self.delay, self.delay_max, self.backoff_factor, false);
+        let now = Instant::now();
+        (0..attempts).map(|_| strategy.next_delay(now)).collect()
+    }
+
+    /// The initial and maximum delays for retrying after a resolution failure, or `None` if they
+    /// aren't configured separately from the general ones.
+    pub(crate) fn dns_delays(&self) -> Option<(Duration, Duration)> {
+        if self.dns_retry_delay.is_none() && self.dns_delay_max.is_none() {
+            return None;
+        }
+        Some((
+            self.dns_retry_delay.unwrap_or(self.delay),
+            self.dns_delay_max.unwrap_or(self.delay_max),
+        ))
+    }
+
+    /// Whether a response with `status` should be treated as the start of the event stream.
+    pub(crate) fn accepts_status(&self, status: StatusCode) -> bool {
+        match &self.accept_statuses {
+            Some(statuses) => statuses.contains(&status),
+            None => status.is_success(),
+        }
+    }
+
+    /// The base reconnect delay to use after the server sent a `retry:` field of `retry_ms`
+    /// milliseconds, or `None` if server-provided values are ignored.
+    pub(crate) fn server_retry_delay(&self, retry_ms: u64) -> Option<Duration> {
+        if !self.honor_server_retry {
+            return None;
+        }
+
+        let requested = Duration::from_millis(retry_ms);
+        Some(std::cmp::max(
+            self.delay,
+            std::cmp::min(requested, self.delay_max),
+        ))
+    }
 }
 
 impl Default for ReconnectOptions {
@@ -51,7 +253,8 @@ impl Default for ReconnectOptions {
     /// The client will wait before each reconnect attempt, to allow time for
     /// the error condition to be resolved (e.g. for the SSE server to restart
     /// if it went down). It will wait 1 second before the first attempt, and
-    /// then back off exponentially, up to a maximum wait of 1 minute.
+    /// then back off exponentially, up to a maximum wait of 1 minute. A `retry:` value sent by
+    /// the server is honoured within those bounds.
     fn default() -> ReconnectOptions {
         ReconnectOptions {
             retry_initial: false,
@@ -59,10 +262,60 @@ impl Default for ReconnectOptions {
             delay: Duration::from_secs(1),
             backoff_factor: 2,
             delay_max: Duration::from_secs(60),
+            honor_server_retry: true,
+            min_reconnect_interval: Duration::ZERO,
+            reconnect_grace: Duration::ZERO,
+            dns_retry_delay: None,
+            dns_delay_max: None,
+            accept_statuses: None,
+            circuit_breaker: None,
+            strategy: ReconnectStrategy::default(),
+            retry_budget: None,
+        }
+    }
+}
+
+/// Read `{prefix}_{name}` with `lookup` and parse it, or `None` if it isn't set.
+fn env_value<T>(
+    lookup: impl Fn(&str) -> std::result::Result<String, env::VarError>,
+    prefix: &str,
+    name: &str,
+    expected: &str,
+    parse: impl FnOnce(&str) -> Option<T>,
+) -> Result<Option<T>> {
+    let key = format!("{}_{}", prefix, name);
+    let value = match lookup(&key) {
+        Ok(value) => value,
+        Err(env::VarError::NotPresent) => return Ok(None),
+        Err(env::VarError::NotUnicode(_)) => {
+            return Err(invalid_env(format!("{}: value is not valid unicode", key)))
         }
+    };
+
+    match parse(value.trim()) {
+        Some(parsed) => Ok(Some(parsed)),
+        None => Err(invalid_env(format!(
+            "{}: expected {}, got {:?}",
+            key, expected, value
+        ))),
+    }
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "1" => Some(true),
+        "false" | "0" => Some(false),
+        _ => None,
     }
 }
 
+fn invalid_env(message: String) -> Error {
+    Error::InvalidParameter(Box::new(std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        message,
+    )))
+}
+
 /// Builder for [`ReconnectOptions`].
 ///
 /// [`ReconnectOptions`]: struct.ReconnectOptions.html
@@ -133,8 +386,499 @@ impl ReconnectOptionsBuilder {
         self
     }
 
+    /// Configure whether to use the reconnect delay requested by the server in an event's
+    /// `retry:` field (the [default] is `true`).
+    ///
+    /// A server-provided delay replaces the configured [`delay`] as the base of the backoff, but
+    /// is clamped so that it is never shorter than [`delay`] nor longer than [`delay_max`]. Set
+    /// this to `false` to ignore `retry:` fields entirely.
+    ///
+    /// [default]: struct.ReconnectOptions.html#method.default
+    /// [`delay`]: #method.delay
+    /// [`delay_max`]: #method.delay_max
+    pub fn honor_server_retry(mut self, honor: bool) -> Self {
+        self.opts.honor_server_retry = honor;
+        self
+    }
+
+    /// Configure the minimum time between the starts of two consecutive connection attempts
+    /// (the [default] is zero, i.e. no minimum).
+    ///
+    /// Unlike [`delay`], this floor does not depend on the backoff state: it still applies when
+    /// the backoff has been reset by a brief successful connection, or when a connection attempt
+    /// is retried without a delay. Redirects are still followed straight away.
+    ///
+    /// [default]: struct.ReconnectOptions.html#method.default
+    /// [`delay`]: #method.delay
+    pub fn min_reconnect_interval(mut self, interval: Duration) -> Self {
+        self.opts.min_reconnect_interval = interval;
+        self
+    }
+
+    /// Configure the minimum wait before reconnecting after an established connection is lost
+    /// (the [default] is zero, i.e. no minimum).
+    ///
+    /// Some servers limit the connections each client may have open, and take a moment to
+    /// release a closed one, so an immediate reconnect is rejected. This grace applies only to
+    /// the first attempt after a connection that had been established, however it ended; if
+    /// the backoff [`delay`] is longer anyway, that is used, and attempts that follow a failed
+    /// one back off as usual. Unlike the backoff, it also applies to
+    /// [`ReconnectingRequest::reconnect_now`].
+    ///
+    /// [default]: struct.ReconnectOptions.html#method.default
+    /// [`delay`]: #method.delay
+    /// [`ReconnectingRequest::reconnect_now`]: crate::ReconnectingRequest::reconnect_now
+    pub fn reconnect_grace(mut self, grace: Duration) -> Self {
+        self.opts.reconnect_grace = grace;
+        self
+    }
+
+    /// Configure a separate initial delay for retrying after the server's host name couldn't be
+    /// resolved (by default the [`delay`] is used).
+    ///
+    /// Resolution failures often take longer to clear up than, say, a refused connection, so
+    /// they can be given their own, longer, backoff. It grows by the same [`backoff_factor`], up
+    /// to [`dns_delay_max`], and is reset by a successful connection like the general one.
+    ///
+    /// [`delay`]: #method.delay
+    /// [`backoff_factor`]: #method.backoff_factor
+    /// [`dns_delay_max`]: #method.dns_delay_max
+    pub fn dns_retry_delay(mut self, delay: Duration) -> Self {
+        self.opts.dns_retry_delay = Some(delay);
+        self
+    }
+
+    /// Configure the maximum delay between retries after the server's host name couldn't be
+    /// resolved (by default the [`delay_max`] is used). See [`dns_retry_delay`].
+    ///
+    /// [`delay_max`]: #method.delay_max
+    /// [`dns_retry_delay`]: #method.dns_retry_delay
+    pub fn dns_delay_max(mut self, max: Duration) -> Self {
+        self.opts.dns_delay_max = Some(max);
+        self
+    }
+
+    /// Configure which response statuses start the event stream (by default, any 2xx status).
+    ///
+    /// This replaces the default check entirely, so list every status that should be accepted,
+    /// for example `[StatusCode::OK, StatusCode::PARTIAL_CONTENT]`. A response with any other
+    /// status is reported as an [`Error::UnexpectedResponse`] and the request is retried, or
+    /// followed if it is a redirect. A `204 No Content` response ends the stream whether or not
+    /// it is listed, as the SSE specification has servers use it to stop clients reconnecting.
+    pub fn accept_statuses(mut self, statuses: impl IntoIterator<Item = StatusCode>) -> Self {
+        self.opts.accept_statuses = Some(statuses.into_iter().collect());
+        self
+    }
+
+    /// Configure a circuit breaker, which pauses reconnects for a cooldown once connections
+    /// have failed too often (by [default] there is none).
+    ///
+    /// Every reconnect or retry counts as a failure, except one the client asked for itself,
+    /// such as with [`ReconnectingRequest::reconnect_now`]. Once the breaker opens, the next
+    /// attempt waits for at least the cooldown. If that attempt connects the breaker closes
+    /// again; if it fails, the breaker opens for another cooldown. The backoff continues as
+    /// usual throughout. See [`ReconnectingRequest::circuit_state`].
+    ///
+    /// [default]: struct.ReconnectOptions.html#method.default
+    /// [`ReconnectingRequest::reconnect_now`]: crate::ReconnectingRequest::reconnect_now
+    /// [`ReconnectingRequest::circuit_state`]: crate::ReconnectingRequest::circuit_state
+    pub fn circuit_breaker(mut self, config: CircuitConfig) -> Self {
+        self.opts.circuit_breaker = Some(config);
+        self
+    }
+
+    /// Configure how the delay before each reconnect is chosen (by [default], an exponential
+    /// backoff). See [`ReconnectStrategy`].
+    ///
+    /// [default]: struct.ReconnectOptions.html#method.default
+    pub fn strategy(mut self, strategy: ReconnectStrategy) -> Self {
+        self.opts.strategy = strategy;
+        self
+    }
+
+    /// Allow at most `max_reconnects` reconnects within any `window` (by [default] there is no
+    /// limit). Once that many have been made, the stream yields
+    /// [`Error::RetryBudgetExhausted`] and closes instead of reconnecting again. This bounds
+    /// the reconnects during an outage that is too slow to trip a
+    /// [circuit breaker](Self::circuit_breaker). Reconnects the client asked for itself, such as
+    /// with [`ReconnectingRequest::reconnect_now`], and redirects don't count.
+    ///
+    /// [default]: struct.ReconnectOptions.html#method.default
+    /// [`ReconnectingRequest::reconnect_now`]: crate::ReconnectingRequest::reconnect_now
+    pub fn retry_budget(mut self, max_reconnects: u32, window: Duration) -> Self {
+        self.opts.retry_budget = Some((max_reconnects, window));
+        self
+    }
+
     /// Finish building the `ReconnectOptions`.
     pub fn build(self) -> ReconnectOptions {
         self.opts
     }
 }
+
+/// How a stream chooses the delay before each reconnect, set with
+/// [`ReconnectOptionsBuilder::strategy`]. Retries after a host name couldn't be resolved always
+/// use the exponential backoff.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReconnectStrategy {
+    /// Start from the [`delay`](ReconnectOptionsBuilder::delay) and multiply it by the
+    /// [`backoff_factor`](ReconnectOptionsBuilder::backoff_factor) after each failure, up to
+    /// the [`delay_max`](ReconnectOptionsBuilder::delay_max) (the default). The backoff starts
+    /// again once a connection has stayed up for a minute.
+    #[default]
+    Exponential,
+    /// Like `Exponential`, but learning from how long connections stay up. While connections
+    /// keep failing fast, delays are up to half as long again, to be cautious. While they are
+    /// generally stable and only occasionally drop, delays are down to half as long, and a
+    /// dropped connection starts the backoff again however briefly it was up. The average time
+    /// connections have stayed up is reported by
+    /// [`ReconnectingRequest::average_uptime`](crate::ReconnectingRequest::average_uptime).
+    Adaptive,
+}
+
+/// When the circuit breaker set with [`ReconnectOptionsBuilder::circuit_breaker`] opens.
+///
+/// ```
+/// # use std::time::Duration;
+/// # use eventsource_client::{CircuitConfig, ReconnectOptions};
+/// // Pause for five minutes after ten failures within a minute.
+/// let opts = ReconnectOptions::reconnect(true)
+///     .circuit_breaker(CircuitConfig::new(
+///         10,
+///         Duration::from_secs(60),
+///         Duration::from_secs(300),
+///     ))
+///     .build();
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CircuitConfig {
+    pub(crate) max_failures: u32,
+    pub(crate) window: Duration,
+    pub(crate) cooldown: Duration,
+}
+
+impl CircuitConfig {
+    /// Open the circuit once `max_failures` failures have happened within `window`, pausing
+    /// reconnects for `cooldown`. A `max_failures` of zero is treated as one.
+    pub fn new(max_failures: u32, window: Duration, cooldown: Duration) -> Self {
+        CircuitConfig {
+            max_failures: max_failures.max(1),
+            window,
+            cooldown,
+        }
+    }
+}
+
+/// The state of a stream's circuit breaker, as returned by
+/// [`ReconnectingRequest::circuit_state`](crate::ReconnectingRequest::circuit_state).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Reconnects happen as usual (the default, and the only state without a circuit breaker).
+    #[default]
+    Closed,
+    /// Too many connections have failed, and the stream is waiting out the cooldown.
+    Open,
+    /// The cooldown is over, and the stream is making one attempt to see whether the server
+    /// has recovered.
+    HalfOpen,
+}
+
+/// How the data of each event is encoded, set with
+/// [`ClientBuilder::data_encoding`](crate::ClientBuilder::data_encoding).
+///
+/// Line breaks are ignored when decoding, so an encoded value may be split over several `data:`
+/// lines. The decoded data must be valid UTF-8.
+#[cfg(feature = "data-encoding")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DataEncoding {
+    /// The data is delivered as received (the default).
+    #[default]
+    Raw,
+    /// The data is base64, with the standard alphabet and padding.
+    Base64,
+    /// The data is hexadecimal, in either case.
+    Hex,
+}
+
+#[cfg(feature = "data-encoding")]
+impl DataEncoding {
+    /// Decode event data of this encoding.
+    pub(crate) fn decode(self, data: &str) -> std::result::Result<String, crate::client::BoxError> {
+        let encoded: Vec<u8> = data.bytes().filter(|b| *b != b'\n').collect();
+        let decoded = match self {
+            DataEncoding::Raw => return Ok(data.to_string()),
+            DataEncoding::Base64 => {
+                use base64::Engine;
+                base64::engine::general_purpose::STANDARD.decode(encoded)?
+            }
+            DataEncoding::Hex => decode_hex(&encoded)?,
+        };
+        Ok(String::from_utf8(decoded)?)
+    }
+}
+
+#[cfg(feature = "data-encoding")]
+fn decode_hex(encoded: &[u8]) -> std::result::Result<Vec<u8>, std::io::Error> {
+    let invalid = |message: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+    if !encoded.len().is_multiple_of(2) {
+        return Err(invalid("hex data has an odd number of digits"));
+    }
+    let digit = |b: u8| {
+        (b as char)
+            .to_digit(16)
+            .ok_or_else(|| invalid("hex data contains a character that isn't a hex digit"))
+    };
+    encoded
+        .chunks(2)
+        .map(|pair| Ok((digit(pair[0])? * 16 + digit(pair[1])?) as u8))
+        .collect()
+}
+
+/// What the client does with an event ID longer than the limit set by
+/// [`ClientBuilder::max_last_event_id_len`](crate::ClientBuilder::max_last_event_id_len).
+///
+/// Either way the event itself is delivered with its full ID; this only affects the ID that is
+/// remembered and sent as `Last-Event-ID` when reconnecting.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LastEventIdOverflow {
+    /// Store the ID truncated to the limit (the default).
+    #[default]
+    Truncate,
+    /// Don't store the ID, keeping the previously stored one.
+    Ignore,
+}
+
+/// What the client does with an event whose type isn't one of those declared with
+/// [`ClientBuilder::expected_event_types`](crate::ClientBuilder::expected_event_types).
+///
+/// Either way a warning is logged and the event is counted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EventTypePolicy {
+    /// Deliver the event as usual (the default).
+    #[default]
+    Warn,
+    /// Yield [`Error::UnexpectedEventType`](crate::Error::UnexpectedEventType) in place of the
+    /// event. The stream stays connected.
+    Error,
+}
+
+/// The oldest TLS version that [`ClientBuilder::build`](crate::ClientBuilder::build) will
+/// negotiate, set with [`ClientBuilder::min_tls_version`](crate::ClientBuilder::min_tls_version).
+/// Versions before TLS 1.2 aren't supported at all.
+#[cfg(feature = "rustls")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TlsVersion {
+    /// TLS 1.2 or 1.3 (the default).
+    #[default]
+    Tls12,
+    /// TLS 1.3 only.
+    Tls13,
+}
+
+/// What keeps a connection's read timeout, set with
+/// [`ClientBuilder::read_timeout`](crate::ClientBuilder::read_timeout), from expiring.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReadTimeoutPolicy {
+    /// Any bytes received on the connection, including comments used as keep-alives (the
+    /// default).
+    #[default]
+    AnyBytes,
+    /// Only events. A server that sends nothing but keep-alives for the length of the timeout
+    /// is treated as having timed out.
+    EventsOnly,
+    /// Events and comments, but not other data such as blank lines or fields of an event that
+    /// hasn't been completed.
+    EventsAndComments,
+}
+
+/// How the client should handle a successful response whose `Content-Type` is not
+/// `text/event-stream`.
+///
+/// This only applies to 2xx responses; other statuses are always reported as
+/// [`Error::UnexpectedResponse`](crate::Error::UnexpectedResponse).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ContentTypePolicy {
+    /// Accept the response regardless of its content type (the default).
+    #[default]
+    Ignore,
+    /// Report [`Error::InvalidContentType`](crate::Error::InvalidContentType) and close the
+    /// stream.
+    Fail,
+    /// Report [`Error::InvalidContentType`](crate::Error::InvalidContentType) and reconnect,
+    /// waiting according to the configured [`ReconnectOptions`] backoff. If reconnection is
+    /// disabled, this behaves like [`ContentTypePolicy::Fail`].
+    Reconnect,
+}
+
+/// A change in the state of the reconnect backoff, reported to the callback registered with
+/// [`ClientBuilder::on_backoff_change`](crate::ClientBuilder::on_backoff_change).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BackoffEvent {
+    /// The delay before the next reconnect attempt has reached the configured
+    /// [`delay_max`](ReconnectOptionsBuilder::delay_max). Reported once, until the client
+    /// connects successfully again.
+    ReachedMax,
+    /// The client connected successfully after the delay had reached its maximum.
+    Recovered,
+}
+
+/// What the client should do after an error, as decided by a callback registered with
+/// [`ClientBuilder::on_error`](crate::ClientBuilder::on_error), or configured for a limit such as
+/// [`ClientBuilder::max_bytes_per_connection`](crate::ClientBuilder::max_bytes_per_connection).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorAction {
+    /// Report the error, then reconnect after the next backoff delay.
+    Reconnect,
+    /// Report the error, then close the stream.
+    Fail,
+    /// End the stream without reporting the error.
+    End,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[cfg(feature = "data-encoding")]
+    #[test_case(DataEncoding::Raw, "aGk=", Some("aGk="); "raw")]
+    #[test_case(DataEncoding::Base64, "aGVsbG8g\nd29ybGQ=", Some("hello world"); "base64 over lines")]
+    #[test_case(DataEncoding::Base64, "not base64!", None; "invalid base64")]
+    #[test_case(DataEncoding::Hex, "68690a4869", Some("hi\nHi"); "hex")]
+    #[test_case(DataEncoding::Hex, "6A6b", Some("jk"); "mixed case hex")]
+    #[test_case(DataEncoding::Hex, "686", None; "odd hex")]
+    #[test_case(DataEncoding::Hex, "zz", None; "invalid hex")]
+    #[test_case(DataEncoding::Hex, "ff", None; "invalid utf-8")]
+    fn test_data_encoding_decode(encoding: DataEncoding, data: &str, expected: Option<&str>) {
+        assert_eq!(encoding.decode(data).ok().as_deref(), expected);
+    }
+
+    #[test_case(500, Some(Duration::from_secs(1)); "below delay")]
+    #[test_case(5_000, Some(Duration::from_secs(5)); "within bounds")]
+    #[test_case(600_000, Some(Duration::from_secs(60)); "above delay_max")]
+    fn test_server_retry_is_clamped(retry_ms: u64, expected: Option<Duration>) {
+        let opts = ReconnectOptions::default();
+        assert_eq!(opts.server_retry_delay(retry_ms), expected);
+    }
+
+    #[test]
+    fn test_server_retry_can_be_ignored() {
+        let opts = ReconnectOptions::reconnect(true)
+            .honor_server_retry(false)
+            .build();
+        assert_eq!(opts.server_retry_delay(5_000), None);
+    }
+
+    #[test]
+    fn test_dns_delays_fall_back_to_general_settings() {
+        assert_eq!(ReconnectOptions::default().dns_delays(), None);
+
+        let opts = ReconnectOptions::reconnect(true)
+            .dns_retry_delay(Duration::from_secs(5))
+            .build();
+        assert_eq!(
+            opts.dns_delays(),
+            Some((Duration::from_secs(5), Duration::from_secs(60)))
+        );
+
+        let opts = ReconnectOptions::reconnect(true)
+            .dns_delay_max(Duration::from_secs(300))
+            .build();
+        assert_eq!(
+            opts.dns_delays(),
+            Some((Duration::from_secs(1), Duration::from_secs(300)))
+        );
+    }
+
+    #[test]
+    fn test_preview_schedule_backs_off_to_delay_max() {
+        let opts = ReconnectOptions::default();
+        let secs: Vec<_> = opts
+            .preview_schedule(8)
+            .iter()
+            .map(Duration::as_secs)
+            .collect();
+        assert_eq!(secs, vec![1, 2, 4, 8, 16, 32, 60, 60]);
+
+        let opts = ReconnectOptions::reconnect(true).backoff_factor(1).build();
+        assert_eq!(opts.preview_schedule(3), vec![Duration::from_secs(1); 3]);
+        assert!(opts.preview_schedule(0).is_empty());
+    }
+
+    #[test_case(None, 200, true; "default accepts 200")]
+    #[test_case(None, 204, true; "default accepts other 2xx")]
+    #[test_case(None, 302, false; "default rejects redirect")]
+    #[test_case(Some(vec![200, 299]), 299, true; "listed status")]
+    #[test_case(Some(vec![200, 299]), 204, false; "unlisted 2xx")]
+    fn test_accepts_status(statuses: Option<Vec<u16>>, status: u16, expected: bool) {
+        let mut builder = ReconnectOptions::reconnect(true);
+        if let Some(statuses) = statuses {
+            builder = builder.accept_statuses(
+                statuses
+                    .into_iter()
+                    .map(|code| StatusCode::from_u16(code).unwrap()),
+            );
+        }
+        let status = StatusCode::from_u16(status).unwrap();
+        assert_eq!(builder.build().accepts_status(status), expected);
+    }
+
+    /// Read options from the variables in `vars` instead of the process environment, which
+    /// tests running in parallel must not change.
+    fn from_vars(vars: &[(&str, &str)]) -> Result<ReconnectOptions> {
+        let vars: std::collections::HashMap<_, _> = vars.iter().copied().collect();
+        ReconnectOptions::from_lookup("ES", |key| {
+            vars.get(key)
+                .map(|value| value.to_string())
+                .ok_or(env::VarError::NotPresent)
+        })
+    }
+
+    #[test]
+    fn test_from_env_reads_values_and_defaults() {
+        let opts = from_vars(&[
+            ("ES_DELAY_MS", "250"),
+            ("ES_BACKOFF_FACTOR", "3"),
+            ("ES_RETRY_INITIAL", "TRUE"),
+            ("ES_RECONNECT_GRACE_MS", "2000"),
+        ])
+        .unwrap();
+        assert_eq!(opts.delay, Duration::from_millis(250));
+        assert_eq!(opts.backoff_factor, 3);
+        assert!(opts.retry_initial);
+        assert_eq!(opts.reconnect_grace, Duration::from_secs(2));
+        assert_eq!(opts.delay_max, ReconnectOptions::default().delay_max);
+        assert!(opts.reconnect);
+        assert_eq!(opts.accept_statuses, None);
+    }
+
+    #[test]
+    fn test_from_env_reads_accept_statuses() {
+        let opts = from_vars(&[("ES_ACCEPT_STATUSES", "200, 206")]).unwrap();
+        assert_eq!(
+            opts.accept_statuses,
+            Some(vec![StatusCode::OK, StatusCode::PARTIAL_CONTENT])
+        );
+    }
+
+    #[test_case("BACKOFF_FACTOR", "0"; "zero backoff factor")]
+    #[test_case("BACKOFF_FACTOR", "1.5"; "fractional backoff factor")]
+    #[test_case("DELAY_MS", "soon"; "unparseable delay")]
+    #[test_case("RECONNECT", "yes"; "unparseable boolean")]
+    #[test_case("DELAY_MAX_MS", "10"; "max below delay")]
+    #[test_case("ACCEPT_STATUSES", "200,ok"; "unparseable status")]
+    fn test_from_env_rejects_invalid_values(name: &str, value: &str) {
+        let key = format!("ES_{}", name);
+        match from_vars(&[(&key, value)]) {
+            Err(Error::InvalidParameter(e)) => assert!(e.to_string().contains(name)),
+            other => panic!("expected an invalid parameter error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_from_env_reads_the_environment() {
+        // Only reads, with a prefix nothing sets, so this doesn't race with other tests.
+        let opts = ReconnectOptions::from_env("ES_TEST_UNSET_PREFIX").unwrap();
+        assert_eq!(opts.delay, ReconnectOptions::default().delay);
+    }
+}
@@ -0,0 +1,143 @@
+use futures::future::BoxFuture;
+use hyper::{
+    client::connect::{Connected, Connection},
+    service::Service,
+    Uri,
+};
+use std::{
+    fmt::{self, Debug, Formatter},
+    io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::client::BoxError;
+
+/// A connector of any type, boxed so that clients built from different connectors are built
+/// the same way. Pass it to [`ClientBuilder::build_with_boxed_conn`](crate::ClientBuilder::build_with_boxed_conn).
+///
+/// ```
+/// # use eventsource_client as es;
+/// # fn main() -> Result<(), es::Error> {
+/// # let use_tls = false;
+/// let connector = if use_tls {
+///     es::BoxConnector::new(es::HttpsConnector::with_native_roots())
+/// } else {
+///     es::BoxConnector::new(es::HttpConnector::new())
+/// };
+/// let client = es::ClientBuilder::for_url("http://localhost:8080/stream")?
+///     .build_with_boxed_conn(connector);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct BoxConnector {
+    connect: Arc<dyn Fn(Uri) -> BoxFuture<'static, Result<BoxConnection, BoxError>> + Send + Sync>,
+}
+
+impl BoxConnector {
+    /// Box `conn`, which may be any connector accepted by
+    /// [`ClientBuilder::build_with_conn`](crate::ClientBuilder::build_with_conn).
+    pub fn new<C>(conn: C) -> Self
+    where
+        C: Service<Uri> + Clone + Send + Sync + 'static,
+        C::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin,
+        C::Future: Send + 'static,
+        C::Error: Into<BoxError>,
+    {
+        BoxConnector {
+            connect: Arc::new(move |uri| {
+                let mut conn = conn.clone();
+                Box::pin(async move {
+                    futures::future::poll_fn(|cx| conn.poll_ready(cx))
+                        .await
+                        .map_err(Into::into)?;
+                    let io = conn.call(uri).await.map_err(Into::into)?;
+                    Ok(BoxConnection(Box::new(io)))
+                })
+            }),
+        }
+    }
+}
+
+impl Debug for BoxConnector {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BoxConnector").finish_non_exhaustive()
+    }
+}
+
+impl Service<Uri> for BoxConnector {
+    type Response = BoxConnection;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<BoxConnection, BoxError>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // The boxed connector is polled for readiness as part of each call.
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        (self.connect)(uri)
+    }
+}
+
+trait Io: AsyncRead + AsyncWrite + Connection + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Connection + Send + Unpin> Io for T {}
+
+/// A connection made by a [`BoxConnector`].
+pub struct BoxConnection(Box<dyn Io>);
+
+impl Debug for BoxConnection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BoxConnection").finish_non_exhaustive()
+    }
+}
+
+impl Connection for BoxConnection {
+    fn connected(&self) -> Connected {
+        self.0.connected()
+    }
+}
+
+impl AsyncRead for BoxConnection {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for BoxConnection {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut *self.0).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut *self.0).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.0.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.0).poll_shutdown(cx)
+    }
+}
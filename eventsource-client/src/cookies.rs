@@ -0,0 +1,394 @@
+use hyper::{
+    header::{HeaderMap, HeaderValue, COOKIE, SET_COOKIE},
+    Uri,
+};
+use log::{debug, warn};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+/// Cookies set by the server, sent back with each later request of the stream. Pass a jar to
+/// [`ClientBuilder::cookie_jar`](crate::ClientBuilder::cookie_jar).
+///
+/// The jar is shared between its clones, so keep a clone to inspect the cookies the server has
+/// set, or to seed the jar with cookies of your own.
+///
+/// A cookie set by a server is only sent back to the host that set it, so cookies don't follow
+/// a redirect to another host. Cookies stored with [`CookieJar::set`] are sent to the host of the
+/// stream's URL. Only the `Path`, `Max-Age` and `Expires` attributes are respected: `Domain` is
+/// ignored, so a cookie is never shared with other hosts of the same domain, and `Secure` is
+/// ignored too.
+///
+/// ```
+/// # use eventsource_client as es;
+/// # fn main() -> Result<(), es::Error> {
+/// let jar = es::CookieJar::new();
+/// jar.set("session", "abc123");
+/// let client = es::ClientBuilder::for_url("https://example.com/stream")?
+///     .cookie_jar(jar.clone())
+///     .build_http();
+/// assert_eq!(jar.get("session").as_deref(), Some("abc123"));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CookieJar {
+    cookies: Arc<Mutex<Vec<Cookie>>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Cookie {
+    name: String,
+    value: String,
+    /// the host that set the cookie, in lowercase, or `None` for one stored with
+    /// [`CookieJar::set`], which belongs to the host of the stream's URL
+    host: Option<String>,
+    path: String,
+    expires: Option<SystemTime>,
+}
+
+impl Cookie {
+    fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    /// Whether the cookie is sent to `host`, for a stream whose URL's host is `home`.
+    fn host_matches(&self, host: &str, home: &str) -> bool {
+        self.host
+            .as_deref()
+            .unwrap_or(home)
+            .eq_ignore_ascii_case(host)
+    }
+}
+
+impl CookieJar {
+    /// Create an empty jar.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store a cookie that is sent with every request to the host of the stream's URL, replacing
+    /// any set this way with the same name.
+    pub fn set(&self, name: &str, value: &str) {
+        self.insert(Cookie {
+            name: name.to_string(),
+            value: value.to_string(),
+            host: None,
+            path: "/".to_string(),
+            expires: None,
+        });
+    }
+
+    /// The value of the unexpired cookie called `name`, if there is one. If cookies with that
+    /// name were set for several paths, the one with the longest path is returned; the host that
+    /// set it isn't considered.
+    pub fn get(&self, name: &str) -> Option<String> {
+        let now = SystemTime::now();
+        self.lock()
+            .iter()
+            .filter(|cookie| cookie.name == name && !cookie.is_expired(now))
+            .max_by_key(|cookie| cookie.path.len())
+            .map(|cookie| cookie.value.clone())
+    }
+
+    /// The names and values of all the unexpired cookies in the jar.
+    pub fn cookies(&self) -> Vec<(String, String)> {
+        let now = SystemTime::now();
+        self.lock()
+            .iter()
+            .filter(|cookie| !cookie.is_expired(now))
+            .map(|cookie| (cookie.name.clone(), cookie.value.clone()))
+            .collect()
+    }
+
+    /// Remove every cookie from the jar.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    /// Store the cookies set by a response to a request for `url`.
+    pub(crate) fn store_response(&self, headers: &HeaderMap, url: &Uri) {
+        let now = SystemTime::now();
+        let host = url.host().unwrap_or_default().to_ascii_lowercase();
+        for header in headers.get_all(SET_COOKIE) {
+            let parsed = header
+                .to_str()
+                .ok()
+                .and_then(|header| parse_set_cookie(header, &host, url.path(), now));
+            match parsed {
+                Some(cookie) => {
+                    debug!(
+                        "storing cookie {:?} for {}{}",
+                        cookie.name, host, cookie.path
+                    );
+                    self.insert(cookie);
+                }
+                None => warn!("ignoring invalid Set-Cookie header: {:?}", header),
+            }
+        }
+    }
+
+    /// Add the cookies that apply to `url` to a request's `Cookie` header, after any cookies
+    /// already in it. `home` is the stream's URL, whose host gets the cookies stored with
+    /// [`CookieJar::set`].
+    pub(crate) fn add_to_request(&self, headers: &mut HeaderMap, url: &Uri, home: &Uri) {
+        let now = SystemTime::now();
+        let mut cookies = self.lock();
+        cookies.retain(|cookie| !cookie.is_expired(now));
+
+        let host = url.host().unwrap_or_default();
+        let home = home.host().unwrap_or_default();
+        let mut matching: Vec<_> = cookies
+            .iter()
+            .filter(|cookie| {
+                cookie.host_matches(host, home) && path_matches(&cookie.path, url.path())
+            })
+            .collect();
+        if matching.is_empty() {
+            return;
+        }
+        // Cookies with longer paths are listed first, as RFC 6265 recommends.
+        matching.sort_by_key(|cookie| std::cmp::Reverse(cookie.path.len()));
+
+        let mut pairs: Vec<String> = headers
+            .get(COOKIE)
+            .and_then(|existing| existing.to_str().ok())
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        pairs.extend(
+            matching
+                .iter()
+                .map(|cookie| format!("{}={}", cookie.name, cookie.value)),
+        );
+        match HeaderValue::from_str(&pairs.join("; ")) {
+            Ok(value) => {
+                headers.insert(COOKIE, value);
+            }
+            Err(e) => warn!("not sending cookies: {}", e),
+        }
+    }
+
+    fn insert(&self, cookie: Cookie) {
+        let mut cookies = self.lock();
+        cookies.retain(|existing| {
+            existing.name != cookie.name
+                || existing.host != cookie.host
+                || existing.path != cookie.path
+        });
+        cookies.push(cookie);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Cookie>> {
+        // A panic while holding the lock can't leave the list half-updated, so recover from it.
+        self.cookies.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Parse a `Set-Cookie` header received in response to a request to `host` for `request_path`.
+/// A cookie that has already expired is returned with its expiry, so that it replaces any stored
+/// copy.
+fn parse_set_cookie(
+    header: &str,
+    host: &str,
+    request_path: &str,
+    now: SystemTime,
+) -> Option<Cookie> {
+    let mut attributes = header.split(';');
+    let (name, value) = attributes.next()?.split_once('=')?;
+    let name = name.trim();
+    if name.is_empty() {
+        return None;
+    }
+
+    let mut path = None;
+    let mut max_age = None;
+    let mut expires = None;
+    for attribute in attributes {
+        let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+        let value = value.trim();
+        match key.trim().to_ascii_lowercase().as_str() {
+            "path" if value.starts_with('/') => path = Some(value.to_string()),
+            "max-age" => {
+                // A Max-Age too large to represent means the cookie never expires.
+                max_age = value
+                    .parse::<i64>()
+                    .ok()
+                    .map(|secs| match u64::try_from(secs) {
+                        Ok(secs) if secs > 0 => now.checked_add(Duration::from_secs(secs)),
+                        _ => Some(SystemTime::UNIX_EPOCH),
+                    })
+            }
+            "expires" => expires = httpdate::parse_http_date(value).ok(),
+            _ => {}
+        }
+    }
+
+    Some(Cookie {
+        name: name.to_string(),
+        value: value.trim().to_string(),
+        host: Some(host.to_string()),
+        path: path.unwrap_or_else(|| default_path(request_path)),
+        // Max-Age takes precedence over Expires.
+        expires: max_age.unwrap_or(expires),
+    })
+}
+
+/// The path of a cookie set without a `Path` attribute: the request path up to, but not
+/// including, its last `/`.
+fn default_path(request_path: &str) -> String {
+    match request_path.rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(i) => request_path[..i].to_string(),
+    }
+}
+
+fn path_matches(cookie_path: &str, request_path: &str) -> bool {
+    match request_path.strip_prefix(cookie_path) {
+        Some(rest) => rest.is_empty() || cookie_path.ends_with('/') || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    const HOME: &str = "http://example.com";
+
+    fn url(url: &str) -> Uri {
+        url.parse().unwrap()
+    }
+
+    fn cookie_header(jar: &CookieJar, path: &str) -> Option<String> {
+        cookie_header_for(jar, &format!("{}{}", HOME, path))
+    }
+
+    fn cookie_header_for(jar: &CookieJar, request_url: &str) -> Option<String> {
+        let mut headers = HeaderMap::new();
+        jar.add_to_request(&mut headers, &url(request_url), &url(HOME));
+        headers
+            .get(COOKIE)
+            .map(|value| value.to_str().unwrap().to_string())
+    }
+
+    fn set_cookies(values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(SET_COOKIE, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test_case("/", "/stream", true; "root")]
+    #[test_case("/api", "/api", true; "exact")]
+    #[test_case("/api", "/api/stream", true; "subdirectory")]
+    #[test_case("/api/", "/api/stream", true; "trailing slash")]
+    #[test_case("/api", "/apistream", false; "prefix of a segment")]
+    #[test_case("/api/stream", "/api", false; "parent")]
+    fn test_path_matches(cookie_path: &str, request_path: &str, expected: bool) {
+        assert_eq!(path_matches(cookie_path, request_path), expected);
+    }
+
+    #[test]
+    fn test_cookies_are_stored_and_sent() {
+        let jar = CookieJar::new();
+        jar.store_response(
+            &set_cookies(&[
+                "session=abc; Path=/; HttpOnly",
+                "stream=xyz; Path=/api/stream",
+                "default=1",
+            ]),
+            &url("http://example.com/api/stream"),
+        );
+
+        assert_eq!(jar.get("session").as_deref(), Some("abc"));
+        assert_eq!(
+            cookie_header(&jar, "/api/stream").as_deref(),
+            Some("stream=xyz; default=1; session=abc")
+        );
+        assert_eq!(
+            cookie_header(&jar, "/other").as_deref(),
+            Some("session=abc")
+        );
+    }
+
+    #[test]
+    fn test_cookies_are_replaced_and_expire() {
+        let jar = CookieJar::new();
+        jar.store_response(
+            &set_cookies(&["a=1", "b=2", "c=3"]),
+            &url("http://example.com/stream"),
+        );
+        jar.store_response(
+            &set_cookies(&[
+                "a=one",
+                "b=gone; Max-Age=0",
+                "c=gone; Expires=Thu, 01 Jan 1970 00:00:00 GMT",
+            ]),
+            &url("http://example.com/stream"),
+        );
+
+        assert_eq!(jar.cookies(), vec![("a".to_string(), "one".to_string())]);
+        assert_eq!(cookie_header(&jar, "/stream").as_deref(), Some("a=one"));
+    }
+
+    #[test]
+    fn test_cookies_are_added_to_an_existing_header() {
+        let jar = CookieJar::new();
+        jar.set("session", "abc");
+        let mut headers = HeaderMap::new();
+        headers.insert(COOKIE, HeaderValue::from_static("theme=dark"));
+        jar.add_to_request(&mut headers, &url("http://example.com/stream"), &url(HOME));
+        assert_eq!(headers[COOKIE], "theme=dark; session=abc");
+    }
+
+    #[test]
+    fn test_invalid_set_cookie_is_ignored() {
+        let jar = CookieJar::new();
+        jar.store_response(
+            &set_cookies(&["no value", "=nameless"]),
+            &url("http://example.com/"),
+        );
+        assert!(jar.cookies().is_empty());
+    }
+
+    #[test]
+    fn test_huge_max_age_never_expires() {
+        let jar = CookieJar::new();
+        jar.store_response(
+            &set_cookies(&[
+                "a=1; Max-Age=9223372036854775807",
+                "b=2; Max-Age=9223372036854775807; Expires=Thu, 01 Jan 1970 00:00:00 GMT",
+            ]),
+            &url("http://example.com/"),
+        );
+        assert_eq!(cookie_header(&jar, "/").as_deref(), Some("a=1; b=2"));
+    }
+
+    #[test]
+    fn test_cookies_are_only_sent_to_their_host() {
+        let jar = CookieJar::new();
+        jar.set("token", "secret");
+        jar.store_response(
+            &set_cookies(&["session=abc"]),
+            &url("http://Example.com:8080/stream"),
+        );
+        jar.store_response(
+            &set_cookies(&["session=xyz", "other=1"]),
+            &url("http://other.example.com/stream"),
+        );
+
+        assert_eq!(
+            cookie_header_for(&jar, "https://example.com/stream").as_deref(),
+            Some("token=secret; session=abc")
+        );
+        assert_eq!(
+            cookie_header_for(&jar, "http://other.example.com/stream").as_deref(),
+            Some("session=xyz; other=1")
+        );
+        assert_eq!(cookie_header_for(&jar, "http://elsewhere.com/stream"), None);
+    }
+}
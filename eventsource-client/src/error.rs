@@ -1,4 +1,4 @@
-use hyper::StatusCode;
+use hyper::{header::HeaderValue, StatusCode};
 
 /// Error type returned from this library's functions.
 #[derive(Debug)]
@@ -9,6 +9,8 @@ pub enum Error {
     InvalidParameter(Box<dyn std::error::Error + Send + 'static>),
     /// The HTTP response could not be handled.
     UnexpectedResponse(StatusCode),
+    /// A successful HTTP response did not have a `text/event-stream` content type.
+    InvalidContentType(Option<HeaderValue>),
     /// An error reading from the HTTP response body.
     HttpStream(Box<dyn std::error::Error + Send + 'static>),
     /// The HTTP response stream ended
@@ -16,13 +18,46 @@ pub enum Error {
     /// The HTTP response stream ended unexpectedly (e.g. in the
     /// middle of an event).
     UnexpectedEof,
+    /// A connection succeeded but no event arrived within the configured first event timeout.
+    FirstEventTimeout,
+    /// A connection delivered more bytes than the configured per-connection maximum, which is
+    /// held here.
+    ConnectionByteLimit(u64),
+    /// The stream had already reconnected as many times as the retry budget allows within its
+    /// window, so it closed instead of reconnecting again. See
+    /// [`ReconnectOptionsBuilder::retry_budget`](crate::ReconnectOptionsBuilder::retry_budget).
+    RetryBudgetExhausted,
     /// Encountered a line not conforming to the SSE protocol.
     InvalidLine(String),
     InvalidEvent,
+    /// The server sent an event of the configured error event type; holds the event's data.
+    ServerEvent(String),
+    /// The server sent an event of a type that wasn't declared as expected; holds the type.
+    UnexpectedEventType(String),
+    /// The transform set with
+    /// [`ClientBuilder::transform_data`](crate::ClientBuilder::transform_data) failed on an
+    /// event's data.
+    TransformFailed(Box<dyn std::error::Error + Send + 'static>),
+    /// An event's data couldn't be decoded with the encoding set with
+    /// `ClientBuilder::data_encoding`, which the `data-encoding` feature provides.
+    InvalidDataEncoding(Box<dyn std::error::Error + Send + 'static>),
+    /// An event's data couldn't be deserialized from JSON.
+    InvalidJson(Box<dyn std::error::Error + Send + 'static>),
+    /// The server responded with a redirect status but no `Location` header to follow.
+    RedirectWithoutLocation(StatusCode),
     /// Encountered a malformed Location header.
     MalformedLocationHeader(Box<dyn std::error::Error + Send + 'static>),
     /// Reached maximum redirect limit after encountering Location headers.
     MaxRedirectLimitReached(u32),
+    /// The request's headers came to more bytes than the limit set with
+    /// [`ClientBuilder::max_header_size`](crate::ClientBuilder::max_header_size), so it wasn't
+    /// sent. Holds the size of the headers.
+    HeadersTooLarge(usize),
+    /// The preflight request configured with
+    /// [`ClientBuilder::preflight`](crate::ClientBuilder::preflight) couldn't be made.
+    PreflightFailed(Box<dyn std::error::Error + Send + 'static>),
+    /// The preflight request got a non-2xx response, whose status is held here.
+    PreflightRejected(StatusCode),
     /// An unexpected failure occurred.
     Unexpected(Box<dyn std::error::Error + Send + 'static>),
 }
@@ -50,6 +85,10 @@ impl Error {
     pub fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
             Error::HttpStream(err) => Some(err.as_ref()),
+            Error::InvalidJson(err) => Some(err.as_ref()),
+            Error::InvalidDataEncoding(err) => Some(err.as_ref()),
+            Error::TransformFailed(err) => Some(err.as_ref()),
+            Error::PreflightFailed(err) => Some(err.as_ref()),
             Error::Unexpected(err) => Some(err.as_ref()),
             _ => None,
         }
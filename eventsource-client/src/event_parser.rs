@@ -5,13 +5,18 @@ use log::{debug, log_enabled, trace};
 use pin_project::pin_project;
 
 use super::error::{Error, Result};
+use crate::seen_ids::SeenIds;
 
 #[derive(Default, PartialEq)]
 struct EventData {
     pub event_type: String,
+    /// each `data` value followed by a newline, so that a block whose only data line is empty
+    /// (`"\n"`) can be told apart from one with no data line at all (`""`)
     pub data: String,
     pub id: Option<String>,
     pub retry: Option<u64>,
+    /// whether the block had an `id` field of its own, rather than inheriting the last ID
+    pub has_id: bool,
 }
 
 impl EventData {
@@ -30,43 +35,70 @@ impl EventData {
     }
 }
 
+/// An item of an event stream: an event, a comment, or one of the notices the client can be
+/// configured to produce. Some variants only exist with a feature enabled, and more may be added,
+/// so matches on `SSE` need a wildcard arm; that way enabling a feature anywhere in a build can't
+/// break them.
 #[derive(Debug, PartialEq)]
+#[non_exhaustive]
 pub enum SSE {
     Event(Event),
     Comment(String),
+    /// A piece of the data of an event that is still being received, produced only when
+    /// incremental data is enabled. Concatenating an event's pieces gives its `data`, including
+    /// the newlines between `data:` lines. The complete [`SSE::Event`] follows once the event
+    /// ends.
+    Partial(String),
+    /// The `(field, value)` pairs of an event block, in the order and with the repetition the
+    /// server sent them, before any normalization. Only produced when raw events are enabled,
+    /// in which case each block's `Raw` comes just before the [`SSE::Event`] it produced, if any.
+    /// Comments are not included, as they are reported separately.
+    #[cfg(feature = "raw-events")]
+    Raw(Vec<(String, String)>),
+    /// The client is about to connect, sending `last_event_id` as its `Last-Event-ID` (or none,
+    /// if it is `None`). Only produced when resume notices are enabled, before the stream's
+    /// first connection and each reconnect; redirects aren't included.
+    Resuming {
+        last_event_id: Option<String>,
+    },
+    /// A tick produced by the client while the stream is connected, not sent by the server.
+    /// Only produced when a heartbeat interval is set.
+    Heartbeat,
+}
+
+impl EventData {
+    fn into_event(self) -> Event {
+        let event_type = if self.event_type.is_empty() {
+            String::from("message")
+        } else {
+            self.event_type
+        };
+
+        let mut data = self.data;
+        if data.ends_with('\n') {
+            data.truncate(data.len() - 1);
+        }
+
+        Event {
+            event_type,
+            data,
+            id: self.id,
+            retry: self.retry,
+        }
+    }
 }
 
 impl TryFrom<EventData> for Option<SSE> {
     type Error = Error;
 
     fn try_from(event_data: EventData) -> std::result::Result<Self, Self::Error> {
-        if event_data == EventData::default() {
-            return Err(Error::InvalidEvent);
-        }
-
+        // Per the spec, an event with no data fields (including one made up only of bare or
+        // unknown field lines) is not dispatched. One with an empty data field is.
         if event_data.data.is_empty() {
             return Ok(None);
         }
 
-        let event_type = if event_data.event_type.is_empty() {
-            String::from("message")
-        } else {
-            event_data.event_type
-        };
-
-        let mut data = event_data.data.clone();
-        data.truncate(data.len() - 1);
-
-        let id = event_data.id.clone();
-
-        let retry = event_data.retry;
-
-        Ok(Some(SSE::Event(Event {
-            event_type,
-            data,
-            id,
-            retry,
-        })))
+        Ok(Some(SSE::Event(event_data.into_event())))
     }
 }
 
@@ -78,13 +110,106 @@ pub struct Event {
     pub retry: Option<u64>,
 }
 
+impl Event {
+    /// Create an event, for example to feed to code under test. Its retry hint is unset; see
+    /// [`Event::with_retry`].
+    ///
+    /// ```
+    /// # use eventsource_client::Event;
+    /// let event = Event::new("put", r#"{"flag":true}"#, Some("42".to_string()));
+    /// assert_eq!(event.event_type, "put");
+    /// ```
+    pub fn new(
+        event_type: impl Into<String>,
+        data: impl Into<String>,
+        id: Option<String>,
+    ) -> Event {
+        Event {
+            event_type: event_type.into(),
+            data: data.into(),
+            id,
+            retry: None,
+        }
+    }
+
+    /// Set the reconnect delay hint, in milliseconds, as sent in a `retry:` field.
+    pub fn with_retry(mut self, retry: u64) -> Event {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// The event in the event stream format, as `event:`, `data:`, `id:` and `retry:` lines
+    /// followed by a blank line. Parsing the result gives back an equal event, except that a
+    /// carriage return in the data, which the format can't carry, comes back as `\n`. See also
+    /// [`encode_event`](crate::encode_event).
+    pub fn to_sse(&self) -> String {
+        let mut sse = format!("event: {}\n", self.event_type);
+        for line in self
+            .data
+            .split("\r\n")
+            .flat_map(|line| line.split(['\r', '\n']))
+        {
+            sse.push_str("data: ");
+            sse.push_str(line);
+            sse.push('\n');
+        }
+        if let Some(id) = &self.id {
+            sse.push_str(&format!("id: {}\n", id));
+        }
+        if let Some(retry) = self.retry {
+            sse.push_str(&format!("retry: {}\n", retry));
+        }
+        sse.push('\n');
+        sse
+    }
+}
+
+/// Encode `event` in the event stream format, as given by [`Event::to_sse`], for example to
+/// serve it from a test server or to record it for replaying later.
+///
+/// ```
+/// # use eventsource_client::{encode_event, Event};
+/// let event = Event::new("put", "line 1\nline 2", Some("7".to_string()));
+/// assert_eq!(
+///     encode_event(&event),
+///     b"event: put\ndata: line 1\ndata: line 2\nid: 7\n\n".to_vec()
+/// );
+/// ```
+pub fn encode_event(event: &Event) -> Vec<u8> {
+    event.to_sse().into_bytes()
+}
+
+/// Encode `events` one after another in the event stream format, as a response body or a
+/// recording that `ClientBuilder::build_from_file`, which the `test-util` feature provides, can
+/// replay. Parsing the result gives back the same events in order.
+pub fn encode_events<'a, I>(events: I) -> Vec<u8>
+where
+    I: IntoIterator<Item = &'a Event>,
+{
+    events.into_iter().flat_map(encode_event).collect()
+}
+
+/// Whether `event_type` is the `configured` type, ignoring ASCII case if `ignore_case` is set.
+pub(crate) fn event_type_matches(configured: &str, event_type: &str, ignore_case: bool) -> bool {
+    if ignore_case {
+        configured.eq_ignore_ascii_case(event_type)
+    } else {
+        configured == event_type
+    }
+}
+
 const LOGIFY_MAX_CHARS: usize = 100;
 fn logify(bytes: &[u8]) -> &str {
     let stringified = from_utf8(bytes).unwrap_or("<bad utf8>");
     if stringified.len() <= LOGIFY_MAX_CHARS {
         stringified
     } else {
-        &stringified[..LOGIFY_MAX_CHARS - 1]
+        // back off to a char boundary so multi-byte characters can't make the slice panic
+        let mut end = LOGIFY_MAX_CHARS - 1;
+        while !stringified.is_char_boundary(end) {
+            end -= 1;
+        }
+        &stringified[..end]
     }
 }
 
@@ -119,6 +244,22 @@ fn parse_field(line: &[u8]) -> Result<Option<(&str, &str)>> {
     }
 }
 
+/// Describe a parsed field for the trace enabled by [`EventParser::with_field_trace`], with its
+/// value replaced if the field is one of `redacted`.
+#[cfg(feature = "trace-fields")]
+fn describe_field(key: &str, value: &str, redacted: &[String]) -> String {
+    let value = if redacted.iter().any(|name| name == key) {
+        "<redacted>"
+    } else {
+        logify(value.as_bytes())
+    };
+    let note = match key {
+        "comment" | "event" | "data" | "id" | "retry" => "",
+        _ => " (unrecognized, ignored)",
+    };
+    format!("field {:?}: {:?}{}", key, value, note)
+}
+
 fn parse_key(key: &[u8]) -> Result<&str> {
     from_utf8(key).map_err(|e| Error::InvalidLine(format!("malformed key: {:?}", e)))
 }
@@ -143,9 +284,45 @@ pub struct EventParser {
     event_data: Option<EventData>,
     /// the last-seen event ID; events without an ID will take on this value until it is updated.
     last_event_id: Option<String>,
+    /// the ID set by the last block that had an `id` field but wasn't dispatched, if no event has
+    /// been dispatched since
+    undispatched_id: Option<Option<String>>,
+    /// events of this type clear the last-seen event ID once dispatched
+    reset_event_type: Option<String>,
+    /// compare event types ignoring ASCII case
+    ignore_event_type_case: bool,
+    /// dispatch events that have fields but no data, rather than dropping them
+    emit_empty_events: bool,
+    /// drop events whose data field is present but empty
+    skip_empty_data: bool,
+    /// the IDs of recent events, when events with an ID seen before are dropped
+    seen_ids: Option<SeenIds>,
+    /// the longest line, in bytes, that will be buffered before parsing fails
+    max_line_length: Option<usize>,
+    /// whether the rest of an oversized line is being dropped, up to its terminator
+    skipping_line: bool,
+    /// report data as it arrives, before the event is complete
+    incremental_data: bool,
+    /// how many bytes of the value of the incomplete `data:` line have been reported already
+    partial_data_emitted: usize,
+    /// report the fields of each event block as received
+    #[cfg(feature = "raw-events")]
+    raw_events: bool,
+    /// the fields of the event currently being decoded, when raw events are enabled
+    #[cfg(feature = "raw-events")]
+    raw_fields: Vec<(String, String)>,
+    /// log each field as it is parsed, redacting the values of the fields listed
+    #[cfg(feature = "trace-fields")]
+    field_trace: Option<Vec<String>>,
     sse: VecDeque<SSE>,
 }
 
+impl Default for EventParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl EventParser {
     pub fn new() -> Self {
         Self {
@@ -154,10 +331,104 @@ impl EventParser {
             last_char_was_cr: false,
             event_data: None,
             last_event_id: None,
+            undispatched_id: None,
+            reset_event_type: None,
+            ignore_event_type_case: false,
+            emit_empty_events: false,
+            skip_empty_data: false,
+            seen_ids: None,
+            max_line_length: None,
+            skipping_line: false,
+            incremental_data: false,
+            partial_data_emitted: 0,
+            #[cfg(feature = "raw-events")]
+            raw_events: false,
+            #[cfg(feature = "raw-events")]
+            raw_fields: Vec::new(),
+            #[cfg(feature = "trace-fields")]
+            field_trace: None,
             sse: VecDeque::with_capacity(3),
         }
     }
 
+    /// Clear the last-seen event ID whenever an event of the given type is dispatched, so that
+    /// following events don't inherit it.
+    pub fn with_reset_event_type(mut self, event_type: Option<String>) -> Self {
+        self.reset_event_type = event_type;
+        self
+    }
+
+    /// Match the reset event type ignoring ASCII case.
+    pub fn with_case_insensitive_event_types(mut self, enabled: bool) -> Self {
+        self.ignore_event_type_case = enabled;
+        self
+    }
+
+    /// Dispatch events that have other fields (such as `event:` or `id:`) but no `data:` field,
+    /// with empty data. By default such events are dropped, as the spec requires. Blank lines
+    /// that aren't terminating any fields never produce an event.
+    pub fn with_emit_empty_events(mut self, emit: bool) -> Self {
+        self.emit_empty_events = emit;
+        self
+    }
+
+    /// Drop events that have a `data:` field whose value is empty, such as `data:` on its own,
+    /// which the spec dispatches with empty data. An `id:` field in such an event still updates
+    /// the last event ID. Events with no `data:` field are governed by
+    /// [`EventParser::with_emit_empty_events`] instead.
+    pub fn with_skip_empty_data(mut self, skip: bool) -> Self {
+        self.skip_empty_data = skip;
+        self
+    }
+
+    /// Drop events whose `id:` field repeats one of the last `capacity` distinct IDs that were
+    /// dispatched. Events that only inherit the last event ID are never dropped.
+    pub fn with_skip_seen_ids(mut self, capacity: Option<usize>) -> Self {
+        self.seen_ids = capacity.map(SeenIds::new);
+        self
+    }
+
+    pub(crate) fn seen_ids(&self) -> Option<&SeenIds> {
+        self.seen_ids.as_ref()
+    }
+
+    /// Take over the IDs seen by `old`, the parser this one replaces, so that duplicates are
+    /// still found across a reconnect.
+    pub(crate) fn keep_seen_ids(&mut self, old: &mut EventParser) {
+        if let Some(seen_ids) = old.seen_ids.take() {
+            self.seen_ids = Some(seen_ids);
+        }
+    }
+
+    /// Fail with [`Error::InvalidLine`] once a line grows beyond `max` bytes, rather than
+    /// buffering it indefinitely. By default lines may be any length.
+    pub fn with_max_line_length(mut self, max: Option<usize>) -> Self {
+        self.max_line_length = max;
+        self
+    }
+
+    /// Yield an [`SSE::Partial`] with each piece of event data as it is received, including the
+    /// received part of a `data:` line that hasn't been terminated yet.
+    pub fn with_incremental_data(mut self, enabled: bool) -> Self {
+        self.incremental_data = enabled;
+        self
+    }
+
+    /// Yield an [`SSE::Raw`] with the fields of each event block, as well as the normal events.
+    #[cfg(feature = "raw-events")]
+    pub fn with_raw_events(mut self, enabled: bool) -> Self {
+        self.raw_events = enabled;
+        self
+    }
+
+    /// Log each field at trace level as it is parsed, with the values of the fields named in
+    /// `redacted` hidden. `None` turns the trace off.
+    #[cfg(feature = "trace-fields")]
+    pub fn with_field_trace(mut self, redacted: Option<Vec<String>>) -> Self {
+        self.field_trace = redacted;
+        self
+    }
+
     pub fn was_processing(&self) -> bool {
         if self.incomplete_line.is_some() || !self.complete_lines.is_empty() {
             true
@@ -166,10 +437,32 @@ impl EventParser {
         }
     }
 
+    pub fn peek_event(&self) -> Option<&SSE> {
+        self.sse.front()
+    }
+
     pub fn get_event(&mut self) -> Option<SSE> {
         self.sse.pop_front()
     }
 
+    /// Queue `event` to be yielded as if it had been parsed, unless its ID was seen before.
+    pub(crate) fn queue_event(&mut self, event: Event) {
+        if event.id.is_some() && self.is_seen(event.id.as_deref()) {
+            debug!("dropping event with an ID seen before: {:?}", event.id);
+            return;
+        }
+        self.sse.push_back(SSE::Event(event));
+    }
+
+    /// The event ID set by a block that wasn't dispatched, such as a lone `id:` line, if no event
+    /// has been dispatched since. The spec has such a block change the ID to resume from even
+    /// though no event carries it, so the client takes it when the connection is replaced.
+    pub fn take_undispatched_id(&mut self) -> Option<Option<String>> {
+        self.undispatched_id.take()
+    }
+
+    /// Parse a chunk of bytes received from the server. Any input, split at any point, is
+    /// accepted without panicking; malformed input is reported as an error instead.
     pub fn process_bytes(&mut self, bytes: Bytes) -> Result<()> {
         trace!("Parsing bytes {:?}", bytes);
         // We get bytes from the underlying stream in chunks.  Decoding a chunk has two phases:
@@ -182,8 +475,103 @@ impl EventParser {
         // invocations, and begin by processing any incomplete events from previous invocations,
         // before requesting new input from the underlying stream and processing that.
 
+        let bytes = match self.skip_oversized_line(bytes) {
+            Some(bytes) => bytes,
+            None => return Ok(()),
+        };
         self.decode_and_buffer_lines(bytes);
+        self.check_line_lengths()?;
         self.parse_complete_lines_into_event()?;
+        if self.incremental_data {
+            self.emit_partial_data();
+        }
+
+        Ok(())
+    }
+
+    // Report the part of an unterminated `data:` line's value that is valid UTF-8 and hasn't
+    // been reported yet. The rest is reported once it arrives, or once the line is complete.
+    fn emit_partial_data(&mut self) {
+        let value = match self
+            .incomplete_line
+            .as_deref()
+            .and_then(|line| line.strip_prefix(b"data:"))
+        {
+            Some(value) => value.strip_prefix(b" ").unwrap_or(value),
+            None => return,
+        };
+        let valid = match from_utf8(value) {
+            Ok(valid) => valid,
+            Err(e) => from_utf8(&value[..e.valid_up_to()]).unwrap_or_default(),
+        };
+
+        if valid.len() > self.partial_data_emitted {
+            let mut fragment = String::new();
+            let follows_data = self
+                .event_data
+                .as_ref()
+                .is_some_and(|event_data| !event_data.data.is_empty());
+            if self.partial_data_emitted == 0 && follows_data {
+                fragment.push('\n');
+            }
+            fragment.push_str(&valid[self.partial_data_emitted..]);
+            self.partial_data_emitted = valid.len();
+            self.sse.push_back(SSE::Partial(fragment));
+        }
+    }
+
+    /// Handle the end of the input as if it ended with a blank line, dispatching the event in
+    /// progress (if it has data) even though the server never terminated it.
+    pub fn flush(&mut self) -> Result<()> {
+        if let Some(line) = self.incomplete_line.take() {
+            self.complete_lines.push_back(line);
+        }
+        self.complete_lines.push_back(Vec::new());
+        self.last_char_was_cr = false;
+        self.parse_complete_lines_into_event()
+    }
+
+    // Drop what is left of a line that was reported as oversized before it ended, so that none
+    // of it can be parsed as a field of its own. Returns the rest of `bytes`, if any.
+    fn skip_oversized_line(&mut self, bytes: Bytes) -> Option<Bytes> {
+        if !self.skipping_line {
+            return Some(bytes);
+        }
+        let end = bytes.iter().position(|&b| b == b'\n' || b == b'\r')?;
+        self.skipping_line = false;
+        self.last_char_was_cr = bytes[end] == b'\r';
+        Some(bytes.slice(end + 1..))
+    }
+
+    // Discard buffered input if any line exceeds the configured maximum, so a single oversized
+    // line doesn't linger in the buffers after being reported.
+    fn check_line_lengths(&mut self) -> Result<()> {
+        let max = match self.max_line_length {
+            Some(max) => max,
+            None => return Ok(()),
+        };
+
+        let oversized = self
+            .complete_lines
+            .iter()
+            .chain(self.incomplete_line.iter())
+            .any(|line| line.len() > max);
+
+        if oversized {
+            // An oversized line that hasn't ended yet is skipped until it does.
+            self.skipping_line = self
+                .incomplete_line
+                .as_ref()
+                .is_some_and(|line| line.len() > max);
+            self.complete_lines.clear();
+            self.incomplete_line = None;
+            self.event_data = None;
+            self.partial_data_emitted = 0;
+            return Err(Error::InvalidLine(format!(
+                "line exceeds maximum length of {} bytes",
+                max
+            )));
+        }
 
         Ok(())
     }
@@ -198,6 +586,10 @@ impl EventParser {
             let mut seen_empty_line = false;
 
             while let Some(line) = self.complete_lines.pop_front() {
+                // Only the first line parsed can be one that was partially reported, since it
+                // was the incomplete line before this chunk.
+                let reported = std::mem::take(&mut self.partial_data_emitted);
+
                 if line.is_empty() && self.event_data.is_some() {
                     seen_empty_line = true;
                     break;
@@ -206,11 +598,21 @@ impl EventParser {
                 }
 
                 if let Some((key, value)) = parse_field(&line)? {
+                    #[cfg(feature = "trace-fields")]
+                    if let Some(redacted) = &self.field_trace {
+                        trace!("{}", describe_field(key, value, redacted));
+                    }
+
                     if key == "comment" {
                         self.sse.push_back(SSE::Comment(value.to_string()));
                         continue;
                     }
 
+                    #[cfg(feature = "raw-events")]
+                    if self.raw_events {
+                        self.raw_fields.push((key.to_string(), value.to_string()));
+                    }
+
                     let id = &self.last_event_id;
                     let event_data = self
                         .event_data
@@ -219,6 +621,16 @@ impl EventParser {
                     if key == "event" {
                         event_data.event_type = value.to_string()
                     } else if key == "data" {
+                        if self.incremental_data {
+                            let mut fragment = String::new();
+                            if reported == 0 && !event_data.data.is_empty() {
+                                fragment.push('\n');
+                            }
+                            fragment.push_str(value.get(reported..).unwrap_or_default());
+                            if !fragment.is_empty() {
+                                self.sse.push_back(SSE::Partial(fragment));
+                            }
+                        }
                         event_data.append_data(value);
                     } else if key == "id" {
                         // If id contains a null byte, it is a non-fatal error and the rest of
@@ -234,7 +646,8 @@ impl EventParser {
                             self.last_event_id = Some(value.to_string());
                         }
 
-                        event_data.id = self.last_event_id.clone()
+                        event_data.id = self.last_event_id.clone();
+                        event_data.has_id = true;
                     } else if key == "retry" {
                         match value.parse::<u64>() {
                             Ok(retry) => {
@@ -254,11 +667,52 @@ impl EventParser {
                     event_data.as_ref().map(|event_data| &event_data.event_type)
                 );
 
+                #[cfg(feature = "raw-events")]
+                if self.raw_events && event_data.is_some() {
+                    let fields = std::mem::take(&mut self.raw_fields);
+                    self.sse.push_back(SSE::Raw(fields));
+                }
+
                 if let Some(event_data) = event_data {
-                    match Option::<SSE>::try_from(event_data) {
+                    let has_id = event_data.has_id;
+                    let dispatch = if event_data.data.is_empty() && self.emit_empty_events {
+                        Ok(Some(SSE::Event(event_data.into_event())))
+                    } else if event_data.data == "\n" && self.skip_empty_data {
+                        trace!("dropping event with empty data");
+                        Ok(None)
+                    } else {
+                        Option::<SSE>::try_from(event_data)
+                    };
+                    let dispatch = match dispatch {
+                        Ok(Some(SSE::Event(evt))) if has_id && self.is_seen(evt.id.as_deref()) => {
+                            debug!("dropping event with an ID seen before: {:?}", evt.id);
+                            Ok(None)
+                        }
+                        dispatch => dispatch,
+                    };
+
+                    match dispatch {
                         Err(e) => return Err(e),
+                        Ok(None) if has_id => {
+                            self.undispatched_id = Some(self.last_event_id.clone());
+                        }
                         Ok(None) => (),
-                        Ok(Some(event)) => self.sse.push_back(event),
+                        Ok(Some(event)) => {
+                            self.undispatched_id = None;
+                            if let SSE::Event(evt) = &event {
+                                let is_reset = self.reset_event_type.as_deref().is_some_and(|t| {
+                                    event_type_matches(
+                                        t,
+                                        &evt.event_type,
+                                        self.ignore_event_type_case,
+                                    )
+                                });
+                                if is_reset {
+                                    self.last_event_id = None;
+                                }
+                            }
+                            self.sse.push_back(event)
+                        }
                     };
                 }
 
@@ -273,6 +727,15 @@ impl EventParser {
         Ok(())
     }
 
+    /// Whether `id` has been seen before, remembering it if not. Empty IDs, which clear the last
+    /// event ID, are never counted as seen.
+    fn is_seen(&mut self, id: Option<&str>) -> bool {
+        match (self.seen_ids.as_mut(), id) {
+            (Some(seen_ids), Some(id)) if !id.is_empty() => !seen_ids.insert(id),
+            _ => false,
+        }
+    }
+
     // Decode a chunk into lines and buffer them for subsequent parsing, taking account of
     // incomplete lines from previous chunks.
     fn decode_and_buffer_lines(&mut self, chunk: Bytes) {
@@ -292,23 +755,24 @@ impl EventParser {
                 );
 
                 self.last_char_was_cr = false;
-                if !line.is_empty() {
-                    // Checking the last character handles lines where the last character is a
-                    // terminator, but also where the entire line is a terminator.
-                    match line.last().unwrap() {
-                        b'\r' => {
-                            incomplete_line.extend_from_slice(&line[..line.len() - 1]);
-                            let il = self.incomplete_line.take();
-                            self.complete_lines.push_back(il.unwrap());
-                            self.last_char_was_cr = true;
-                        }
-                        b'\n' => {
-                            incomplete_line.extend_from_slice(&line[..line.len() - 1]);
-                            let il = self.incomplete_line.take();
-                            self.complete_lines.push_back(il.unwrap());
-                        }
-                        _ => incomplete_line.extend_from_slice(line),
-                    };
+                // Checking the last character handles lines where the last character is a
+                // terminator, but also where the entire line is a terminator.
+                match line.split_last() {
+                    Some((b'\r', rest)) => {
+                        incomplete_line.extend_from_slice(rest);
+                        self.complete_lines
+                            .push_back(std::mem::take(incomplete_line));
+                        self.incomplete_line = None;
+                        self.last_char_was_cr = true;
+                    }
+                    Some((b'\n', rest)) => {
+                        incomplete_line.extend_from_slice(rest);
+                        self.complete_lines
+                            .push_back(std::mem::take(incomplete_line));
+                        self.incomplete_line = None;
+                    }
+                    Some(_) => incomplete_line.extend_from_slice(line),
+                    None => (),
                 }
             }
         }
@@ -332,11 +796,11 @@ impl EventParser {
             }
 
             self.last_char_was_cr = false;
-            if line.ends_with(&[b'\r']) {
+            if line.ends_with(b"\r") {
                 self.complete_lines
                     .push_back(line[..line.len() - 1].to_vec());
                 self.last_char_was_cr = true;
-            } else if line.ends_with(&[b'\n']) {
+            } else if line.ends_with(b"\n") {
                 // self isn't a continuation, but rather a line ending with a LF terminator.
                 self.complete_lines
                     .push_back(line[..line.len() - 1].to_vec());
@@ -438,21 +902,66 @@ mod tests {
     }
 
     fn event(typ: &str, data: &str) -> SSE {
-        SSE::Event(Event {
-            data: data.to_string(),
-            id: None,
-            event_type: typ.to_string(),
-            retry: None,
-        })
+        SSE::Event(Event::new(typ, data, None))
     }
 
     fn event_with_id(typ: &str, data: &str, id: &str) -> SSE {
-        SSE::Event(Event {
-            data: data.to_string(),
-            id: Some(id.to_string()),
-            event_type: typ.to_string(),
-            retry: None,
-        })
+        SSE::Event(Event::new(typ, data, Some(id.to_string())))
+    }
+
+    #[test]
+    fn test_event_new_matches_parsed_event() {
+        let mut parser = EventParser::new();
+        parser
+            .process_bytes(Bytes::from("event: put\nid: 7\nretry: 500\ndata: x\n\n"))
+            .unwrap();
+
+        let expected = Event::new("put", "x", Some("7".to_string())).with_retry(500);
+        assert_eq!(parser.get_event(), Some(SSE::Event(expected)));
+    }
+
+    #[test_case(Event::new("put", "x", Some("7".to_string())).with_retry(500); "all fields")]
+    #[test_case(Event::new("message", "one\ntwo\n", None); "multi-line data")]
+    #[test_case(Event::new("message", " padded", None); "leading space")]
+    #[test_case(Event::new("message", "", Some(String::new())); "empty")]
+    fn test_to_sse_round_trips(event: Event) {
+        let mut parser = EventParser::new();
+        parser.process_bytes(Bytes::from(event.to_sse())).unwrap();
+        assert_eq!(parser.get_event(), Some(SSE::Event(event)));
+        assert_eq!(parser.get_event(), None);
+    }
+
+    #[test]
+    fn test_carriage_returns_in_data_are_encoded_as_line_breaks() {
+        let mut parser = EventParser::new();
+        let sent = Event::new("message", "a\rb\r\nc", None);
+        parser
+            .process_bytes(Bytes::from(encode_event(&sent)))
+            .unwrap();
+        assert_eq!(parser.get_event(), Some(event("message", "a\nb\nc")));
+    }
+
+    #[test]
+    fn test_encode_events_round_trips_parsed_events() {
+        let body = "event: put\nid: 1\ndata: {}\n\n: keep-alive\n\ndata: one\ndata: two\n\n";
+        let mut parser = EventParser::new();
+        parser.process_bytes(Bytes::from(body)).unwrap();
+        let mut events = Vec::new();
+        while let Some(sse) = parser.get_event() {
+            if let SSE::Event(event) = sse {
+                events.push(event);
+            }
+        }
+        assert_eq!(events.len(), 2);
+
+        let mut parser = EventParser::new();
+        parser
+            .process_bytes(Bytes::from(encode_events(&events)))
+            .unwrap();
+        for event in events {
+            assert_eq!(parser.get_event(), Some(SSE::Event(event)));
+        }
+        assert_eq!(parser.get_event(), None);
     }
 
     #[test]
@@ -513,6 +1022,186 @@ mod tests {
         require_pop_event(&mut parser, |e| assert_eq!(e.id, Some("3".into())));
     }
 
+    #[test]
+    fn test_reset_event_type_clears_last_event_id() {
+        let mut parser = EventParser::new().with_reset_event_type(Some("reset".into()));
+        assert!(parser
+            .process_bytes(Bytes::from(
+                "id: 1\ndata: a\n\nevent: reset\ndata: b\n\ndata: c\n\n"
+            ))
+            .is_ok());
+
+        require_pop_event(&mut parser, |e| assert_eq!(e.id, Some("1".into())));
+        require_pop_event(&mut parser, |e| assert_eq!(e.id, Some("1".into())));
+        require_pop_event(&mut parser, |e| assert_eq!(e.id, None));
+    }
+
+    #[test]
+    fn test_lone_id_is_kept_for_resuming() {
+        let mut parser = EventParser::new();
+        assert!(parser
+            .process_bytes(Bytes::from("id: 1\ndata: a\n\nid\n\n"))
+            .is_ok());
+        require_pop_event(&mut parser, |e| assert_eq!(e.id, Some("1".into())));
+        assert!(parser.get_event().is_none());
+        assert_eq!(parser.take_undispatched_id(), Some(Some("".into())));
+        assert_eq!(parser.take_undispatched_id(), None);
+
+        // A block that is dispatched supersedes it.
+        assert!(parser
+            .process_bytes(Bytes::from("id: 2\n\ndata: b\n\n"))
+            .is_ok());
+        require_pop_event(&mut parser, |e| assert_eq!(e.id, Some("2".into())));
+        assert_eq!(parser.take_undispatched_id(), None);
+    }
+
+    #[test]
+    fn test_bare_data_line_dispatches_empty_data() {
+        let mut parser = EventParser::new();
+        assert!(parser.process_bytes(Bytes::from("data\n\n")).is_ok());
+        assert_eq!(parser.get_event(), Some(event("message", "")));
+
+        assert!(parser
+            .process_bytes(Bytes::from("data: a\ndata\ndata: b\n\n"))
+            .is_ok());
+        assert_eq!(parser.get_event(), Some(event("message", "a\n\nb")));
+        assert!(parser.get_event().is_none());
+    }
+
+    #[test]
+    fn test_bare_event_line_uses_default_type() {
+        let mut parser = EventParser::new();
+        assert!(parser
+            .process_bytes(Bytes::from("event: add\nevent\ndata: x\n\n"))
+            .is_ok());
+        assert_eq!(parser.get_event(), Some(event("message", "x")));
+    }
+
+    #[test]
+    fn test_bare_id_line_clears_id() {
+        let mut parser = EventParser::new();
+        assert!(parser
+            .process_bytes(Bytes::from("id: 1\ndata: a\n\nid\ndata: b\n\ndata: c\n\n"))
+            .is_ok());
+        assert_eq!(parser.get_event(), Some(event_with_id("message", "a", "1")));
+        assert_eq!(parser.get_event(), Some(event_with_id("message", "b", "")));
+        assert_eq!(parser.get_event(), Some(event_with_id("message", "c", "")));
+    }
+
+    #[test_case("event\n\n"; "bare event")]
+    #[test_case("unknown\n\n"; "bare unknown field")]
+    #[test_case("unknown: value\n\n"; "unknown field")]
+    fn test_event_without_data_fields_is_ignored(chunk: &'static str) {
+        let mut parser = EventParser::new();
+        assert!(parser.process_bytes(Bytes::from(chunk)).is_ok());
+        assert!(parser.get_event().is_none());
+
+        assert!(parser.process_bytes(Bytes::from("data: next\n\n")).is_ok());
+        assert_eq!(parser.get_event(), Some(event("message", "next")));
+    }
+
+    #[test_case(&["data: \0a\0\0b\0\n\n"], "\0a\0\0b\0"; "in one chunk")]
+    #[test_case(&["data: \0", "a\0", "\0b", "\0\n", "\n"], "\0a\0\0b\0"; "split at each NUL")]
+    #[test_case(&["data:", " \0a\0\0b\0\r", "\n\r\n"], "\0a\0\0b\0"; "before a CRLF pair")]
+    #[test_case(&["data: \0a\0\n", "data:\0\n\n"], "\0a\0\n\0"; "on several lines")]
+    fn test_nul_bytes_in_data_are_kept(chunks: &[&'static str], expected: &str) {
+        let mut parser = EventParser::new();
+        for chunk in chunks {
+            assert!(parser.process_bytes(Bytes::from(*chunk)).is_ok());
+        }
+        assert_eq!(parser.get_event(), Some(event("message", expected)));
+        assert!(parser.get_event().is_none());
+    }
+
+    #[test_case(&["", "data: hello\n\n", ""]; "around an event")]
+    #[test_case(&["data: hel", "", "lo\n", "", "\n"]; "within an event")]
+    #[test_case(&["data: hello\r", "", "\n\r", "", "\n"]; "within a CRLF pair")]
+    fn test_empty_chunks_are_ignored(chunks: &[&'static str]) {
+        for emit_empty_events in [false, true] {
+            let mut parser = EventParser::new().with_emit_empty_events(emit_empty_events);
+            for chunk in chunks {
+                assert!(parser.process_bytes(Bytes::from(*chunk)).is_ok());
+            }
+            assert_eq!(parser.get_event(), Some(event("message", "hello")));
+            assert!(parser.get_event().is_none());
+        }
+    }
+
+    #[test_case("\n\n\ndata: first\n\n"; "LF")]
+    #[test_case("\r\r\rdata: first\r\r"; "CR")]
+    #[test_case("\r\n\r\n\r\ndata: first\r\n\r\n"; "CRLF")]
+    fn test_leading_blank_lines_produce_no_events(chunk: &'static str) {
+        for emit_empty_events in [false, true] {
+            let mut parser = EventParser::new().with_emit_empty_events(emit_empty_events);
+            assert!(parser.process_bytes(Bytes::from(chunk)).is_ok());
+            assert_eq!(parser.get_event(), Some(event("message", "first")));
+            assert!(parser.get_event().is_none());
+        }
+    }
+
+    #[test]
+    fn test_emit_empty_events() {
+        let chunk = "\n\nevent: ping\n\nid: 1\n\ndata: x\n\n";
+
+        let mut parser = EventParser::new();
+        assert!(parser.process_bytes(Bytes::from(chunk)).is_ok());
+        assert_eq!(parser.get_event(), Some(event_with_id("message", "x", "1")));
+        assert!(parser.get_event().is_none());
+
+        let mut parser = EventParser::new().with_emit_empty_events(true);
+        assert!(parser.process_bytes(Bytes::from(chunk)).is_ok());
+        assert_eq!(parser.get_event(), Some(event("ping", "")));
+        assert_eq!(parser.get_event(), Some(event_with_id("message", "", "1")));
+        assert_eq!(parser.get_event(), Some(event_with_id("message", "x", "1")));
+        assert!(parser.get_event().is_none());
+    }
+
+    #[test]
+    fn test_skip_empty_data() {
+        let chunk = "data:\n\nid: 1\ndata\n\nevent: ping\n\ndata: x\n\n";
+
+        let mut parser = EventParser::new().with_emit_empty_events(true);
+        assert!(parser.process_bytes(Bytes::from(chunk)).is_ok());
+        assert_eq!(parser.get_event(), Some(event("message", "")));
+        assert_eq!(parser.get_event(), Some(event_with_id("message", "", "1")));
+        assert_eq!(parser.get_event(), Some(event_with_id("ping", "", "1")));
+        assert_eq!(parser.get_event(), Some(event_with_id("message", "x", "1")));
+        assert!(parser.get_event().is_none());
+
+        let mut parser = EventParser::new()
+            .with_emit_empty_events(true)
+            .with_skip_empty_data(true);
+        assert!(parser.process_bytes(Bytes::from(chunk)).is_ok());
+        // Only the events with an empty data field are dropped, and the ID is still kept.
+        assert_eq!(parser.get_event(), Some(event_with_id("ping", "", "1")));
+        assert_eq!(parser.get_event(), Some(event_with_id("message", "x", "1")));
+        assert!(parser.get_event().is_none());
+    }
+
+    #[test]
+    fn test_skip_seen_ids() {
+        let mut parser = EventParser::new().with_skip_seen_ids(Some(2));
+        let chunk = concat!(
+            "id: 1\ndata: a\n\n",
+            // Inherits the ID "1", but has no `id:` field of its own.
+            "data: b\n\n",
+            "id: 2\ndata: c\n\n",
+            "id: 1\ndata: d\n\n",
+            "id: 3\ndata: e\n\n",
+            // "1" has been evicted to make room for "3".
+            "id: 1\ndata: f\n\n",
+        );
+        assert!(parser.process_bytes(Bytes::from(chunk)).is_ok());
+
+        let mut data = Vec::new();
+        while let Some(SSE::Event(event)) = parser.get_event() {
+            data.push(event.data);
+        }
+        assert_eq!(data, vec!["a", "b", "c", "e", "f"]);
+        let seen_ids = parser.seen_ids().unwrap();
+        assert_eq!((seen_ids.len(), seen_ids.evictions()), (2, 2));
+    }
+
     #[test_case(b":hello\n"; "with LF")]
     #[test_case(b":hello\r"; "with CR")]
     #[test_case(b":hello\r\n"; "with CRLF")]
@@ -688,6 +1377,58 @@ mod tests {
         });
     }
 
+    fn drain_events(parser: &mut EventParser) -> Vec<SSE> {
+        std::iter::from_fn(|| parser.get_event()).collect()
+    }
+
+    #[test_case("one-event.sse"; "one-event.sse")]
+    #[test_case("one-event-crlf.sse"; "one-event-crlf.sse")]
+    #[test_case("two-events.sse"; "two-events.sse")]
+    #[test_case("two-events-crlf.sse"; "two-events-crlf.sse")]
+    #[test_case("persistent-event-id.sse"; "persistent-event-id.sse")]
+    #[test_case("big-event-followed-by-another.sse"; "big-event-followed-by-another.sse")]
+    #[test_case("big-event-followed-by-another-crlf.sse"; "big-event-followed-by-another-crlf.sse")]
+    fn test_decode_file_one_byte_at_a_time(file: &str) {
+        let contents = read_contents_from_file(file);
+
+        let mut whole = EventParser::new();
+        assert!(whole.process_bytes(Bytes::from(contents.clone())).is_ok());
+        let expected = drain_events(&mut whole);
+        assert!(!expected.is_empty());
+
+        let mut parser = EventParser::new();
+        let mut actual = Vec::new();
+        for byte in contents {
+            assert!(parser.process_bytes(Bytes::from(vec![byte])).is_ok());
+            actual.extend(drain_events(&mut parser));
+        }
+
+        assert_eq!(actual, expected);
+        assert!(!parser.was_processing());
+    }
+
+    #[test_case("data: hello\n\ndata: world\n\n"; "with lf")]
+    #[test_case("data: hello\r\rdata: world\r\r"; "with cr")]
+    #[test_case("data: hello\r\n\r\ndata: world\r\n\r\n"; "with crlf")]
+    #[test_case(":ping\r\ndata: hello\r\n\r\n:ping\r\ndata: world\r\n\r\n"; "with comments")]
+    fn test_decode_events_one_byte_at_a_time(stream: &'static str) {
+        let mut parser = EventParser::new();
+        let mut events = Vec::new();
+        for byte in stream.bytes() {
+            assert!(parser.process_bytes(Bytes::from(vec![byte])).is_ok());
+            events.extend(
+                drain_events(&mut parser)
+                    .into_iter()
+                    .filter(|sse| matches!(sse, SSE::Event(_))),
+            );
+        }
+
+        assert_eq!(
+            events,
+            vec![event("message", "hello"), event("message", "world")]
+        );
+    }
+
     fn read_contents_from_file(name: &str) -> Vec<u8> {
         std::fs::read(format!("test-data/{}", name))
             .unwrap_or_else(|_| panic!("couldn't read {}", name))
@@ -700,5 +1441,153 @@ mod tests {
             parser.incomplete_line = Some(previous.as_bytes().to_vec());
             parser.decode_and_buffer_lines(Bytes::from(next));
         }
+
+        #[test]
+        fn test_encode_event_round_trips(event_type in "[a-z]{1,8}", data in "[^\r]*", id in proptest::option::of("[^\r\n\x00]*"), retry in proptest::option::of(proptest::num::u64::ANY)) {
+            let mut event = Event::new(event_type, data, id);
+            event.retry = retry;
+            let mut parser = EventParser::new();
+            parser.process_bytes(Bytes::from(encode_event(&event))).unwrap();
+            proptest::prop_assert_eq!(parser.get_event(), Some(SSE::Event(event)));
+        }
+
+        #[test]
+        fn test_process_bytes_does_not_panic(input in proptest::collection::vec(proptest::num::u8::ANY, 0..512), splits in proptest::collection::vec(0usize..512, 0..8)) {
+            let mut parser = EventParser::new()
+                .with_max_line_length(Some(64))
+                .with_incremental_data(true);
+            let mut splits: Vec<_> = splits.into_iter().map(|i| i.min(input.len())).collect();
+            splits.sort_unstable();
+
+            let mut start = 0;
+            for end in splits.into_iter().chain(std::iter::once(input.len())) {
+                let _ = parser.process_bytes(Bytes::copy_from_slice(&input[start..end]));
+                while parser.get_event().is_some() {}
+                start = end;
+            }
+        }
+    }
+
+    #[cfg(feature = "trace-fields")]
+    #[test_case("data", "hello", "field \"data\": \"hello\""; "recognized")]
+    #[test_case("token", "abc", "field \"token\": \"<redacted>\" (unrecognized, ignored)"; "redacted")]
+    #[test_case("dat", "hello", "field \"dat\": \"hello\" (unrecognized, ignored)"; "unrecognized")]
+    fn test_describe_field(key: &str, value: &str, expected: &str) {
+        assert_eq!(describe_field(key, value, &["token".to_string()]), expected);
+    }
+
+    #[cfg(feature = "raw-events")]
+    #[test]
+    fn test_raw_events_preserve_field_order() {
+        let mut parser = EventParser::new().with_raw_events(true);
+        parser
+            .process_bytes(Bytes::from(
+                "data: a\n:hi\nid: 1\ndata: b\nfoo\n\nevent: ping\n\n",
+            ))
+            .unwrap();
+
+        let fields = |pairs: &[(&str, &str)]| {
+            SSE::Raw(
+                pairs
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+            )
+        };
+        assert_eq!(parser.get_event(), Some(SSE::Comment("hi".to_string())));
+        assert_eq!(
+            parser.get_event(),
+            Some(fields(&[
+                ("data", "a"),
+                ("id", "1"),
+                ("data", "b"),
+                ("foo", "")
+            ]))
+        );
+        assert_eq!(
+            parser.get_event(),
+            Some(event_with_id("message", "a\nb", "1"))
+        );
+        // a block without data isn't dispatched as an event, but is still reported raw
+        assert_eq!(parser.get_event(), Some(fields(&[("event", "ping")])));
+        assert_eq!(parser.get_event(), None);
+    }
+
+    #[test]
+    fn test_incremental_data_reports_pieces_as_received() {
+        let mut parser = EventParser::new().with_incremental_data(true);
+        let mut pieces = Vec::new();
+        // The "é" is split between chunks, so it can't be reported until it's complete.
+        for chunk in [
+            &b"data: Hel"[..],
+            b"lo w",
+            b"orld \xc3",
+            b"\xa9\ndata: ",
+            b"two\n\n",
+        ] {
+            parser.process_bytes(Bytes::copy_from_slice(chunk)).unwrap();
+            while let Some(sse) = parser.get_event() {
+                pieces.push(sse);
+            }
+        }
+
+        let partial = |s: &str| SSE::Partial(s.to_string());
+        assert_eq!(
+            pieces,
+            vec![
+                partial("Hel"),
+                partial("lo w"),
+                partial("orld "),
+                partial("é"),
+                partial("\ntwo"),
+                event("message", "Hello world é\ntwo"),
+            ]
+        );
+    }
+
+    #[test_case("data: last"; "unterminated line")]
+    #[test_case("data: last\n"; "unterminated event")]
+    fn test_flush_dispatches_pending_event(chunk: &'static str) {
+        let mut parser = EventParser::new();
+        parser.process_bytes(Bytes::from(chunk)).unwrap();
+        assert_eq!(parser.get_event(), None);
+
+        parser.flush().unwrap();
+        assert_eq!(parser.get_event(), Some(event("message", "last")));
+        assert!(!parser.was_processing());
+    }
+
+    #[test]
+    fn test_logify_truncates_on_char_boundary() {
+        let line = "é".repeat(LOGIFY_MAX_CHARS);
+        assert!(logify(line.as_bytes()).len() < LOGIFY_MAX_CHARS);
+    }
+
+    #[test]
+    fn test_oversized_line_is_an_error() {
+        let mut parser = EventParser::new().with_max_line_length(Some(8));
+        let res = parser.process_bytes(Bytes::from("data: much too long"));
+        assert!(matches!(res, Err(InvalidLine(_))));
+
+        // the oversized input is discarded, so parsing can continue
+        assert!(parser.process_bytes(Bytes::from("\ndata: ok\n\n")).is_ok());
+        assert_eq!(parser.get_event(), Some(event("message", "ok")));
+    }
+
+    #[test_case(&["id: evil\n", "data: ok\n\n"]; "tail ends with lf")]
+    #[test_case(&["more id: evil\r", "\ndata: ok\n\n"]; "tail ends with split crlf")]
+    #[test_case(&["id:", " evil", "\rdata: ok\n\n"]; "tail over several chunks")]
+    fn test_rest_of_oversized_line_is_skipped(tail: &[&str]) {
+        let mut parser = EventParser::new().with_max_line_length(Some(8));
+        let res = parser.process_bytes(Bytes::from("data: much too long"));
+        assert!(matches!(res, Err(InvalidLine(_))));
+
+        for chunk in tail {
+            parser
+                .process_bytes(Bytes::from(chunk.to_string()))
+                .unwrap();
+        }
+        assert_eq!(parser.get_event(), Some(event("message", "ok")));
+        assert_eq!(parser.get_event(), None);
     }
 }
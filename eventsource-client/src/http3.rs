@@ -0,0 +1,272 @@
+use futures::{future::FutureExt, Future};
+use hyper::{body::Buf, header, service::Service, Body, Request, Response};
+use std::{
+    fmt::{self, Debug, Formatter},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use quinn::rustls;
+
+use crate::client::{AbortOnDrop, BoxError};
+
+type ResponseFuture = Pin<Box<dyn Future<Output = Result<Response<Body>, BoxError>> + Send + Sync>>;
+
+/// Headers that HTTP/3 doesn't allow, as they describe an HTTP/1.1 connection.
+const CONNECTION_HEADERS: [&str; 5] = [
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// An experimental HTTP [`Service`] that sends each request over HTTP/3, using [quinn] for QUIC
+/// and [h3] for HTTP/3. Streams built with
+/// [`ClientBuilder::build_http3`](crate::ClientBuilder::build_http3) use one with the default
+/// TLS configuration; pass one to
+/// [`ClientBuilder::build_with_service`](crate::ClientBuilder::build_with_service) to trust other
+/// certificates.
+///
+/// Each request is sent over a QUIC connection of its own, which is closed once the response
+/// body is dropped, so every reconnect makes a new connection. Only `https` URLs are supported,
+/// and the server must offer HTTP/3 on the URL's port; there is no fallback to HTTP/1.1 or
+/// HTTP/2, nor discovery through `Alt-Svc`. Must be used within a tokio runtime.
+///
+/// [quinn]: https://docs.rs/quinn
+/// [h3]: https://docs.rs/h3
+#[derive(Clone)]
+pub struct Http3Service {
+    tls: Arc<rustls::ClientConfig>,
+}
+
+impl Http3Service {
+    /// Create a service that trusts the Mozilla root certificates provided by the
+    /// `webpki-roots` crate.
+    pub fn new() -> Self {
+        let mut roots = rustls::RootCertStore::empty();
+        roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        let tls = rustls::ClientConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .expect("the ring provider supports TLS 1.3")
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+        Self::with_tls_config(tls)
+    }
+
+    /// Create a service that connects with `tls`, which must allow TLS 1.3, as QUIC requires.
+    /// Its ALPN protocols are replaced with HTTP/3's.
+    pub fn with_tls_config(mut tls: rustls::ClientConfig) -> Self {
+        tls.alpn_protocols = vec![b"h3".to_vec()];
+        Self { tls: Arc::new(tls) }
+    }
+}
+
+impl Default for Http3Service {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for Http3Service {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Http3Service").finish_non_exhaustive()
+    }
+}
+
+impl Service<Request<Body>> for Http3Service {
+    type Response = Response<Body>;
+    type Error = BoxError;
+    type Future = ResponseFuture;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        // The connection's futures aren't `Sync`, so the request is made on a task of its own.
+        let task = AbortOnDrop(tokio::spawn(send(self.tls.clone(), request)));
+        Box::pin(task.map(|joined| joined.unwrap_or_else(|e| Err(BoxError::from(e)))))
+    }
+}
+
+async fn send(
+    tls: Arc<rustls::ClientConfig>,
+    request: Request<Body>,
+) -> Result<Response<Body>, BoxError> {
+    let uri = request.uri().clone();
+    if uri.scheme_str() != Some("https") {
+        return Err(format!("HTTP/3 requires an https URL, not {}", uri).into());
+    }
+    let host = uri
+        .host()
+        .ok_or_else(|| format!("URL {} has no host", uri))?
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    let port = uri.port_u16().unwrap_or(443);
+    let addr = tokio::net::lookup_host((host, port))
+        .await?
+        .next()
+        .ok_or_else(|| format!("{} has no addresses", host))?;
+
+    let local: SocketAddr = if addr.is_ipv6() {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    };
+    let mut endpoint = quinn::Endpoint::client(local)?;
+    let crypto = quinn::crypto::rustls::QuicClientConfig::try_from(tls)?;
+    endpoint.set_default_client_config(quinn::ClientConfig::new(Arc::new(crypto)));
+    let connection = endpoint.connect(addr, host)?.await?;
+
+    let (mut driver, mut send_request) =
+        h3::client::new(h3_quinn::Connection::new(connection)).await?;
+    let driver = AbortOnDrop(tokio::spawn(async move {
+        futures::future::poll_fn(|cx| driver.poll_close(cx)).await;
+    }));
+
+    let (parts, body) = request.into_parts();
+    let mut builder = http::Request::builder()
+        .method(parts.method.as_str())
+        .uri(uri.to_string());
+    for (name, value) in &parts.headers {
+        if *name != header::HOST && !CONNECTION_HEADERS.contains(&name.as_str()) {
+            builder = builder.header(name.as_str(), value.as_bytes());
+        }
+    }
+    let body = hyper::body::to_bytes(body).await?;
+
+    let mut stream = send_request.send_request(builder.body(())?).await?;
+    if !body.is_empty() {
+        stream.send_data(body).await?;
+    }
+    stream.finish().await?;
+    let response = stream.recv_response().await?;
+
+    let mut builder = Response::builder()
+        .status(response.status().as_u16())
+        .version(hyper::Version::HTTP_3);
+    for (name, value) in response.headers() {
+        builder = builder.header(name.as_str(), value.as_bytes());
+    }
+    // The connection, and the endpoint it was made from, are kept until the body is dropped.
+    let connection = (send_request, driver, endpoint);
+    let chunks = futures::stream::unfold(Some((stream, connection)), |state| async move {
+        let (mut stream, connection) = state?;
+        match stream.recv_data().await {
+            Ok(Some(mut chunk)) => {
+                let chunk = chunk.copy_to_bytes(chunk.remaining());
+                Some((Ok(chunk), Some((stream, connection))))
+            }
+            Ok(None) => None,
+            Err(e) => Some((Err(BoxError::from(e)), None)),
+        }
+    });
+    Ok(builder.body(Body::wrap_stream(chunks))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Client, ClientBuilder, SSE};
+    use futures::StreamExt;
+    use hyper::body::Bytes;
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+
+    /// Start an HTTP/3 server on a local port that answers one request with an event, returning
+    /// the port, the certificate it presents, and the request it receives.
+    fn serve_one_event() -> (
+        u16,
+        CertificateDer<'static>,
+        tokio::task::JoinHandle<http::Request<()>>,
+    ) {
+        let key = rcgen::generate_simple_self_signed(vec!["127.0.0.1".to_string()]).unwrap();
+        let cert = key.cert.der().clone();
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let mut tls = rustls::ServerConfig::builder_with_provider(provider)
+            .with_protocol_versions(&[&rustls::version::TLS13])
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![cert.clone()],
+                PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key.signing_key.serialize_der())),
+            )
+            .unwrap();
+        tls.alpn_protocols = vec![b"h3".to_vec()];
+        let crypto = quinn::crypto::rustls::QuicServerConfig::try_from(tls).unwrap();
+        let endpoint = quinn::Endpoint::server(
+            quinn::ServerConfig::with_crypto(Arc::new(crypto)),
+            (Ipv4Addr::LOCALHOST, 0).into(),
+        )
+        .unwrap();
+        let port = endpoint.local_addr().unwrap().port();
+
+        let server = tokio::spawn(async move {
+            let connection = endpoint.accept().await.unwrap().await.unwrap();
+            let mut connection: h3::server::Connection<_, Bytes> =
+                h3::server::Connection::new(h3_quinn::Connection::new(connection))
+                    .await
+                    .unwrap();
+            let resolver = connection.accept().await.unwrap().unwrap();
+            let (request, mut stream) = resolver.resolve_request().await.unwrap();
+            let response = http::Response::builder()
+                .status(200)
+                .header("content-type", "text/event-stream")
+                .body(())
+                .unwrap();
+            stream.send_response(response).await.unwrap();
+            stream
+                .send_data(Bytes::from_static(b"data: hello\n\n"))
+                .await
+                .unwrap();
+            stream.finish().await.unwrap();
+            // Keep the connection open until the client closes it.
+            let _ = connection.accept().await;
+            request
+        });
+        (port, cert, server)
+    }
+
+    #[tokio::test]
+    async fn test_streams_events_over_http3() {
+        let (port, cert, server) = serve_one_event();
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(cert).unwrap();
+        let tls = rustls::ClientConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+        let client = ClientBuilder::for_url(&format!("https://127.0.0.1:{}/stream", port))
+            .unwrap()
+            .header("x-test", "yes")
+            .unwrap()
+            .build_with_service(Http3Service::with_tls_config(tls));
+        let mut stream = client.stream();
+        match stream.next().await {
+            Some(Ok(SSE::Event(event))) => assert_eq!(event.data, "hello"),
+            other => panic!("expected an event, got {:?}", other),
+        }
+        drop(stream);
+
+        let request = server.await.unwrap();
+        assert_eq!(request.uri().path(), "/stream");
+        assert_eq!(request.headers()["x-test"], "yes");
+    }
+
+    #[tokio::test]
+    async fn test_requires_https() {
+        let request = Request::get("http://example.com/stream")
+            .body(Body::empty())
+            .unwrap();
+        let err = Http3Service::new().call(request).await.unwrap_err();
+        assert!(err.to_string().contains("https"), "{}", err);
+    }
+}
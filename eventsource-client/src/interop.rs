@@ -0,0 +1,105 @@
+use futures::Stream;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use crate::client::BoxStream;
+use crate::error::Result;
+use crate::event_parser::{Event, SSE};
+
+/// An event in the shape used by other SSE crates, such as `eventsource-stream`, for code that is
+/// written against that shape.
+///
+/// Unlike [`Event`], a missing ID is represented by an empty string, and the retry hint is a
+/// [`Duration`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InteropEvent {
+    pub event: String,
+    pub data: String,
+    pub id: String,
+    pub retry: Option<Duration>,
+}
+
+impl From<Event> for InteropEvent {
+    fn from(event: Event) -> Self {
+        InteropEvent {
+            event: event.event_type,
+            data: event.data,
+            id: event.id.unwrap_or_default(),
+            retry: event.retry.map(Duration::from_millis),
+        }
+    }
+}
+
+/// Convert an event stream into a stream of [`InteropEvent`]s. Comments, and any other items that
+/// aren't events, are dropped; errors are passed through unchanged.
+pub fn interop_stream(stream: BoxStream<Result<SSE>>) -> BoxStream<Result<InteropEvent>> {
+    Box::pin(Interop { stream })
+}
+
+struct Interop {
+    stream: BoxStream<Result<SSE>>,
+}
+
+impl Stream for Interop {
+    type Item = Result<InteropEvent>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            return match futures::ready!(self.stream.as_mut().poll_next(cx)) {
+                Some(Ok(SSE::Event(event))) => Poll::Ready(Some(Ok(event.into()))),
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => Poll::Ready(Some(Err(e))),
+                None => Poll::Ready(None),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+    use futures::{executor::block_on, stream, StreamExt};
+
+    #[test]
+    fn test_from_event() {
+        let event = Event {
+            event_type: "put".to_string(),
+            data: "{}".to_string(),
+            id: None,
+            retry: Some(1500),
+        };
+
+        assert_eq!(
+            InteropEvent::from(event),
+            InteropEvent {
+                event: "put".to_string(),
+                data: "{}".to_string(),
+                id: String::new(),
+                retry: Some(Duration::from_millis(1500)),
+            }
+        );
+    }
+
+    #[test]
+    fn test_interop_stream_drops_comments() {
+        let source: BoxStream<Result<SSE>> = Box::pin(stream::iter(0..3).map(|i| match i {
+            0 => Ok(SSE::Comment("ping".to_string())),
+            1 => Ok(SSE::Event(Event {
+                event_type: "message".to_string(),
+                data: "hello".to_string(),
+                id: Some("1".to_string()),
+                retry: None,
+            })),
+            _ => Err(Error::Eof),
+        }));
+
+        let items: Vec<_> = block_on(interop_stream(source).collect());
+        assert_eq!(items.len(), 2);
+        assert!(matches!(&items[0], Ok(event) if event.data == "hello" && event.id == "1"));
+        assert!(matches!(items[1], Err(Error::Eof)));
+    }
+}
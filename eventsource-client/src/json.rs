@@ -0,0 +1,96 @@
+use log::debug;
+use serde::de::DeserializeOwned;
+use std::pin::Pin;
+
+use crate::client::{BoxError, ReconnectingRequest};
+use crate::error::{Error, Result};
+use hyper::{service::Service, Body, Request, Response};
+
+/// What [`ReconnectingRequest::for_each_json`] does with an event whose data can't be
+/// deserialized.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum JsonErrorPolicy {
+    /// Log the error and carry on with the next event (the default).
+    #[default]
+    Skip,
+    /// Stop, returning [`Error::InvalidJson`].
+    Stop,
+}
+
+impl<S> ReconnectingRequest<S> {
+    /// Deserialize the data of each event of the stream as JSON into a `T`, and call `f` with
+    /// it, until the stream ends.
+    ///
+    /// Events whose data isn't a valid `T` are handled according to `policy`. Comments and other
+    /// items are skipped. Errors from the stream are logged and skipped, as the stream
+    /// reconnects after them, and this returns `Ok` once the stream is closed.
+    pub async fn for_each_json<T, F>(
+        mut self: Pin<&mut Self>,
+        policy: JsonErrorPolicy,
+        mut f: F,
+    ) -> Result<()>
+    where
+        T: DeserializeOwned,
+        F: FnMut(T),
+        S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + Sync + 'static,
+        S::Error: Into<BoxError>,
+        S::Future: Send + Sync + 'static,
+    {
+        while let Some(event) = self.as_mut().next_event().await {
+            match serde_json::from_str(&event.data) {
+                Ok(value) => f(value),
+                Err(e) if policy == JsonErrorPolicy::Skip => {
+                    debug!(
+                        "skipping {:?} event with invalid data: {}",
+                        event.event_type, e
+                    )
+                }
+                Err(e) => return Err(Error::InvalidJson(Box::new(e))),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ClientBuilder;
+    use futures::executor::block_on;
+    use serde::Deserialize;
+    use test_case::test_case;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Flag {
+        key: String,
+    }
+
+    #[test_case(JsonErrorPolicy::Skip; "skip")]
+    #[test_case(JsonErrorPolicy::Stop; "stop")]
+    fn test_for_each_json(policy: JsonErrorPolicy) {
+        let builder = ClientBuilder::for_url("http://example.com/stream").unwrap();
+        let mut request = Box::pin(builder.build_stream_with_http_client(hyper::Client::new()));
+        request.as_mut().receive_for_test(
+            "data: {\"key\":\"a\"}\n\n:ping\n\ndata: nope\n\ndata: {\"key\":\"b\"}\n\n",
+        );
+        request.as_mut().shutdown();
+
+        let mut keys = Vec::new();
+        let result = block_on(
+            request
+                .as_mut()
+                .for_each_json(policy, |flag: Flag| keys.push(flag.key)),
+        );
+
+        match policy {
+            JsonErrorPolicy::Skip => {
+                assert!(result.is_ok());
+                assert_eq!(keys, vec!["a", "b"]);
+            }
+            JsonErrorPolicy::Stop => {
+                assert!(matches!(result, Err(Error::InvalidJson(_))));
+                assert_eq!(keys, vec!["a"]);
+            }
+        }
+    }
+}
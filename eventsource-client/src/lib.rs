@@ -14,7 +14,8 @@
 //! let mut stream = Box::pin(client.stream())
 //!     .map_ok(|event| match event {
 //!         SSE::Comment(comment) => println!("got a comment event: {:?}", comment),
-//!         SSE::Event(evt) => println!("got an event: {}", evt.event_type)
+//!         SSE::Event(evt) => println!("got an event: {}", evt.event_type),
+//!         _ => {}
 //!     })
 //!     .map_err(|e| println!("error streaming events: {:?}", e));
 //! # while let Ok(Some(_)) = stream.try_next().await {}
@@ -26,14 +27,52 @@
 //![Server-Sent Events]: https://html.spec.whatwg.org/multipage/server-sent-events.html
 //![EventSource]: https://developer.mozilla.org/en-US/docs/Web/API/EventSource
 
+mod broadcast;
+mod circuit;
 mod client;
 mod config;
+mod connector;
+#[cfg(feature = "cookies")]
+mod cookies;
 mod error;
 mod event_parser;
+#[cfg(feature = "http3")]
+mod http3;
+#[cfg(feature = "interop")]
+mod interop;
+#[cfg(feature = "json")]
+mod json;
+mod merge;
+mod observer;
+mod rate;
 mod retry;
+mod seen_ids;
+mod take_events;
+#[cfg(feature = "test-util")]
+mod testing;
+mod timer;
 
+pub use broadcast::EventBroadcast;
 pub use client::*;
 pub use config::*;
+pub use connector::{BoxConnection, BoxConnector};
+#[cfg(feature = "cookies")]
+pub use cookies::CookieJar;
 pub use error::*;
-pub use event_parser::Event;
+#[cfg(feature = "fuzz")]
+#[doc(hidden)]
+pub use event_parser::EventParser;
 pub use event_parser::SSE;
+pub use event_parser::{encode_event, encode_events, Event};
+#[cfg(feature = "http3")]
+pub use http3::Http3Service;
+#[cfg(feature = "interop")]
+pub use interop::{interop_stream, InteropEvent};
+#[cfg(feature = "json")]
+pub use json::JsonErrorPolicy;
+pub use merge::merge;
+pub use observer::StreamObserver;
+pub use take_events::take_events;
+#[cfg(feature = "test-util")]
+pub use testing::ReconnectFixture;
+pub use timer::{SleepFuture, Timer, TimerClock, TokioTimer, WallClockTimer};
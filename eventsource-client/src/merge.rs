@@ -0,0 +1,101 @@
+use futures::{stream, Stream};
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::client::BoxStream;
+use crate::error::{Error, Result};
+use crate::event_parser::SSE;
+
+/// Merge several event streams into one, tagging every item with the label of the stream it
+/// came from. Items are yielded from whichever stream is ready first.
+///
+/// Errors are passed through with their label, since most stream errors are followed by a
+/// reconnect. A stream that reports [`Error::StreamClosed`] has given up for good, so that error
+/// is yielded once and the stream is then dropped from the merge. The merged stream ends once all
+/// of its streams have ended.
+///
+/// ```
+/// # use eventsource_client as es;
+/// # use es::Client;
+/// # fn main() -> Result<(), es::Error> {
+/// let flags = es::ClientBuilder::for_url("https://example.com/flags")?.build();
+/// let events = es::ClientBuilder::for_url("https://example.com/events")?.build();
+///
+/// let merged = es::merge(vec![("flags", flags.stream()), ("events", events.stream())]);
+/// # Ok(())
+/// # }
+/// ```
+pub fn merge<L, I>(streams: I) -> BoxStream<(L, Result<SSE>)>
+where
+    I: IntoIterator<Item = (L, BoxStream<Result<SSE>>)>,
+    L: Clone + Send + Sync + 'static,
+{
+    Box::pin(stream::select_all(streams.into_iter().map(
+        |(label, stream)| Labeled {
+            label,
+            stream: Some(stream),
+        },
+    )))
+}
+
+/// Tags each item of `stream` with `label`, ending after the stream reports it has closed.
+struct Labeled<L> {
+    label: L,
+    stream: Option<BoxStream<Result<SSE>>>,
+}
+
+// The label is never pinned, and the inner stream is already boxed.
+impl<L> Unpin for Labeled<L> {}
+
+impl<L: Clone> Stream for Labeled<L> {
+    type Item = (L, Result<SSE>);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let item = match self.stream.as_mut() {
+            Some(stream) => futures::ready!(stream.as_mut().poll_next(cx)),
+            None => return Poll::Ready(None),
+        };
+
+        if matches!(item, None | Some(Err(Error::StreamClosed))) {
+            self.stream = None;
+        }
+
+        Poll::Ready(item.map(|item| (self.label.clone(), item)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{executor::block_on, StreamExt};
+
+    fn comment(text: &str) -> Result<SSE> {
+        Ok(SSE::Comment(text.to_string()))
+    }
+
+    #[test]
+    fn test_merge_labels_items_and_ends_closed_streams() {
+        // A closed stream keeps reporting that it is closed.
+        let first: BoxStream<Result<SSE>> = Box::pin(stream::iter(0..).map(|i| match i {
+            0 => comment("one"),
+            _ => Err(Error::StreamClosed),
+        }));
+        let second: BoxStream<Result<SSE>> = Box::pin(stream::iter(0..2).map(|i| match i {
+            0 => comment("two"),
+            _ => Err(Error::UnexpectedEof),
+        }));
+
+        let mut items: Vec<_> =
+            block_on(merge(vec![("first", first), ("second", second)]).collect());
+        items.sort_by_key(|(label, _)| *label);
+
+        let labels: Vec<_> = items.iter().map(|(label, _)| *label).collect();
+        assert_eq!(labels, vec!["first", "first", "second", "second"]);
+        assert_eq!(items[0].1, comment("one"));
+        assert!(matches!(items[1].1, Err(Error::StreamClosed)));
+        assert_eq!(items[2].1, comment("two"));
+        assert!(matches!(items[3].1, Err(Error::UnexpectedEof)));
+    }
+}
@@ -0,0 +1,84 @@
+use hyper::{HeaderMap, StatusCode};
+use std::{sync::Arc, time::Duration};
+
+use crate::client::ReconnectReason;
+use crate::error::Error;
+use crate::event_parser::Event;
+
+/// Receives notice of each step in a stream's life: connecting, connecting successfully,
+/// receiving events, losing the connection and scheduling the next attempt.
+///
+/// Register an observer with [`ClientBuilder::observer`](crate::ClientBuilder::observer). Every
+/// method does nothing by default, so implement only the ones you need. The methods are called
+/// from inside the stream's `poll_next`, so they should return quickly. An `Arc` of an observer
+/// is an observer too, so you can keep a handle to one that records what it sees.
+///
+/// ```
+/// # use eventsource_client as es;
+/// # use std::sync::atomic::{AtomicU64, Ordering};
+/// #[derive(Default)]
+/// struct Counter {
+///     events: AtomicU64,
+/// }
+///
+/// impl es::StreamObserver for Counter {
+///     fn on_event(&self, _event: &es::Event) {
+///         self.events.fetch_add(1, Ordering::Relaxed);
+///     }
+/// }
+///
+/// # fn main() -> Result<(), es::Error> {
+/// let client = es::ClientBuilder::for_url("https://example.com/stream")?
+///     .observer(Counter::default())
+///     .build_http();
+/// # Ok(())
+/// # }
+/// ```
+pub trait StreamObserver: Send + Sync {
+    /// A connection attempt is starting, including the stream's first and each reconnect, but
+    /// not a redirect followed as part of an attempt.
+    fn on_connecting(&self) {}
+
+    /// A response with `status` and `headers` was accepted, and the stream will now read its
+    /// body.
+    fn on_connected(&self, _status: StatusCode, _headers: &HeaderMap) {}
+
+    /// The stream yielded `event`.
+    fn on_event(&self, _event: &Event) {}
+
+    /// A connection that had been established was lost or dropped for `reason`, and the stream
+    /// is going to reconnect.
+    fn on_disconnected(&self, _reason: ReconnectReason) {}
+
+    /// The stream will wait `delay` before its next connection attempt.
+    fn on_reconnect_scheduled(&self, _delay: Duration) {}
+
+    /// The stream yielded `error`.
+    fn on_error(&self, _error: &Error) {}
+}
+
+impl<O: StreamObserver + ?Sized> StreamObserver for Arc<O> {
+    fn on_connecting(&self) {
+        (**self).on_connecting()
+    }
+
+    fn on_connected(&self, status: StatusCode, headers: &HeaderMap) {
+        (**self).on_connected(status, headers)
+    }
+
+    fn on_event(&self, event: &Event) {
+        (**self).on_event(event)
+    }
+
+    fn on_disconnected(&self, reason: ReconnectReason) {
+        (**self).on_disconnected(reason)
+    }
+
+    fn on_reconnect_scheduled(&self, delay: Duration) {
+        (**self).on_reconnect_scheduled(delay)
+    }
+
+    fn on_error(&self, error: &Error) {
+        (**self).on_error(error)
+    }
+}
@@ -0,0 +1,146 @@
+use std::time::{Duration, Instant};
+
+/// An exponentially weighted moving average of how many events arrive per second.
+///
+/// Each event adds `1 / window` to the rate, which otherwise decays by a factor of `e` every
+/// `window`. Events arriving steadily at `r` per second therefore hold the rate at `r`, and
+/// the rate falls towards zero once they stop.
+pub(crate) struct EventRate {
+    window: Duration,
+    rate: f64,
+    last_event: Option<Instant>,
+}
+
+impl EventRate {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            // A zero window would divide by zero.
+            window: window.max(Duration::from_millis(1)),
+            rate: 0.0,
+            last_event: None,
+        }
+    }
+
+    /// Record an event received at `now`.
+    pub fn record(&mut self, now: Instant) {
+        self.rate = self.rate_at(now) + 1.0 / self.window.as_secs_f64();
+        self.last_event = Some(now);
+    }
+
+    /// The smoothed rate at `now`, in events per second.
+    pub fn rate_at(&self, now: Instant) -> f64 {
+        match self.last_event {
+            Some(last_event) => {
+                let elapsed = now.saturating_duration_since(last_event).as_secs_f64();
+                self.rate * (-elapsed / self.window.as_secs_f64()).exp()
+            }
+            None => 0.0,
+        }
+    }
+}
+
+/// A token bucket allowing up to `rate` events per second, in bursts of up to `rate` events.
+pub(crate) struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    last_refill: Option<Instant>,
+}
+
+impl TokenBucket {
+    pub fn new(events_per_second: u32) -> Self {
+        let rate = f64::from(events_per_second.max(1));
+        Self {
+            rate,
+            tokens: rate,
+            last_refill: None,
+        }
+    }
+
+    /// Take a token for an event at `now`. If the bucket is empty the token is borrowed from the
+    /// future, and the time until it would have been available is returned: the event should
+    /// wait that long before being delivered.
+    pub fn acquire(&mut self, now: Instant) -> Option<Duration> {
+        if let Some(last_refill) = self.last_refill {
+            let elapsed = now.saturating_duration_since(last_refill).as_secs_f64();
+            self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        }
+        self.last_refill = Some(now);
+
+        self.tokens -= 1.0;
+        if self.tokens >= 0.0 {
+            None
+        } else {
+            Some(Duration::from_secs_f64(-self.tokens / self.rate))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::rate::{EventRate, TokenBucket};
+
+    #[test]
+    fn test_rate_starts_at_zero() {
+        let rate = EventRate::new(Duration::from_secs(10));
+        assert_eq!(rate.rate_at(Instant::now()), 0.0);
+    }
+
+    #[test]
+    fn test_rate_converges_to_steady_rate() {
+        let mut rate = EventRate::new(Duration::from_secs(10));
+        let start = Instant::now();
+
+        // Four events a second, for a minute.
+        for i in 0..240 {
+            rate.record(start + Duration::from_millis(250) * i);
+        }
+
+        let now = start + Duration::from_secs(60);
+        let measured = rate.rate_at(now);
+        assert!((measured - 4.0).abs() < 0.1, "{}", measured);
+    }
+
+    #[test]
+    fn test_rate_decays_without_events() {
+        let mut rate = EventRate::new(Duration::from_secs(1));
+        let start = Instant::now();
+        for i in 0..100 {
+            rate.record(start + Duration::from_millis(100) * i);
+        }
+
+        let recent = rate.rate_at(start + Duration::from_secs(10));
+        let later = rate.rate_at(start + Duration::from_secs(20));
+        assert!(later < recent / 1000.0, "{} then {}", recent, later);
+    }
+
+    #[test]
+    fn test_bucket_allows_a_burst_then_paces() {
+        let mut bucket = TokenBucket::new(4);
+        let start = Instant::now();
+
+        for _ in 0..4 {
+            assert_eq!(bucket.acquire(start), None);
+        }
+        assert_eq!(bucket.acquire(start), Some(Duration::from_millis(250)));
+        assert_eq!(bucket.acquire(start), Some(Duration::from_millis(500)));
+
+        // The borrowed tokens are paid back before any more are available.
+        let later = start + Duration::from_millis(500);
+        assert_eq!(bucket.acquire(later), Some(Duration::from_millis(250)));
+    }
+
+    #[test]
+    fn test_bucket_refills_up_to_its_capacity() {
+        let mut bucket = TokenBucket::new(2);
+        let start = Instant::now();
+        assert_eq!(bucket.acquire(start), None);
+        assert_eq!(bucket.acquire(start), None);
+
+        let later = start + Duration::from_secs(60);
+        assert_eq!(bucket.acquire(later), None);
+        assert_eq!(bucket.acquire(later), None);
+        assert!(bucket.acquire(later).is_some());
+    }
+}
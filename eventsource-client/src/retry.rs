@@ -9,11 +9,31 @@ pub(crate) trait RetryStrategy {
     /// Modify the strategy's default base delay.
     fn change_base_delay(&mut self, base_delay: Duration);
 
+    /// Replace the strategy's base delay, maximum delay and backoff factor. Progress through the
+    /// backoff is kept unless the base delay changes, but never exceeds the new maximum.
+    fn change_limits(&mut self, base_delay: Duration, max_delay: Duration, backoff_factor: u32);
+
     /// Used to indicate to the strategy that it can reset as a successful connection has been made.
     fn reset(&mut self, current_time: Instant);
+
+    /// Whether the most recent delay returned by [`RetryStrategy::next_delay`] (before jitter)
+    /// was capped at the strategy's maximum.
+    fn at_max_delay(&self) -> bool;
+
+    /// The smoothed time that connections have stayed up, if the strategy learns from it and
+    /// at least one connection has ended.
+    fn average_uptime(&self) -> Option<Duration> {
+        None
+    }
 }
 
-const DEFAULT_RESET_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+/// How long a connection must stay up for the backoff to start again from its base delay.
+pub(crate) const DEFAULT_RESET_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The weight of the latest connection in [`AdaptiveRetry`]'s average uptime.
+const UPTIME_SMOOTHING: f64 = 0.3;
+/// The average uptime at which [`AdaptiveRetry`] treats connections as fully stable.
+const STABLE_UPTIME: Duration = DEFAULT_RESET_RETRY_INTERVAL;
 
 pub(crate) struct BackoffRetry {
     base_delay: Duration,
@@ -24,6 +44,7 @@ pub(crate) struct BackoffRetry {
     reset_interval: Duration,
     next_delay: Duration,
     good_since: Option<Instant>,
+    at_max_delay: bool,
 }
 
 impl BackoffRetry {
@@ -41,6 +62,7 @@ impl BackoffRetry {
             reset_interval: DEFAULT_RESET_RETRY_INTERVAL,
             next_delay: base_delay,
             good_since: None,
+            at_max_delay: false,
         }
     }
 }
@@ -56,6 +78,7 @@ impl RetryStrategy for BackoffRetry {
         }
 
         self.good_since = None;
+        self.at_max_delay = current_delay >= self.max_delay;
         self.next_delay = std::cmp::min(self.max_delay, current_delay * self.backoff_factor);
 
         if self.include_jitter {
@@ -70,6 +93,15 @@ impl RetryStrategy for BackoffRetry {
         self.next_delay = self.base_delay;
     }
 
+    fn change_limits(&mut self, base_delay: Duration, max_delay: Duration, backoff_factor: u32) {
+        if base_delay != self.base_delay {
+            self.change_base_delay(base_delay);
+        }
+        self.max_delay = max_delay;
+        self.backoff_factor = backoff_factor;
+        self.next_delay = std::cmp::min(self.next_delay, self.max_delay);
+    }
+
     fn reset(&mut self, current_time: Instant) {
         // While the external application has indicated success, we don't actually want to reset the
         // retry policy just yet. Instead, we want to record the time it was successful. Then when
@@ -77,6 +109,104 @@ impl RetryStrategy for BackoffRetry {
         // DEFAULT_RESET_RETRY_INTERVAL seconds.
         self.good_since = Some(current_time);
     }
+
+    fn at_max_delay(&self) -> bool {
+        self.at_max_delay
+    }
+}
+
+/// An exponential backoff scaled by how stable connections have been.
+///
+/// The strategy keeps a moving average of how long connections stay up, counting a failed
+/// attempt as zero. Its stability is that average as a fraction of [`STABLE_UPTIME`], at most 1.
+/// Each delay is the exponential one scaled by between 1.5 (for connections that always fail
+/// fast) and 0.5 (for stable ones), and capped at the maximum. Once connections are at least
+/// half stable, a dropped connection also starts the backoff again from its base delay, however
+/// briefly it was up.
+pub(crate) struct AdaptiveRetry {
+    backoff: BackoffRetry,
+    max_delay: Duration,
+    include_jitter: bool,
+    connected_since: Option<Instant>,
+    average_uptime: Option<Duration>,
+}
+
+impl AdaptiveRetry {
+    pub fn new(
+        base_delay: Duration,
+        max_delay: Duration,
+        backoff_factor: u32,
+        include_jitter: bool,
+    ) -> Self {
+        Self {
+            backoff: BackoffRetry::new(base_delay, max_delay, backoff_factor, false),
+            max_delay,
+            include_jitter,
+            connected_since: None,
+            average_uptime: None,
+        }
+    }
+
+    fn stability(&self) -> f64 {
+        let average = self.average_uptime.unwrap_or_default();
+        (average.as_secs_f64() / STABLE_UPTIME.as_secs_f64()).min(1.0)
+    }
+}
+
+impl RetryStrategy for AdaptiveRetry {
+    fn next_delay(&mut self, current_time: Instant) -> Duration {
+        let uptime = self
+            .connected_since
+            .take()
+            .map(|since| current_time.saturating_duration_since(since));
+        let latest = uptime.unwrap_or_default();
+        self.average_uptime = Some(match self.average_uptime {
+            Some(average) => {
+                average.mul_f64(1.0 - UPTIME_SMOOTHING) + latest.mul_f64(UPTIME_SMOOTHING)
+            }
+            None => latest,
+        });
+
+        let stability = self.stability();
+        if uptime.is_some() && stability >= 0.5 {
+            let base_delay = self.backoff.base_delay;
+            self.backoff.change_base_delay(base_delay);
+        }
+        let delay = self
+            .backoff
+            .next_delay(current_time)
+            .mul_f64(1.5 - stability)
+            .min(self.max_delay);
+
+        if self.include_jitter {
+            thread_rng().gen_range(delay / 2..=delay)
+        } else {
+            delay
+        }
+    }
+
+    fn change_base_delay(&mut self, base_delay: Duration) {
+        self.backoff.change_base_delay(base_delay);
+    }
+
+    fn change_limits(&mut self, base_delay: Duration, max_delay: Duration, backoff_factor: u32) {
+        self.backoff
+            .change_limits(base_delay, max_delay, backoff_factor);
+        self.max_delay = max_delay;
+    }
+
+    fn reset(&mut self, current_time: Instant) {
+        self.connected_since = Some(current_time);
+        self.backoff.reset(current_time);
+    }
+
+    fn at_max_delay(&self) -> bool {
+        self.backoff.at_max_delay()
+    }
+
+    fn average_uptime(&self) -> Option<Duration> {
+        self.average_uptime
+    }
 }
 
 #[cfg(test)]
@@ -84,7 +214,7 @@ mod tests {
     use std::ops::Add;
     use std::time::{Duration, Instant};
 
-    use crate::retry::{BackoffRetry, RetryStrategy};
+    use crate::retry::{AdaptiveRetry, BackoffRetry, RetryStrategy, DEFAULT_RESET_RETRY_INTERVAL};
 
     #[test]
     fn test_fixed_retry() {
@@ -130,6 +260,24 @@ mod tests {
         assert_eq!(retry.next_delay(start.add(Duration::from_secs(3))), max);
     }
 
+    #[test]
+    fn test_change_limits_keeps_progress() {
+        let base = Duration::from_secs(10);
+        let mut retry = BackoffRetry::new(base, Duration::from_secs(60), 2, false);
+        let start = Instant::now();
+
+        assert_eq!(retry.next_delay(start), base);
+        assert_eq!(retry.next_delay(start), base * 2);
+
+        retry.change_limits(base, Duration::from_secs(30), 3);
+        assert_eq!(retry.next_delay(start), Duration::from_secs(30));
+        assert!(retry.at_max_delay());
+
+        retry.change_limits(Duration::from_secs(1), Duration::from_secs(30), 3);
+        assert_eq!(retry.next_delay(start), Duration::from_secs(1));
+        assert_eq!(retry.next_delay(start), Duration::from_secs(3));
+    }
+
     #[test]
     fn test_with_jitter() {
         let base = Duration::from_secs(10);
@@ -163,6 +311,27 @@ mod tests {
         assert_eq!(delay, max);
     }
 
+    #[test]
+    fn test_at_max_delay() {
+        let base = Duration::from_secs(10);
+        let max = Duration::from_secs(30);
+        let mut retry = BackoffRetry::new(base, max, 2, false);
+        let start = Instant::now();
+        assert!(!retry.at_max_delay());
+
+        assert_eq!(retry.next_delay(start), base);
+        assert!(!retry.at_max_delay());
+        assert_eq!(retry.next_delay(start), base * 2);
+        assert!(!retry.at_max_delay());
+        assert_eq!(retry.next_delay(start), max);
+        assert!(retry.at_max_delay());
+
+        retry.reset(start);
+        let delay = retry.next_delay(start.add(DEFAULT_RESET_RETRY_INTERVAL));
+        assert_eq!(delay, base);
+        assert!(!retry.at_max_delay());
+    }
+
     #[test]
     fn test_reset_interval() {
         let base = Duration::from_secs(10);
@@ -198,4 +367,42 @@ mod tests {
         let delay = retry.next_delay(time);
         assert_eq!(delay, base);
     }
+
+    #[test]
+    fn test_adaptive_retry_is_cautious_when_connections_fail_fast() {
+        let base = Duration::from_secs(10);
+        let mut retry = AdaptiveRetry::new(base, Duration::from_secs(60), 2, false);
+        let start = Instant::now();
+
+        assert_eq!(retry.average_uptime(), None);
+        assert_eq!(retry.next_delay(start), base.mul_f64(1.5));
+        assert_eq!(retry.next_delay(start), base.mul_f64(3.0));
+        // Capped at the maximum.
+        assert_eq!(retry.next_delay(start), Duration::from_secs(60));
+        assert_eq!(retry.average_uptime(), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_adaptive_retry_recovers_quickly_when_connections_are_stable() {
+        let base = Duration::from_secs(10);
+        let mut retry = AdaptiveRetry::new(base, Duration::from_secs(60), 2, false);
+        let mut time = Instant::now();
+
+        // A long-lived connection ends.
+        retry.reset(time);
+        time += Duration::from_secs(600);
+        assert_eq!(retry.average_uptime(), None);
+        assert_eq!(retry.next_delay(time), base.mul_f64(0.5));
+        assert_eq!(retry.average_uptime(), Some(Duration::from_secs(600)));
+
+        // A failed attempt backs off, but less than usual.
+        let delay = retry.next_delay(time);
+        assert!(delay < base * 2, "{:?}", delay);
+
+        // A brief blip still starts the backoff again, as connections are generally stable.
+        retry.reset(time);
+        time += Duration::from_secs(1);
+        let delay = retry.next_delay(time);
+        assert!(delay < base, "{:?}", delay);
+    }
 }
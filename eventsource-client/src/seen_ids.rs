@@ -0,0 +1,80 @@
+use std::collections::{HashSet, VecDeque};
+
+/// The most recent distinct event IDs received, up to a fixed capacity. Once it is full, each
+/// new ID evicts the oldest one.
+pub(crate) struct SeenIds {
+    capacity: usize,
+    order: VecDeque<String>,
+    ids: HashSet<String>,
+    evictions: u64,
+}
+
+impl SeenIds {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            order: VecDeque::new(),
+            ids: HashSet::new(),
+            evictions: 0,
+        }
+    }
+
+    /// Remember `id`, returning `false` if it was already remembered. Seeing an ID again doesn't
+    /// make it any newer, so IDs are evicted in the order they were first seen.
+    pub fn insert(&mut self, id: &str) -> bool {
+        if self.ids.contains(id) {
+            return false;
+        }
+        if self.capacity == 0 {
+            return true;
+        }
+        if self.order.len() == self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.ids.remove(&oldest);
+                self.evictions += 1;
+            }
+        }
+        self.order.push_back(id.to_string());
+        self.ids.insert(id.to_string());
+        true
+    }
+
+    /// How many IDs are remembered.
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    /// How many IDs have been forgotten to make room for newer ones.
+    pub fn evictions(&self) -> u64 {
+        self.evictions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SeenIds;
+
+    #[test]
+    fn test_oldest_id_is_evicted() {
+        let mut seen = SeenIds::new(2);
+        assert!(seen.insert("1"));
+        assert!(seen.insert("2"));
+        assert!(!seen.insert("1"));
+        assert_eq!((seen.len(), seen.evictions()), (2, 0));
+
+        // "1" was seen first, so it goes even though it was seen again more recently.
+        assert!(seen.insert("3"));
+        assert_eq!((seen.len(), seen.evictions()), (2, 1));
+        assert!(!seen.insert("2"));
+        assert!(seen.insert("1"));
+        assert_eq!((seen.len(), seen.evictions()), (2, 2));
+    }
+
+    #[test]
+    fn test_zero_capacity_remembers_nothing() {
+        let mut seen = SeenIds::new(0);
+        assert!(seen.insert("1"));
+        assert!(seen.insert("1"));
+        assert_eq!((seen.len(), seen.evictions()), (0, 0));
+    }
+}
@@ -0,0 +1,96 @@
+use futures::Stream;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::client::BoxStream;
+use crate::error::Result;
+use crate::event_parser::SSE;
+
+/// Limit an event stream to its first `n` events.
+///
+/// Only [`SSE::Event`]s count towards the limit; comments and errors (such as those reported
+/// when the client reconnects) are passed through without being counted. Once the `n`th event
+/// has been yielded, the underlying stream is dropped, closing its connection, and the returned
+/// stream ends.
+pub fn take_events(stream: BoxStream<Result<SSE>>, n: usize) -> BoxStream<Result<SSE>> {
+    Box::pin(TakeEvents {
+        stream: if n == 0 { None } else { Some(stream) },
+        remaining: n,
+    })
+}
+
+struct TakeEvents {
+    stream: Option<BoxStream<Result<SSE>>>,
+    remaining: usize,
+}
+
+impl Stream for TakeEvents {
+    type Item = Result<SSE>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let item = match self.stream.as_mut() {
+            Some(stream) => futures::ready!(stream.as_mut().poll_next(cx)),
+            None => return Poll::Ready(None),
+        };
+
+        match item {
+            Some(Ok(SSE::Event(_))) => {
+                self.remaining -= 1;
+                if self.remaining == 0 {
+                    self.stream = None;
+                }
+            }
+            None => self.stream = None,
+            _ => (),
+        }
+
+        Poll::Ready(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Error, Event};
+    use futures::{executor::block_on, stream, StreamExt};
+
+    fn event(data: &str) -> Result<SSE> {
+        Ok(SSE::Event(Event {
+            event_type: "message".to_string(),
+            data: data.to_string(),
+            id: None,
+            retry: None,
+        }))
+    }
+
+    #[test]
+    fn test_take_events_counts_only_events_across_reconnects() {
+        let source: BoxStream<Result<SSE>> = Box::pin(stream::iter(0..).map(|i| match i {
+            0 => event("one"),
+            1 => Ok(SSE::Comment("ping".to_string())),
+            2 => Err(Error::Eof),
+            3 => event("two"),
+            _ => event("more"),
+        }));
+
+        let items: Vec<_> = block_on(take_events(source, 2).collect());
+
+        let data: Vec<_> = items
+            .iter()
+            .filter_map(|item| match item {
+                Ok(SSE::Event(event)) => Some(event.data.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(data, vec!["one", "two"]);
+        assert_eq!(items.len(), 4);
+    }
+
+    #[test]
+    fn test_take_zero_events_ends_immediately() {
+        let source: BoxStream<Result<SSE>> = Box::pin(stream::iter(0..).map(|_| event("x")));
+        assert!(block_on(take_events(source, 0).next()).is_none());
+    }
+}
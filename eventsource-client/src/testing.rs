@@ -0,0 +1,251 @@
+use futures::StreamExt;
+use std::{
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::client::Client;
+use crate::event_parser::{Event, SSE};
+
+/// A local server for checking that a client resumes a stream after the server disconnects.
+///
+/// The server sends its first batch of events on the first connection and then closes it. On
+/// the next connection it sends the second batch and keeps the connection open. Any later
+/// connections are accepted and closed straight away. The `Last-Event-ID` header of every
+/// request is recorded.
+///
+/// ```
+/// # use eventsource_client as es;
+/// # use std::time::Duration;
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), es::Error> {
+/// let event = |id: &str| es::Event {
+///     event_type: "message".to_string(),
+///     data: format!("event {}", id),
+///     id: Some(id.to_string()),
+///     retry: None,
+/// };
+/// let fixture = es::ReconnectFixture::start(vec![event("1"), event("2")], vec![event("3")])?;
+///
+/// let client = es::ClientBuilder::for_url(&fixture.url())?
+///     .reconnect(es::ReconnectOptions::reconnect(true).delay(Duration::from_millis(10)).build())
+///     .build_http();
+/// fixture.assert_reconnects(&client).await;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ReconnectFixture {
+    addr: SocketAddr,
+    first: Vec<Event>,
+    second: Vec<Event>,
+    last_event_ids: Arc<Mutex<Vec<Option<String>>>>,
+    shutdown: Arc<AtomicBool>,
+}
+
+impl ReconnectFixture {
+    /// How long [`ReconnectFixture::assert_reconnects`] waits for all events to arrive.
+    pub const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Start the server on a free local port, serving `first` and then `second`. The server
+    /// runs on its own thread until the fixture is dropped.
+    pub fn start(first: Vec<Event>, second: Vec<Event>) -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let fixture = ReconnectFixture {
+            addr: listener.local_addr()?,
+            first,
+            second,
+            last_event_ids: Arc::new(Mutex::new(Vec::new())),
+            shutdown: Arc::new(AtomicBool::new(false)),
+        };
+
+        let bodies = [&fixture.first, &fixture.second].map(|events| {
+            events
+                .iter()
+                .map(Event::to_sse)
+                .collect::<Vec<_>>()
+                .concat()
+        });
+        let last_event_ids = fixture.last_event_ids.clone();
+        let shutdown = fixture.shutdown.clone();
+        thread::spawn(move || {
+            // The second connection is held open until the server shuts down.
+            let mut open = None;
+            let mut requests = 0;
+            for stream in listener.incoming() {
+                if shutdown.load(Ordering::SeqCst) {
+                    break;
+                }
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                let last_event_id = match read_last_event_id(&mut stream) {
+                    Ok(last_event_id) => last_event_id,
+                    Err(_) => continue,
+                };
+                last_event_ids.lock().unwrap().push(last_event_id);
+                requests += 1;
+
+                if let Some(body) = bodies.get(requests - 1) {
+                    let _ = write!(
+                        stream,
+                        "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\n\
+                         connection: close\r\n\r\n{}",
+                        body
+                    );
+                    if requests == 2 {
+                        open = Some(stream);
+                    }
+                }
+            }
+            drop(open);
+        });
+
+        Ok(fixture)
+    }
+
+    /// The URL of the stream served by the fixture.
+    pub fn url(&self) -> String {
+        format!("http://{}/stream", self.addr)
+    }
+
+    /// The `Last-Event-ID` header sent with each request received so far, in order.
+    pub fn last_event_ids(&self) -> Vec<Option<String>> {
+        self.last_event_ids.lock().unwrap().clone()
+    }
+
+    /// Stream events from `client`, which must be connected to [`ReconnectFixture::url`],
+    /// panicking unless both batches of events arrive in order within
+    /// [`ReconnectFixture::TIMEOUT`], and the second request carries the ID of the last event
+    /// in the first batch that had one.
+    pub async fn assert_reconnects(&self, client: &dyn Client) {
+        let expected: Vec<_> = self.first.iter().chain(&self.second).cloned().collect();
+        let mut stream = client.stream();
+        let mut received = Vec::new();
+
+        let collected = tokio::time::timeout(Self::TIMEOUT, async {
+            while received.len() < expected.len() {
+                match stream.next().await {
+                    Some(Ok(SSE::Event(event))) => received.push(event),
+                    Some(_) => continue,
+                    None => break,
+                }
+            }
+        })
+        .await;
+        assert!(
+            collected.is_ok(),
+            "timed out with {} of {} events received",
+            received.len(),
+            expected.len()
+        );
+        // An event without an ID is received with the last ID seen, so IDs aren't compared.
+        let summary = |events: &[Event]| -> Vec<(String, String)> {
+            events
+                .iter()
+                .map(|event| (event.event_type.clone(), event.data.clone()))
+                .collect()
+        };
+        assert_eq!(
+            summary(&received),
+            summary(&expected),
+            "events were not received in order"
+        );
+
+        let resumed_from = self.first.iter().rev().find_map(|event| event.id.clone());
+        let last_event_ids = self.last_event_ids();
+        assert!(
+            last_event_ids.len() >= 2,
+            "the client didn't reconnect: {} request(s) received",
+            last_event_ids.len()
+        );
+        assert_eq!(
+            last_event_ids[1], resumed_from,
+            "the second request had the wrong Last-Event-ID"
+        );
+    }
+}
+
+impl Drop for ReconnectFixture {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        // Wake the server up from waiting for a connection, so that it sees the flag.
+        let _ = TcpStream::connect(self.addr);
+    }
+}
+
+/// Read a request's headers from `stream`, returning its `Last-Event-ID` header if any.
+fn read_last_event_id(stream: &mut TcpStream) -> io::Result<Option<String>> {
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = stream.read(&mut buf)?;
+        if read == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        request.extend_from_slice(&buf[..read]);
+    }
+
+    Ok(String::from_utf8_lossy(&request)
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("last-event-id"))
+        .map(|(_, value)| value.trim().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ClientBuilder, ReconnectOptions};
+
+    fn event(id: Option<&str>, data: &str) -> Event {
+        Event {
+            event_type: "message".to_string(),
+            data: data.to_string(),
+            id: id.map(str::to_string),
+            retry: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fixture_checks_resumption() {
+        let fixture = ReconnectFixture::start(
+            vec![
+                event(Some("1"), "a"),
+                event(Some("2"), "b"),
+                event(None, "c"),
+            ],
+            vec![event(Some("3"), "d")],
+        )
+        .unwrap();
+        let client = ClientBuilder::for_url(&fixture.url())
+            .unwrap()
+            .reconnect(
+                ReconnectOptions::reconnect(true)
+                    .delay(Duration::from_millis(1))
+                    .build(),
+            )
+            .build_http();
+
+        fixture.assert_reconnects(&client).await;
+        assert_eq!(fixture.last_event_ids()[0], None);
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "the client didn't reconnect")]
+    async fn test_fixture_fails_without_reconnect() {
+        let fixture = ReconnectFixture::start(vec![event(Some("1"), "a")], vec![]).unwrap();
+        let client = ClientBuilder::for_url(&fixture.url())
+            .unwrap()
+            .reconnect(ReconnectOptions::reconnect(false).build())
+            .build_http();
+
+        fixture.assert_reconnects(&client).await;
+    }
+}
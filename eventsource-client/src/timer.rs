@@ -0,0 +1,115 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    time::{Duration, SystemTime},
+};
+
+/// A future that completes once a [`Timer`]'s delay has elapsed.
+pub type SleepFuture = Pin<Box<dyn Future<Output = ()> + Send + Sync>>;
+
+/// Source of the delays the client waits out between reconnection attempts.
+///
+/// The client uses [`TokioTimer`] by default. Provide another implementation with
+/// [`ClientBuilder::timer`](crate::ClientBuilder::timer) to drive reconnection from a different
+/// runtime's timer, or use [`WallClockTimer`] to keep delays on schedule across system suspend.
+pub trait Timer: Send + Sync {
+    /// Return a future that completes after `duration`.
+    fn sleep(&self, duration: Duration) -> SleepFuture;
+
+    /// The clock that the timer's delays are measured against. Monotonic by default.
+    fn clock(&self) -> TimerClock {
+        TimerClock::Monotonic
+    }
+}
+
+/// The clock a [`Timer`] measures its delays against, as reported by [`Timer::clock`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimerClock {
+    /// A monotonic clock, which can't be changed but, on most platforms (including Linux and
+    /// macOS), stops while the system is suspended. A delay that spans a suspend is extended by
+    /// however long the system was asleep.
+    Monotonic,
+    /// The system's wall clock. A delay that spans a suspend ends on schedule, or as soon as the
+    /// system resumes if it was due during the suspend, but setting the clock also moves it.
+    WallClock,
+}
+
+/// A [`Timer`] backed by [`tokio::time::sleep`]. Requires a tokio runtime with the time driver
+/// enabled.
+///
+/// Tokio measures time with a monotonic clock, so delays don't count time spent suspended.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TokioTimer;
+
+impl Timer for TokioTimer {
+    fn sleep(&self, duration: Duration) -> SleepFuture {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// A [`Timer`] whose delays end at a wall-clock deadline, so that a long reconnect delay isn't
+/// stretched by a system suspend, for example on a laptop that sleeps. Requires a tokio runtime
+/// with the time driver enabled.
+///
+/// Tokio can only sleep against its monotonic clock, so the deadline is checked at least once
+/// per check interval; a delay may therefore end up to one interval late after a resume.
+#[derive(Clone, Copy, Debug)]
+pub struct WallClockTimer {
+    check_interval: Duration,
+}
+
+impl WallClockTimer {
+    /// Create a timer that checks its deadline at least once per `check_interval`.
+    pub fn new(check_interval: Duration) -> Self {
+        Self { check_interval }
+    }
+}
+
+impl Default for WallClockTimer {
+    /// Checks the deadline once a second.
+    fn default() -> Self {
+        Self::new(Duration::from_secs(1))
+    }
+}
+
+impl Timer for WallClockTimer {
+    fn sleep(&self, duration: Duration) -> SleepFuture {
+        let deadline = SystemTime::now() + duration;
+        let check_interval = self.check_interval;
+        Box::pin(async move {
+            // A clock set backwards makes the deadline further away, which is accepted; a clock
+            // set forwards ends the delay early.
+            while let Ok(remaining) = deadline.duration_since(SystemTime::now()) {
+                if remaining.is_zero() {
+                    break;
+                }
+                tokio::time::sleep(remaining.min(check_interval)).await;
+            }
+        })
+    }
+
+    fn clock(&self) -> TimerClock {
+        TimerClock::WallClock
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::timer::{Timer, TimerClock, TokioTimer, WallClockTimer};
+
+    #[test]
+    fn test_timer_clocks() {
+        assert_eq!(TokioTimer.clock(), TimerClock::Monotonic);
+        assert_eq!(WallClockTimer::default().clock(), TimerClock::WallClock);
+    }
+
+    #[tokio::test]
+    async fn test_wall_clock_timer_waits_for_deadline() {
+        let timer = WallClockTimer::new(Duration::from_millis(5));
+        let start = Instant::now();
+        timer.sleep(Duration::from_millis(30)).await;
+        assert!(start.elapsed() >= Duration::from_millis(30));
+    }
+}